@@ -0,0 +1,341 @@
+// Maps the raw controller slots of a frame to what the game actually cares about.
+// The game asks "did the player jump", the bindings decide which button that was.
+
+use crate::types::{ButtonId, GameControllerInput, GameInput, StickAxis, KEYBOARD_SLOT};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    MoveX,
+    MoveY,
+    Jump,
+    Pause,
+}
+
+impl Action {
+    pub const COUNT: usize = 4;
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputSource {
+    Button(ButtonId),
+    /// Value in -1..=1, counts as down while outside of 0.
+    Axis(StickAxis),
+    /// Two buttons acting as an axis, e.g. A and D for horizontal movement.
+    ButtonAxis {
+        negative: ButtonId,
+        positive: ButtonId,
+    },
+}
+
+/// Which kind of device in a slot a binding applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Device {
+    Any,
+    Keyboard,
+    Gamepad,
+}
+
+impl Device {
+    fn matches(self, slot: usize) -> bool {
+        match self {
+            Device::Any => true,
+            Device::Keyboard => slot == KEYBOARD_SLOT,
+            Device::Gamepad => slot != KEYBOARD_SLOT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding {
+    pub action: Action,
+    pub source: InputSource,
+    pub device: Device,
+}
+
+//TODO(voided): Load these from a config instead.
+pub const DEFAULT_BINDINGS: &[Binding] = &[
+    Binding {
+        action: Action::MoveX,
+        source: InputSource::Axis(StickAxis::LeftX),
+        device: Device::Gamepad,
+    },
+    Binding {
+        action: Action::MoveX,
+        source: InputSource::ButtonAxis {
+            negative: ButtonId::MoveLeft,
+            positive: ButtonId::MoveRight,
+        },
+        device: Device::Keyboard,
+    },
+    Binding {
+        action: Action::MoveY,
+        source: InputSource::Axis(StickAxis::LeftY),
+        device: Device::Gamepad,
+    },
+    Binding {
+        action: Action::MoveY,
+        source: InputSource::ButtonAxis {
+            negative: ButtonId::MoveDown,
+            positive: ButtonId::MoveUp,
+        },
+        device: Device::Keyboard,
+    },
+    Binding {
+        action: Action::Jump,
+        source: InputSource::Button(ButtonId::ActionDown),
+        device: Device::Any,
+    },
+    Binding {
+        action: Action::Pause,
+        source: InputSource::Button(ButtonId::Start),
+        device: Device::Any,
+    },
+];
+
+/// Bit set of the [`GameInput`] slots a context listens to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControllerSlots(u8);
+
+impl ControllerSlots {
+    pub const NONE: ControllerSlots = ControllerSlots(0);
+    pub const ALL: ControllerSlots = ControllerSlots((1 << 5) - 1);
+
+    pub const fn single(slot: usize) -> Self {
+        ControllerSlots(1 << slot)
+    }
+
+    pub const fn with(self, slot: usize) -> Self {
+        ControllerSlots(self.0 | 1 << slot)
+    }
+
+    pub const fn contains(self, slot: usize) -> bool {
+        self.0 & (1 << slot) != 0
+    }
+}
+
+/// A set of bindings applied to some of the controller slots. One per player, or one over
+/// [`ControllerSlots::ALL`] for the merged "any device" view used by menus.
+#[derive(Debug, Clone, Copy)]
+pub struct ActionContext<'a> {
+    pub slots: ControllerSlots,
+    pub bindings: &'a [Binding],
+}
+
+impl<'a> ActionContext<'a> {
+    pub fn any_device(bindings: &'a [Binding]) -> Self {
+        Self {
+            slots: ControllerSlots::ALL,
+            bindings,
+        }
+    }
+
+    pub fn for_slot(slot: usize, bindings: &'a [Binding]) -> Self {
+        Self {
+            slots: ControllerSlots::single(slot),
+            bindings,
+        }
+    }
+
+    pub fn evaluate(&self, inputs: &GameInput) -> ActionFrame {
+        let mut frame = ActionFrame::default();
+
+        for (slot, controller) in inputs.controllers.iter().enumerate() {
+            if !self.slots.contains(slot) || !controller.is_connected {
+                continue;
+            }
+
+            for binding in self.bindings {
+                if binding.device.matches(slot) {
+                    frame.states[binding.action.index()].merge(binding.source, controller);
+                }
+            }
+        }
+
+        frame
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct ActionState {
+    pub is_down: bool,
+    pub press_count: u32,
+    pub release_count: u32,
+    /// Note(voided): Buttons report 0 or 1, axes -1..=1.
+    /// With several sources bound, the one with the largest magnitude wins.
+    pub value: f32,
+}
+
+impl ActionState {
+    fn merge(&mut self, source: InputSource, controller: &GameControllerInput) {
+        let value = match source {
+            InputSource::Button(id) => {
+                let button = controller.button(id);
+                self.press_count += button.press_count();
+                self.release_count += button.release_count();
+                if button.button_is_down {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            InputSource::Axis(axis) => controller.axis(axis),
+            InputSource::ButtonAxis { negative, positive } => {
+                let negative = controller.button(negative);
+                let positive = controller.button(positive);
+                self.press_count += negative.press_count() + positive.press_count();
+                self.release_count += negative.release_count() + positive.release_count();
+                positive.button_is_down as i32 as f32 - negative.button_is_down as i32 as f32
+            }
+        };
+
+        self.is_down |= value != 0.0;
+        if value.abs() > self.value.abs() {
+            self.value = value;
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ActionFrame {
+    states: [ActionState; Action::COUNT],
+}
+
+impl ActionFrame {
+    pub fn state(&self, action: Action) -> &ActionState {
+        &self.states[action.index()]
+    }
+
+    pub fn is_down(&self, action: Action) -> bool {
+        self.state(action).is_down
+    }
+
+    pub fn was_pressed(&self, action: Action) -> bool {
+        self.state(action).press_count > 0
+    }
+
+    pub fn was_released(&self, action: Action) -> bool {
+        self.state(action).release_count > 0
+    }
+
+    pub fn value(&self, action: Action) -> f32 {
+        self.state(action).value
+    }
+}
+
+#[cfg(test)]
+mod actions_tests {
+    use crate::actions::{
+        Action, ActionContext, Binding, ControllerSlots, Device, InputSource, DEFAULT_BINDINGS,
+    };
+    use crate::types::{GameInput, StickAxis};
+
+    fn connected_inputs() -> GameInput {
+        let mut inputs = GameInput::default();
+        for input in inputs.controllers.iter_mut() {
+            input.is_connected = true;
+        }
+        inputs
+    }
+
+    #[test]
+    fn nothing_pressed() {
+        let inputs = connected_inputs();
+        let frame = ActionContext::any_device(DEFAULT_BINDINGS).evaluate(&inputs);
+        assert!(!frame.is_down(Action::Jump));
+        assert_eq!(frame.value(Action::MoveX), 0.0);
+    }
+
+    #[test]
+    fn keyboard_button_axis() {
+        let mut inputs = connected_inputs();
+        inputs[0].move_left.button_is_down = true;
+        inputs[0].move_left.half_transitions = 1;
+
+        let frame = ActionContext::any_device(DEFAULT_BINDINGS).evaluate(&inputs);
+        assert_eq!(frame.value(Action::MoveX), -1.0);
+        assert!(frame.was_pressed(Action::MoveX));
+
+        inputs[0].move_right.button_is_down = true;
+        let frame = ActionContext::any_device(DEFAULT_BINDINGS).evaluate(&inputs);
+        assert_eq!(frame.value(Action::MoveX), 0.0);
+    }
+
+    #[test]
+    fn gamepad_stick_ignores_fake_dpad_buttons() {
+        let mut inputs = connected_inputs();
+        inputs[2].stick_left.x_average = 0.6;
+        inputs[2].move_right.button_is_down = true;
+
+        let frame = ActionContext::any_device(DEFAULT_BINDINGS).evaluate(&inputs);
+        assert_eq!(frame.value(Action::MoveX), 0.6);
+    }
+
+    #[test]
+    fn largest_magnitude_wins() {
+        let mut inputs = connected_inputs();
+        inputs[1].stick_left.y_average = 0.3;
+        inputs[3].stick_left.y_average = -0.8;
+
+        let frame = ActionContext::any_device(DEFAULT_BINDINGS).evaluate(&inputs);
+        assert_eq!(frame.value(Action::MoveY), -0.8);
+    }
+
+    #[test]
+    fn per_player_context_only_sees_its_slot() {
+        let mut inputs = connected_inputs();
+        inputs[1].action_down.button_is_down = true;
+        inputs[1].action_down.half_transitions = 1;
+
+        let player_one = ActionContext::for_slot(1, DEFAULT_BINDINGS).evaluate(&inputs);
+        let player_two = ActionContext::for_slot(2, DEFAULT_BINDINGS).evaluate(&inputs);
+        assert!(player_one.was_pressed(Action::Jump));
+        assert!(!player_two.is_down(Action::Jump));
+    }
+
+    #[test]
+    fn disconnected_slots_are_ignored() {
+        let mut inputs = GameInput::default();
+        inputs[4].start.button_is_down = true;
+
+        let frame = ActionContext::any_device(DEFAULT_BINDINGS).evaluate(&inputs);
+        assert!(!frame.is_down(Action::Pause));
+    }
+
+    #[test]
+    fn presses_from_several_devices_add_up() {
+        let mut inputs = connected_inputs();
+        inputs[0].start.half_transitions = 2;
+        inputs[1].start.half_transitions = 1;
+        inputs[1].start.button_is_down = true;
+
+        let slots = ControllerSlots::single(0).with(1);
+        let frame = ActionContext {
+            slots,
+            bindings: DEFAULT_BINDINGS,
+        }
+        .evaluate(&inputs);
+        let pause = frame.state(Action::Pause);
+        assert_eq!((pause.press_count, pause.release_count), (2, 1));
+        assert!(pause.is_down);
+    }
+
+    #[test]
+    fn custom_binding() {
+        const BINDINGS: &[Binding] = &[Binding {
+            action: Action::Jump,
+            source: InputSource::Axis(StickAxis::LeftY),
+            device: Device::Any,
+        }];
+        let mut inputs = connected_inputs();
+        inputs[1].stick_left.y_average = 1.0;
+        inputs[1].action_down.button_is_down = true;
+
+        let frame = ActionContext::any_device(BINDINGS).evaluate(&inputs);
+        assert!(frame.is_down(Action::Jump));
+        assert!(!frame.is_down(Action::MoveY));
+    }
+}
@@ -0,0 +1,257 @@
+// Times the tiled renderer at the window's resolution with different thread counts, next to
+// starting new threads for every frame, and what finding the frame's dirty tiles costs on top.
+// `cargo run --release -p crusty_handmade --bin render_bench -- [frames] [max threads]`
+// Threads default to the number of cores. The profiler blocks hit along the way get listed
+// under each thread count.
+
+use std::f32::consts::TAU;
+use std::hint::black_box;
+use std::mem::MaybeUninit;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+use crusty_handmade::dirty_rects::{TileHashes, DIRTY_TILE_SIZE};
+use crusty_handmade::memory::MemoryArena;
+use crusty_handmade::pixels::PixelsMut;
+use crusty_handmade::profiler::{block_stats, reset_block_stats};
+use crusty_handmade::rasterizer::Canvas;
+use crusty_handmade::render_group::{RenderCommand, RenderGroup};
+use utility::image::Bitmap;
+use utility::math::{v2, v4};
+use utility::megabytes;
+use utility::work_queue::WorkQueue;
+
+const WIDTH: usize = 1280;
+const HEIGHT: usize = 720;
+const TILE_HEIGHT: usize = 32;
+const DEFAULT_FRAMES: u32 = 100;
+
+/// Soft edged premultiplied circle, something like a sprite.
+fn blob(size: usize) -> Bitmap {
+    let radius = size as f32 / 2.0;
+    let pixels = (0..size * size)
+        .map(|index| {
+            let x = (index % size) as f32 + 0.5 - radius;
+            let y = (index / size) as f32 + 0.5 - radius;
+            let alpha = (1.0 - (x * x + y * y).sqrt() / radius).clamp(0.0, 1.0);
+            let a = (alpha * 255.0) as u32;
+            a << 24 | a << 16 | (a / 2) << 8 | (a / 4)
+        })
+        .collect();
+    Bitmap::new(size, size, pixels).unwrap()
+}
+
+/// xorshift, the scene only has to look busy and be the same every run.
+fn next_random(state: &mut u32) -> f32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    (*state % 10000) as f32 / 10000.0
+}
+
+fn push_scene<'a>(group: &mut RenderGroup<'a>, sprite: &'a Bitmap) {
+    let mut random = 0x1234567;
+    group.push(RenderCommand::WeirdGradient {
+        x_offset: 0,
+        y_offset: 0,
+    });
+    for _ in 0..500 {
+        let min = v2(
+            next_random(&mut random) * WIDTH as f32,
+            next_random(&mut random) * HEIGHT as f32,
+        );
+        let size = v2(
+            next_random(&mut random) * 200.0,
+            next_random(&mut random) * 100.0,
+        );
+        let color = v4(
+            next_random(&mut random),
+            next_random(&mut random),
+            next_random(&mut random),
+            1.0,
+        );
+        group.push_rectangle(min, min + size, color);
+    }
+    for _ in 0..500 {
+        let position = v2(
+            next_random(&mut random) * WIDTH as f32 - 32.0,
+            next_random(&mut random) * HEIGHT as f32 - 32.0,
+        );
+        group.push_bitmap(sprite, position);
+    }
+    for _ in 0..100 {
+        let center = v2(
+            next_random(&mut random) * WIDTH as f32,
+            next_random(&mut random) * HEIGHT as f32,
+        );
+        let (sin, cos) = (next_random(&mut random) * TAU).sin_cos();
+        let scale = 0.5 + next_random(&mut random) * 2.0;
+        let x_axis = v2(cos, sin) * (64.0 * scale);
+        let y_axis = v2(-sin, cos) * (64.0 * scale);
+        group.push_transformed_bitmap(sprite, center - x_axis * 0.5 - y_axis * 0.5, x_axis, y_axis);
+    }
+}
+
+/// How render_tiled used to split frames, starting its threads anew every frame, to compare the
+/// work queue with.
+fn render_with_new_threads(group: &RenderGroup, pixels: PixelsMut, thread_count: usize) {
+    let tiles = Mutex::new(pixels.into_bands(TILE_HEIGHT).enumerate());
+    let work = || loop {
+        let next_tile = tiles.lock().unwrap().next();
+        let Some((index, tile)) = next_tile else {
+            break;
+        };
+        group.render(&mut Canvas::with_origin(
+            tile,
+            0,
+            (index * TILE_HEIGHT) as i32,
+        ));
+    };
+    thread::scope(|scope| {
+        for _ in 1..thread_count {
+            scope.spawn(work);
+        }
+        work();
+    });
+}
+
+/// How frames used to get hashed to find the dirty tiles, after they were done, on one thread and
+/// a pixel at a time.
+fn hash_after_the_frame(pixels: &PixelsMut, hashes: &mut Vec<u64>) {
+    hashes.clear();
+    for min_y in (0..pixels.height()).step_by(DIRTY_TILE_SIZE) {
+        let max_y = (min_y + DIRTY_TILE_SIZE).min(pixels.height());
+        for min_x in (0..pixels.width()).step_by(DIRTY_TILE_SIZE) {
+            let max_x = (min_x + DIRTY_TILE_SIZE).min(pixels.width());
+            let hash = (min_y..max_y).fold(0xCBF29CE484222325, |hash, y| {
+                pixels.row(y).unwrap()[min_x..max_x]
+                    .iter()
+                    .fold(hash, |hash, &pixel| {
+                        (hash ^ pixel as u64).wrapping_mul(0x100000001B3)
+                    })
+            });
+            hashes.push(hash);
+        }
+    }
+}
+
+/// Milliseconds per frame, and the last frame.
+fn time_frames<F>(frames: u32, mut render: F) -> (f64, Vec<u32>)
+where
+    F: FnMut(PixelsMut),
+{
+    let mut pixels = vec![0u32; WIDTH * HEIGHT];
+    let start = Instant::now();
+    for _ in 0..frames {
+        render(PixelsMut::new(&mut pixels, WIDTH, HEIGHT, WIDTH).unwrap());
+    }
+    let milliseconds = start.elapsed().as_secs_f64() * 1000.0 / frames.max(1) as f64;
+    (milliseconds, pixels)
+}
+
+fn bench(name: &str, group: &RenderGroup, frames: u32, thread_counts: &[usize]) {
+    println!(
+        "{name}: {WIDTH}x{HEIGHT}, {} commands, tiles of {TILE_HEIGHT} rows, {frames} frames",
+        group.entries().len()
+    );
+    let mut reference = vec![];
+    let mut single_threaded_time = 0.0;
+    for &thread_count in thread_counts {
+        let work_queue = WorkQueue::new(thread_count - 1);
+        reset_block_stats();
+        let (milliseconds, pixels) = time_frames(frames, |target| {
+            group.render_tiled(target, TILE_HEIGHT, &work_queue)
+        });
+        let stats = block_stats();
+        let (new_threads_milliseconds, new_threads_pixels) = time_frames(frames, |target| {
+            render_with_new_threads(group, target, thread_count)
+        });
+
+        let mut tile_hashes = TileHashes::default();
+        let (hashing_tiles_milliseconds, _) = time_frames(frames, |target| {
+            tile_hashes.begin_frame(WIDTH, HEIGHT, TILE_HEIGHT);
+            let hashes = &tile_hashes;
+            group.render_tiled_with(target, TILE_HEIGHT, &work_queue, &|index, tile| {
+                hashes.hash_band(index, tile)
+            });
+            black_box(tile_hashes.finish_frame());
+        });
+        let mut frame_hashes = vec![];
+        let (hashing_after_milliseconds, _) = time_frames(frames, |mut target| {
+            let view = target.view_mut(0, 0, WIDTH, HEIGHT).unwrap();
+            group.render_tiled(view, TILE_HEIGHT, &work_queue);
+            hash_after_the_frame(&target, &mut frame_hashes);
+            black_box(&frame_hashes);
+        });
+
+        if thread_count == 1 {
+            single_threaded_time = milliseconds;
+            reference = pixels;
+        } else {
+            assert!(
+                pixels == reference,
+                "{thread_count} threads drew something else"
+            );
+        }
+        assert!(new_threads_pixels == reference);
+        println!(
+            "{thread_count:3} threads: {milliseconds:8.3} ms per frame, {:5.2}x, \
+             {new_threads_milliseconds:8.3} ms starting the threads every frame",
+            single_threaded_time / milliseconds
+        );
+        println!(
+            "             finding dirty tiles: {hashing_tiles_milliseconds:8.3} ms hashing them \
+             as they get drawn, {hashing_after_milliseconds:8.3} ms hashing the frame afterwards"
+        );
+        for stats in stats {
+            println!(
+                "    {}: {} hits, {} cycles per hit",
+                stats.name,
+                stats.hits,
+                stats.cycles_per_hit()
+            );
+        }
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let frames = match args.next() {
+        Some(frames) => frames.parse().expect("frame count is a number"),
+        None => DEFAULT_FRAMES,
+    };
+    // the thread drawing the frame counts, so there's at least one
+    let max_threads = match args.next() {
+        Some(threads) => threads
+            .parse::<NonZeroUsize>()
+            .expect("thread count is a number above 0")
+            .get(),
+        None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+
+    let mut thread_counts: Vec<usize> = (0..)
+        .map(|power| 1 << power)
+        .take_while(|&count| count < max_threads)
+        .collect();
+    thread_counts.push(max_threads);
+
+    let sprite = blob(64);
+    let mut memory = vec![MaybeUninit::uninit(); megabytes(1)];
+    let mut arena = MemoryArena::new(&mut memory);
+    let mut group = RenderGroup::new(&mut arena, 2048).expect("arena fits the group");
+    push_scene(&mut group, &sprite);
+    group.sort();
+    bench("busy", &group, frames, &thread_counts);
+
+    // Note(voided): with little to draw, starting the threads is most of the frame
+    let mut memory = vec![MaybeUninit::uninit(); megabytes(1)];
+    let mut arena = MemoryArena::new(&mut memory);
+    let mut group = RenderGroup::new(&mut arena, 16).expect("arena fits the group");
+    group.push(RenderCommand::WeirdGradient {
+        x_offset: 0,
+        y_offset: 0,
+    });
+    bench("light", &group, frames, &thread_counts);
+}
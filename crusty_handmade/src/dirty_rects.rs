@@ -0,0 +1,300 @@
+// Which parts of a frame changed since the last one, so presenting it only has to copy those.
+// The renderer hashes every frame in tiles as it draws them and reports the tiles whose hash
+// changed, as a handful of rectangles on the back buffer.
+// Note(voided): hashing the pixels instead of tracking what the commands touched means things
+// that get redrawn the same every frame, like the background, don't count as changed. Every band
+// gets hashed by the thread that drew it, right after, while its pixels are still in the cache.
+
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use utility::math::Rect2i;
+
+use crate::pixels::PixelsMut;
+
+/// Most rectangles a frame reports, past that the ones that grow the least get merged.
+pub const MAX_DIRTY_RECTS: usize = 16;
+/// Width of the tiles that get hashed, in pixels.
+pub const DIRTY_TILE_SIZE: usize = 64;
+
+/// Rectangles that never overlap, merged as they get added.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRects {
+    rects: [Rect2i; MAX_DIRTY_RECTS],
+    count: usize,
+}
+
+impl DirtyRects {
+    /// All of `bounds`, for frames nothing is known about.
+    pub fn everything(bounds: Rect2i) -> Self {
+        let mut dirty = Self::default();
+        dirty.add(bounds);
+        dirty
+    }
+
+    /// Merges `rect` with every rectangle it overlaps, and with the ones it lines up with so well
+    /// that their union covers no extra pixels, like neighbouring tiles. Without room for it, it
+    /// gets merged with whichever rectangle that grows the least.
+    pub fn add(&mut self, rect: Rect2i) {
+        if rect.is_empty() {
+            return;
+        }
+        let mut rect = rect;
+        loop {
+            let mergeable = self.rects().iter().position(|other| {
+                !other.intersect(&rect).is_empty()
+                    || other.union(&rect).area() <= other.area() + rect.area()
+            });
+            let merge = mergeable.or_else(|| {
+                (self.count == MAX_DIRTY_RECTS)
+                    .then(|| {
+                        (0..self.count).min_by_key(|&index| {
+                            let other = self.rects[index];
+                            other.union(&rect).area() - other.area()
+                        })
+                    })
+                    .flatten()
+            });
+            let Some(index) = merge else {
+                break;
+            };
+            // the union may overlap others now, so it goes around again
+            rect = rect.union(&self.rects[index]);
+            self.count -= 1;
+            self.rects[index] = self.rects[self.count];
+        }
+        self.rects[self.count] = rect;
+        self.count += 1;
+    }
+
+    pub fn rects(&self) -> &[Rect2i] {
+        &self.rects[..self.count]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Pixels covered, nothing counts twice since the rectangles don't overlap.
+    pub fn area(&self) -> i64 {
+        self.rects().iter().map(Rect2i::area).sum()
+    }
+
+    pub fn bounds(&self) -> Rect2i {
+        self.rects()
+            .iter()
+            .fold(Rect2i::default(), |bounds, rect| bounds.union(rect))
+    }
+}
+
+/// Hashes of the last frame's tiles, to find what the next one changed. Tiles are
+/// [`DIRTY_TILE_SIZE`] wide and as high as the bands the frame gets drawn in.
+#[derive(Debug, Default)]
+pub struct TileHashes {
+    width: usize,
+    height: usize,
+    band_height: usize,
+    hashes: Vec<u64>,
+    /// This frame's, filled in by whichever threads draw the bands.
+    next: Vec<AtomicU64>,
+    resized: bool,
+}
+
+impl TileHashes {
+    /// Gets ready for a frame of `width` x `height` pixels drawn in bands of `band_height` rows.
+    /// The first frame and any frame of a different size are dirty everywhere.
+    pub fn begin_frame(&mut self, width: usize, height: usize, band_height: usize) {
+        let band_height = band_height.max(1);
+        self.resized = (width, height, band_height) != (self.width, self.height, self.band_height);
+        if self.resized {
+            self.width = width;
+            self.height = height;
+            self.band_height = band_height;
+            let tile_count = width.div_ceil(DIRTY_TILE_SIZE) * height.div_ceil(band_height);
+            self.hashes.clear();
+            self.hashes.resize(tile_count, 0);
+            self.next = (0..tile_count).map(|_| AtomicU64::new(0)).collect();
+        }
+    }
+
+    /// Hashes band `index` of the frame, meant to be called by the thread that just drew it.
+    pub fn hash_band(&self, index: usize, band: &PixelsMut) {
+        let tiles_x = self.width.div_ceil(DIRTY_TILE_SIZE);
+        let Some(tiles) = self.next.get(index * tiles_x..(index + 1) * tiles_x) else {
+            return;
+        };
+        // only this thread touches the band's hashes, the work queue orders them with the rest
+        let width = band.width().min(self.width);
+        for tile in tiles {
+            tile.store(FNV_OFFSET, Ordering::Relaxed);
+        }
+        for row in band.rows() {
+            for (tile, pixels) in tiles.iter().zip(row[..width].chunks(DIRTY_TILE_SIZE)) {
+                // rows get hashed on their own, folding the lanes loses too much to carry on from
+                let hash =
+                    (tile.load(Ordering::Relaxed) ^ hash_pixels(pixels)).wrapping_mul(FNV_PRIME);
+                tile.store(hash, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// The tiles that changed since the last frame, once every band has been hashed.
+    /// Note(voided): a changed tile whose hash happens to match the old one counts as unchanged,
+    /// with 64 bits that's not worth worrying about.
+    pub fn finish_frame(&mut self) -> DirtyRects {
+        let tiles_x = self.width.div_ceil(DIRTY_TILE_SIZE);
+        let mut dirty = DirtyRects::default();
+        for (index, (last, next)) in self.hashes.iter_mut().zip(&mut self.next).enumerate() {
+            let hash = *next.get_mut();
+            if mem::replace(last, hash) == hash && !self.resized {
+                continue;
+            }
+            let (tile_x, band) = (index % tiles_x, index / tiles_x);
+            let min_x = tile_x * DIRTY_TILE_SIZE;
+            let min_y = band * self.band_height;
+            dirty.add(Rect2i::new(
+                min_x as i32,
+                min_y as i32,
+                (min_x + DIRTY_TILE_SIZE).min(self.width) as i32,
+                (min_y + self.band_height).min(self.height) as i32,
+            ));
+        }
+        self.resized = false;
+        dirty
+    }
+}
+
+/// 64 bit FNV-1a over a pixel at a time, in four lanes that get folded together at the end so
+/// the multiplies don't all wait on each other.
+fn hash_pixels(pixels: &[u32]) -> u64 {
+    let mut lanes = [FNV_OFFSET; 4];
+    let mut chunks = pixels.chunks_exact(lanes.len());
+    for chunk in &mut chunks {
+        for (lane, &pixel) in lanes.iter_mut().zip(chunk) {
+            *lane = (*lane ^ pixel as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+    let hash = chunks.remainder().iter().fold(lanes[0], |hash, &pixel| {
+        (hash ^ pixel as u64).wrapping_mul(FNV_PRIME)
+    });
+    lanes[1..]
+        .iter()
+        .fold(hash, |hash, &lane| (hash ^ lane).wrapping_mul(FNV_PRIME))
+}
+
+const FNV_OFFSET: u64 = 0xCBF29CE484222325;
+const FNV_PRIME: u64 = 0x100000001B3;
+
+#[cfg(test)]
+mod dirty_rects_tests {
+    use utility::math::Rect2i;
+
+    use crate::dirty_rects::{DirtyRects, TileHashes, DIRTY_TILE_SIZE, MAX_DIRTY_RECTS};
+    use crate::pixels::PixelsMut;
+
+    fn dirty(rects: &[Rect2i]) -> DirtyRects {
+        let mut dirty = DirtyRects::default();
+        for &rect in rects {
+            dirty.add(rect);
+        }
+        dirty
+    }
+
+    #[test]
+    fn overlapping_rects_merge() {
+        let merged = dirty(&[
+            Rect2i::new(0, 0, 10, 10),
+            Rect2i::new(20, 0, 30, 10),
+            Rect2i::new(5, 5, 25, 8),
+        ]);
+        assert_eq!(merged.rects(), [Rect2i::new(0, 0, 30, 10)]);
+
+        // the union of the first two overlaps the third, which has to join them
+        let chained = dirty(&[
+            Rect2i::new(0, 0, 10, 10),
+            Rect2i::new(12, 12, 20, 20),
+            Rect2i::new(8, 8, 14, 14),
+        ]);
+        assert_eq!(chained.rects(), [Rect2i::new(0, 0, 20, 20)]);
+    }
+
+    #[test]
+    fn lined_up_rects_merge_and_others_stay_apart() {
+        let row = dirty(&[
+            Rect2i::new(0, 0, 4, 4),
+            Rect2i::new(4, 0, 8, 4),
+            Rect2i::new(0, 4, 8, 8),
+        ]);
+        assert_eq!(row.rects(), [Rect2i::new(0, 0, 8, 8)]);
+
+        // diagonal neighbours would cover pixels that didn't change
+        let diagonal = dirty(&[Rect2i::new(0, 0, 4, 4), Rect2i::new(4, 4, 8, 8)]);
+        assert_eq!(diagonal.rects().len(), 2);
+        assert_eq!(diagonal.area(), 32);
+        assert_eq!(diagonal.bounds(), Rect2i::new(0, 0, 8, 8));
+
+        let empty = dirty(&[Rect2i::new(3, 3, 3, 9)]);
+        assert!(empty.is_empty());
+        assert_eq!(empty.bounds(), Rect2i::default());
+    }
+
+    #[test]
+    fn full_list_merges_the_closest() {
+        // a diagonal of pixels, far enough apart that none of them line up
+        let mut pixels: Vec<Rect2i> = (0..MAX_DIRTY_RECTS as i32)
+            .map(|i| Rect2i::with_size(i * 10, i * 10, 1, 1))
+            .collect();
+        let mut full = dirty(&pixels);
+        assert_eq!(full.rects().len(), MAX_DIRTY_RECTS);
+
+        full.add(Rect2i::with_size(152, 152, 1, 1));
+        assert_eq!(full.rects().len(), MAX_DIRTY_RECTS);
+        assert!(full.rects().contains(&Rect2i::new(150, 150, 153, 153)));
+        pixels.pop();
+        for pixel in pixels {
+            assert!(full.rects().contains(&pixel));
+        }
+    }
+
+    #[test]
+    fn changed_tiles_are_dirty() {
+        let tile = DIRTY_TILE_SIZE;
+        let (width, height) = (tile * 2 + 5, tile + 1);
+        let mut frame = vec![0u32; width * height];
+        let mut hashes = TileHashes::default();
+        let mut update = |frame: &mut Vec<u32>, band_height: usize| {
+            hashes.begin_frame(width, height, band_height);
+            let pixels = PixelsMut::new(frame, width, height, width).unwrap();
+            for (index, band) in pixels.into_bands(band_height).enumerate() {
+                hashes.hash_band(index, &band);
+            }
+            hashes.finish_frame()
+        };
+
+        let everything = Rect2i::new(0, 0, width as i32, height as i32);
+        assert_eq!(update(&mut frame, tile).rects(), [everything]);
+        assert!(update(&mut frame, tile).is_empty());
+
+        // the last column of tiles is only 5 wide
+        frame[width * 3 + tile * 2 + 4] = 0xFF;
+        frame[width * tile] = 0xFF;
+        let dirty = update(&mut frame, tile);
+        let (tile_i, width_i, height_i) = (tile as i32, width as i32, height as i32);
+        assert_eq!(
+            dirty.rects(),
+            [
+                Rect2i::new(tile_i * 2, 0, width_i, tile_i),
+                Rect2i::new(0, tile_i, tile_i, height_i)
+            ]
+        );
+        assert!(update(&mut frame, tile).is_empty());
+
+        // tiles are as high as the bands, other bands start over
+        assert_eq!(update(&mut frame, 8).rects(), [everything]);
+        frame[width * 3] = 1;
+        assert_eq!(
+            update(&mut frame, 8).rects(),
+            [Rect2i::new(0, 0, tile_i, 8)]
+        );
+    }
+}
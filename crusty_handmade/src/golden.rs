@@ -0,0 +1,347 @@
+// Golden image tests: runs the game on heap memory with scripted input and compares frames with
+// the reference PNGs in `golden/`.
+// - `GOLDEN_BLESS=1 cargo test -p crusty_handmade golden` writes the current frames as the new
+//   references, check the images before committing them.
+// - On a mismatch the frame and a diff image get written next to the reference, as
+//   `<name>.actual.png` and `<name>.diff.png`.
+
+use std::path::{Path, PathBuf};
+
+use utility::image::png::{encode_png, parse_png};
+use utility::image::Bitmap;
+use utility::megabytes;
+
+use crate::game_update_and_render;
+use crate::types::{
+    ButtonId, GameControllerInput, GameInput, GameOffscreenBuffer, GameSoundBuffer, HeapGameMemory,
+    PixelFormat, KEYBOARD_SLOT,
+};
+
+const GOLDEN_DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/golden");
+const WIDTH: i32 = 160;
+const HEIGHT: i32 = 120;
+const SAMPLES_PER_FRAME: usize = 48000 / 30;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    /// Largest difference in any channel for pixels that count as the same.
+    pub channel: u8,
+    /// How many pixels can be different before the frame doesn't match.
+    pub pixels: usize,
+}
+
+impl Tolerance {
+    pub const EXACT: Self = Self {
+        channel: 0,
+        pixels: 0,
+    };
+}
+
+/// Keyboard input, one GameInput per frame.
+#[derive(Default)]
+pub struct InputScript {
+    frames: Vec<GameInput>,
+}
+
+impl InputScript {
+    /// `buttons` are down for the next `frames` frames, everything else is up.
+    pub fn hold(mut self, frames: usize, buttons: &[ButtonId]) -> Self {
+        for _ in 0..frames {
+            let previous = self
+                .frames
+                .last()
+                .map(|input| input[KEYBOARD_SLOT].clone())
+                .unwrap_or_default();
+
+            let mut keyboard = GameControllerInput {
+                is_connected: true,
+                ..Default::default()
+            };
+            for &id in buttons {
+                keyboard.button_mut(id).button_is_down = true;
+            }
+            for (button, old) in keyboard.buttons_mut().into_iter().zip(previous.buttons()) {
+                button.half_transitions = (button.button_is_down != old.button_is_down) as u32;
+            }
+
+            let mut input = GameInput {
+                has_focus: true,
+                ..Default::default()
+            };
+            input[KEYBOARD_SLOT] = keyboard;
+            self.frames.push(input);
+        }
+        self
+    }
+
+    pub fn idle(self, frames: usize) -> Self {
+        self.hold(frames, &[])
+    }
+}
+
+/// Runs the script from a fresh game and returns the frames at the given indices.
+pub fn run_game(script: &InputScript, capture: &[usize]) -> Vec<Bitmap> {
+    run_game_in_format(script, capture, PixelFormat::Bgra8)
+}
+
+/// Like [`run_game`], with a back buffer in `format`. The frames come out as opaque 0xFFRRGGBB
+/// either way.
+pub fn run_game_in_format(
+    script: &InputScript,
+    capture: &[usize],
+    format: PixelFormat,
+) -> Vec<Bitmap> {
+    // Note(voided): the assets live in the workspace root, where `cargo run` starts the game
+    let workspace_root = concat!(env!("CARGO_MANIFEST_DIR"), "/..");
+    let mut memory = HeapGameMemory::new(megabytes(64), megabytes(16), workspace_root);
+    // u32s, so the pixels are aligned for every format
+    let mut pixels = vec![0u32; (WIDTH * HEIGHT) as usize];
+    let mut samples = vec![0.0; SAMPLES_PER_FRAME * 2];
+    let mut frames = vec![];
+
+    for (index, input) in script.frames.iter().enumerate() {
+        let mut buffer =
+            unsafe { GameOffscreenBuffer::new(pixels.as_mut_ptr().cast(), WIDTH, HEIGHT, format) }
+                .expect("test buffer is valid");
+        let mut sound = GameSoundBuffer {
+            samples_rate: 48000,
+            buffer: &mut samples,
+        };
+        unsafe { game_update_and_render(&mut memory.memory, input, &mut buffer, &mut sound) };
+
+        if capture.contains(&index) {
+            frames.push(buffer.to_bitmap());
+        }
+    }
+    frames
+}
+
+fn channel_difference(a: u32, b: u32) -> u8 {
+    [0, 8, 16, 24]
+        .iter()
+        .map(|shift| ((a >> shift) & 0xFF).abs_diff((b >> shift) & 0xFF) as u8)
+        .max()
+        .unwrap_or(0)
+}
+
+/// `None` if the images match, otherwise why not and an image that shows where: differing
+/// pixels in red on top of a faded version of `actual`.
+pub fn compare(
+    expected: &Bitmap,
+    actual: &Bitmap,
+    tolerance: Tolerance,
+) -> Option<(String, Bitmap)> {
+    if (expected.width(), expected.height()) != (actual.width(), actual.height()) {
+        let reason = format!(
+            "size is {}x{}, expected {}x{}",
+            actual.width(),
+            actual.height(),
+            expected.width(),
+            expected.height()
+        );
+        return Some((reason, Bitmap::new(0, 0, vec![]).unwrap()));
+    }
+
+    let mut differing = 0;
+    let mut largest = 0;
+    let diff = expected
+        .pixels()
+        .iter()
+        .zip(actual.pixels())
+        .map(|(&expected, &actual)| {
+            let difference = channel_difference(expected, actual);
+            largest = largest.max(difference);
+            if difference > tolerance.channel {
+                differing += 1;
+                0xFFFF0000
+            } else {
+                // a quarter of the brightness, so the red stands out
+                0xFF000000 | (actual >> 2) & 0x003F3F3F
+            }
+        })
+        .collect();
+
+    (differing > tolerance.pixels).then(|| {
+        let reason = format!(
+            "{differing} pixels differ by more than {}, the largest difference is {largest}",
+            tolerance.channel
+        );
+        let diff = Bitmap::new(actual.width(), actual.height(), diff).unwrap();
+        (reason, diff)
+    })
+}
+
+fn golden_path(name: &str, suffix: &str) -> PathBuf {
+    Path::new(GOLDEN_DIRECTORY).join(format!("{name}{suffix}.png"))
+}
+
+/// Compares `actual` with the reference called `name`, or replaces the reference when blessing.
+pub fn check_golden(name: &str, actual: &Bitmap, tolerance: Tolerance) {
+    let reference = golden_path(name, "");
+    let actual_path = golden_path(name, ".actual");
+    let diff_path = golden_path(name, ".diff");
+
+    if std::env::var_os("GOLDEN_BLESS").is_some() {
+        std::fs::create_dir_all(GOLDEN_DIRECTORY).unwrap();
+        std::fs::write(&reference, encode_png(actual)).unwrap();
+        let _ = std::fs::remove_file(actual_path);
+        let _ = std::fs::remove_file(diff_path);
+        return;
+    }
+
+    let expected = match std::fs::read(&reference) {
+        Ok(data) => parse_png(&data).unwrap(),
+        Err(err) => {
+            std::fs::write(&actual_path, encode_png(actual)).unwrap();
+            panic!(
+                "no reference for {name} at {} ({err}), run with GOLDEN_BLESS=1 to create it",
+                reference.display()
+            );
+        }
+    };
+
+    if let Some((reason, diff)) = compare(&expected, actual, tolerance) {
+        std::fs::write(&actual_path, encode_png(actual)).unwrap();
+        if diff.width() > 0 {
+            std::fs::write(&diff_path, encode_png(&diff)).unwrap();
+        }
+        panic!(
+            "{name} doesn't match the reference: {reason}. See {} and {}, run with \
+             GOLDEN_BLESS=1 if the change is intended",
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+    let _ = std::fs::remove_file(actual_path);
+    let _ = std::fs::remove_file(diff_path);
+}
+
+#[cfg(test)]
+mod golden_tests {
+    use utility::image::Bitmap;
+
+    use utility::megabytes;
+
+    use crate::game_update_and_render;
+    use crate::golden::{
+        check_golden, compare, run_game, run_game_in_format, InputScript, Tolerance,
+        SAMPLES_PER_FRAME,
+    };
+    use crate::types::{
+        ButtonId, GameOffscreenBuffer, GameSoundBuffer, GameState, HeapGameMemory, PixelFormat,
+    };
+
+    #[test]
+    fn compare_with_tolerance() {
+        let expected = Bitmap::new(2, 2, vec![0xFF000000, 0xFF102030, 0xFFFFFFFF, 0]).unwrap();
+        let actual = Bitmap::new(2, 2, vec![0xFF000002, 0xFF102030, 0xFFFFFFF0, 0]).unwrap();
+
+        assert!(compare(&expected, &expected, Tolerance::EXACT).is_none());
+        let loose = Tolerance {
+            channel: 15,
+            pixels: 0,
+        };
+        assert!(compare(&expected, &actual, loose).is_none());
+        let few_pixels = Tolerance {
+            channel: 2,
+            pixels: 1,
+        };
+        assert!(compare(&expected, &actual, few_pixels).is_none());
+
+        let (reason, diff) = compare(&expected, &actual, Tolerance::EXACT).unwrap();
+        assert!(reason.starts_with("2 pixels"), "{reason}");
+        assert_eq!(
+            diff.pixels(),
+            &[0xFFFF0000, 0xFF04080C, 0xFFFF0000, 0xFF000000]
+        );
+
+        let smaller = Bitmap::new(1, 2, vec![0, 0]).unwrap();
+        assert!(compare(&expected, &smaller, loose).is_some());
+    }
+
+    #[test]
+    fn golden_first_frame() {
+        let frames = run_game(&InputScript::default().idle(1), &[0]);
+        check_golden("first_frame", &frames[0], Tolerance::EXACT);
+    }
+
+    #[test]
+    fn golden_scrolling() {
+        let script = InputScript::default()
+            .idle(2)
+            .hold(10, &[ButtonId::MoveRight])
+            .hold(5, &[ButtonId::MoveLeft, ButtonId::ActionDown])
+            .idle(1);
+        let frames = run_game(&script, &[11, 17]);
+        check_golden("scroll_right", &frames[0], Tolerance::EXACT);
+        check_golden("scroll_left_and_jump", &frames[1], Tolerance::EXACT);
+    }
+
+    #[test]
+    fn pixel_formats_draw_the_same_frame() {
+        let script = InputScript::default()
+            .idle(2)
+            .hold(3, &[ButtonId::MoveRight]);
+        let expected = run_game(&script, &[4]);
+        let rgba = run_game_in_format(&script, &[4], PixelFormat::Rgba8);
+        assert!(compare(&expected[0], &rgba[0], Tolerance::EXACT).is_none());
+
+        // 5 bits lose up to half a step of 255 / 31
+        let rgb565 = run_game_in_format(&script, &[4], PixelFormat::Rgb565);
+        let quantized = Tolerance {
+            channel: 4,
+            pixels: 0,
+        };
+        assert!(compare(&expected[0], &rgb565[0], quantized).is_none());
+    }
+
+    /// Runs a couple of frames into a 640x480 buffer, returns whether the game found room to draw
+    /// them and whether any pixel got drawn.
+    fn run_with_transient_storage(
+        format: PixelFormat,
+        transient_storage_size: usize,
+    ) -> (bool, bool) {
+        let (width, height) = (640, 480);
+        let workspace_root = concat!(env!("CARGO_MANIFEST_DIR"), "/..");
+        let mut memory = HeapGameMemory::new(megabytes(64), transient_storage_size, workspace_root);
+        // u32s keep the pixels aligned for every format
+        let size = (width * height) as usize * format.bytes_per_pixel();
+        let mut pixels = vec![0u32; size.div_ceil(4)];
+        let mut samples = vec![0.0; SAMPLES_PER_FRAME * 2];
+        for input in InputScript::default().idle(2).frames {
+            let mut buffer = unsafe {
+                GameOffscreenBuffer::new(pixels.as_mut_ptr().cast(), width, height, format)
+            }
+            .unwrap();
+            let mut sound = GameSoundBuffer {
+                samples_rate: 48000,
+                buffer: &mut samples,
+            };
+            unsafe { game_update_and_render(&mut memory.memory, &input, &mut buffer, &mut sound) };
+        }
+
+        let game_state = unsafe { &*memory.memory.permanent_storage.cast::<GameState>() };
+        (
+            game_state.frame_fits,
+            pixels.iter().any(|&pixel| pixel != 0),
+        )
+    }
+
+    #[test]
+    fn frames_without_room_are_left_out() {
+        // the render group fits, a 640x480 frame to convert doesn't
+        assert_eq!(
+            run_with_transient_storage(PixelFormat::Rgb565, megabytes(1)),
+            (false, false)
+        );
+        // not even the render group fits
+        assert_eq!(
+            run_with_transient_storage(PixelFormat::Bgra8, 1024),
+            (false, false)
+        );
+        assert_eq!(
+            run_with_transient_storage(PixelFormat::Bgra8, megabytes(1)),
+            (true, true)
+        );
+    }
+}
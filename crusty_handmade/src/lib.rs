@@ -1,30 +1,207 @@
+use actions::{Action, ActionContext, DEFAULT_BINDINGS};
+use dirty_rects::{DirtyRects, TileHashes};
+use lighting::{dome_normal_map, Lighting, PointLight};
+use memory::MemoryArena;
+use pixels::PixelsMut;
+use render_group::{RenderCommand, RenderGroup, Transform};
+use render_target::RenderTarget;
+use std::f32::consts::TAU;
+use std::fmt::Write;
+use std::io;
 use std::mem;
+use std::path::Path;
+use std::ptr;
 use utility::audio::{AudioSource, SineAudioSource};
+use utility::color::premultiply_color;
+use utility::debug_io::debug_platform_read_entire_file;
+use utility::font::{Alignment, Font, FontMetrics};
+use utility::image::{parse_image, Bitmap, BitmapView};
+use utility::math::{v2, v3, v4, V2};
+use utility::work_queue::WorkQueue;
 
 use types::GameOffscreenBuffer;
 use types::GameSoundBuffer;
+use types::PixelFormat;
 
 use self::types::GameInput;
 use self::types::GameMemory;
 use self::types::GameState;
 
+pub mod actions;
+pub mod dirty_rects;
+#[cfg(test)]
+mod golden;
+pub mod lighting;
+pub mod memory;
+pub mod pixels;
+pub mod players;
+pub mod profiler;
+pub mod rasterizer;
+pub mod render_group;
+pub mod render_target;
+pub mod simd;
 pub mod types;
 
-unsafe fn render_weird_gradient(buffer: &mut GameOffscreenBuffer, x_offset: i32, y_offset: i32) {
-    let mut row = buffer.memory.cast::<u8>();
-    let pitch = buffer.pitch();
-
-    for y in 0..buffer.height {
-        let mut pixel = row.cast::<u32>();
-        for x in 0..buffer.width {
-            let blue = (x + x_offset) as u32 & 0xFF;
-            let green = (y + y_offset) as u32 & 0xFF;
-            let a = green << 8 | blue;
-            *pixel = a;
-            pixel = pixel.offset(1);
+const MAX_RENDER_COMMANDS: usize = 4096;
+const RENDER_TILE_HEIGHT: usize = 32;
+/// Radians per frame.
+const SPRITE_SPIN: f32 = 0.05;
+/// On top of everything the game draws.
+const DEBUG_OVERLAY_LAYER: i32 = 100;
+/// The minimap shows the whole back buffer at this scale, in the top right corner.
+const MINIMAP_SCALE: f32 = 0.25;
+const MINIMAP_ALPHA: f32 = 0.8;
+const MINIMAP_LAYER: i32 = 50;
+/// Commands per frame for the minimap's own render group.
+const MAX_MINIMAP_COMMANDS: usize = 64;
+
+fn load_bitmap(asset_root: &Path, filename: &str) -> Option<Bitmap> {
+    let path = asset_root.join(filename);
+    match debug_platform_read_entire_file(&path).and_then(|data| parse_image(&data)) {
+        Ok(bitmap) => Some(bitmap),
+        Err(err) => {
+            println!("Failed to load {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+/// A font is an atlas image plus a metrics file, see [`FontMetrics::parse`].
+fn load_font(asset_root: &Path, atlas_filename: &str, metrics_filename: &str) -> Option<Font> {
+    let atlas = load_bitmap(asset_root, atlas_filename)?;
+    let metrics_path = asset_root.join(metrics_filename);
+    let metrics = debug_platform_read_entire_file(&metrics_path).and_then(|data| {
+        let text = String::from_utf8(data)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        FontMetrics::parse(&text)
+    });
+    match metrics.and_then(|metrics| Font::new(atlas, metrics)) {
+        Ok(font) => Some(font),
+        Err(err) => {
+            println!("Failed to load {}: {err}", metrics_path.display());
+            None
+        }
+    }
+}
+
+/// Where the test sprite sits, its spinning, stretched copy is next to it.
+const SPRITE_POSITION: V2 = v2(64.0, 64.0);
+
+/// Origin and axes of the spinning copy of the test sprite.
+fn spinning_sprite_axes(angle: f32) -> (V2, V2, V2) {
+    let (sin, cos) = angle.sin_cos();
+    let x_axis = v2(cos, sin) * 48.0;
+    let y_axis = v2(-sin, cos) * 32.0;
+    let origin = v2(112.0, 80.0) - x_axis * 0.5 - y_axis * 0.5;
+    (origin, x_axis, y_axis)
+}
+
+/// The test sprite, and the spinning copy lit if there are lights.
+fn push_sprites<'a>(
+    render_group: &mut RenderGroup<'a>,
+    game_state: &'a GameState,
+    lights: Option<&'a [PointLight]>,
+) {
+    let Some(sprite) = &game_state.test_sprite else {
+        return;
+    };
+    render_group.push_bitmap(sprite, SPRITE_POSITION);
+
+    let (origin, x_axis, y_axis) = spinning_sprite_axes(game_state.sprite_angle);
+    match (&game_state.test_sprite_normals, lights) {
+        (Some(normals), Some(lights)) => {
+            let lighting = Lighting {
+                ambient: v3(0.15, 0.15, 0.25),
+                lights,
+            };
+            render_group.push_lit_bitmap(sprite, normals, origin, x_axis, y_axis, lighting);
         }
-        row = row.offset(pitch);
+        _ => render_group.push_transformed_bitmap(sprite, origin, x_axis, y_axis),
+    }
+}
+
+/// The sprites shrunk down onto a dark backdrop, in a render target of their own. `None` if
+/// transient storage is out of room.
+fn render_minimap<'a>(
+    transient_arena: &mut MemoryArena<'a>,
+    game_state: &'a GameState,
+) -> Option<BitmapView<'a>> {
+    let width = (game_state.buffer_width as f32 * MINIMAP_SCALE) as usize;
+    let height = (game_state.buffer_height as f32 * MINIMAP_SCALE) as usize;
+    let mut minimap = RenderTarget::new(transient_arena, width, height)?;
+    let mut render_group = RenderGroup::new(transient_arena, MAX_MINIMAP_COMMANDS)?;
+
+    render_group.push_clear(premultiply_color(v4(0.02, 0.02, 0.05, 0.75)));
+    render_group.set_transform(Transform {
+        offset: v2(0.0, 0.0),
+        scale: MINIMAP_SCALE,
+    });
+    //Note(voided): plain bitmaps don't scale, so both sprites take the transformed path here,
+    //unlit since the lights don't follow the transform.
+    if let Some(sprite) = &game_state.test_sprite {
+        let (width, height) = (sprite.width() as f32, sprite.height() as f32);
+        let (x_axis, y_axis) = (v2(width, 0.0), v2(0.0, height));
+        render_group.push_transformed_bitmap(sprite, SPRITE_POSITION, x_axis, y_axis);
+        let (origin, x_axis, y_axis) = spinning_sprite_axes(game_state.sprite_angle);
+        render_group.push_transformed_bitmap(sprite, origin, x_axis, y_axis);
+    }
+
+    render_group.sort();
+    render_group.render(&mut minimap.canvas());
+    Some(minimap.finish())
+}
+
+/// Whether transient storage has room for the render group, and for [`render_frame`] to draw the
+/// buffer's frame in when the renderer can't draw into the buffer directly. Says so if not.
+/// Note(voided): those are the first things in transient storage every frame, so trying them out
+/// once when the buffer changes tells whether they fit, alignment included.
+unsafe fn check_frame_room(buffer: &GameOffscreenBuffer, game_memory: &GameMemory) -> bool {
+    let mut transient_arena = MemoryArena::from_raw(
+        game_memory.transient_storage,
+        game_memory.transient_storage_size,
+    );
+    let (width, height) = (buffer.width() as usize, buffer.height() as usize);
+    let frame_fits = buffer.format() == PixelFormat::Bgra8
+        || RenderTarget::new(&mut transient_arena, width, height).is_some();
+    let fits = frame_fits && RenderGroup::new(&mut transient_arena, MAX_RENDER_COMMANDS).is_some();
+    if !fits {
+        println!(
+            "No room to draw a {width}x{height} frame in transient storage, \
+             nothing gets drawn at this size"
+        );
     }
+    fits
+}
+
+/// Draws the sorted group into the buffer and returns what changed since the last frame.
+/// `frame` is where it gets drawn for buffers the renderer can't draw into, `None` draws nothing
+/// into those.
+fn render_frame(
+    render_group: &RenderGroup,
+    frame: Option<RenderTarget>,
+    buffer: &mut GameOffscreenBuffer,
+    frame_tiles: &mut TileHashes,
+    work_queue: &WorkQueue,
+) -> Option<DirtyRects> {
+    let render_and_hash = |pixels: PixelsMut, frame_tiles: &mut TileHashes| {
+        frame_tiles.begin_frame(pixels.width(), pixels.height(), RENDER_TILE_HEIGHT);
+        let hashes = &*frame_tiles;
+        render_group.render_tiled_with(pixels, RENDER_TILE_HEIGHT, work_queue, &|index, tile| {
+            hashes.hash_band(index, tile)
+        });
+        frame_tiles.finish_frame()
+    };
+
+    if let Some(pixels) = buffer.pixels() {
+        return Some(render_and_hash(pixels, frame_tiles));
+    }
+
+    //Note(voided): the rasterizer only draws 0xAARRGGBB pixels, other formats get drawn into
+    //transient storage and converted once the whole frame is done, a pass over every pixel.
+    let mut frame = frame?;
+    let dirty = render_and_hash(frame.pixels(), frame_tiles);
+    buffer.write_pixels(frame.finish());
+    Some(dirty)
 }
 
 unsafe fn game_output_sound(buffer: &mut GameSoundBuffer, sound: &mut SineAudioSource) {
@@ -36,11 +213,11 @@ unsafe fn game_output_sound(buffer: &mut GameSoundBuffer, sound: &mut SineAudioS
     }
 }
 
-/// TODO(voided): Services that the platform layer provides to the game
+// TODO(voided): Services that the platform layer provides to the game
 
 /// TODO(voided): Services that the game provides to the platform layer
 /// It needs to take the timing, controller/keyboard input, bitmap buffer to use, sound buffer to use
-
+///
 ///  # Safety
 ///  does pointer stuff, consider refactoring to be Safety
 #[no_mangle]
@@ -52,37 +229,136 @@ pub unsafe fn game_update_and_render<'a>(
 ) {
     debug_assert!(mem::size_of::<GameState>() <= game_memory.permanent_storage_size);
 
-    // TODO(voided): currently, we can't load from the platform layer, as it is circular
-    // resolve this by moving those things into their own crate
-
     let game_state = &mut *game_memory.permanent_storage.cast::<GameState>();
     if !game_memory.is_initalized {
         game_state.tone.reset_with(255, 0.3);
+        game_state.players = Default::default();
+        game_state.buffer_width = buffer.width();
+        game_state.buffer_height = buffer.height();
+        //Note(voided): the storage starts out zeroed, which isn't a valid Option<Bitmap> to
+        //drop, so write it without dropping the old value.
+        let asset_root = &game_memory.asset_root;
+        ptr::addr_of_mut!(game_state.test_sprite)
+            .write(load_bitmap(asset_root, "data/test_sprite.bmp"));
+        let normals = game_state
+            .test_sprite
+            .as_ref()
+            .map(|sprite| dome_normal_map(sprite.width(), sprite.height()));
+        ptr::addr_of_mut!(game_state.test_sprite_normals).write(normals);
+        ptr::addr_of_mut!(game_state.debug_font).write(load_font(
+            asset_root,
+            "data/fonts/debug_16.png",
+            "data/fonts/debug_16.font",
+        ));
+        game_state.last_player_event = None;
+        ptr::addr_of_mut!(game_state.frame_tiles).write(Default::default());
+        game_state.frame_fits = check_frame_room(buffer, game_memory);
 
         game_memory.is_initalized = true;
     }
 
-    for input in inputs {
+    if inputs.buffer_resized {
+        //TODO(voided): lay out the ui for the new size once there is one
+        println!(
+            "Back buffer resized to {}x{}",
+            buffer.width(),
+            buffer.height()
+        );
+        game_state.buffer_width = buffer.width();
+        game_state.buffer_height = buffer.height();
+        game_state.frame_fits = check_frame_room(buffer, game_memory);
+    }
+
+    if let Some(event) = game_state.players.update(inputs).iter().last() {
+        game_state.last_player_event = Some(event);
+    }
+
+    //Note(voided): a player lost their controller or we're in the background,
+    //hold everything until it's sorted out.
+    if inputs.has_focus && !game_state.players.any_paused() {
         // TODO(voided): Allow samples offset here for more robust platform options
-        match input.is_analog {
-            true => {
-                game_state.tone.hz = (256.0 + 128.0 * input.stick_left.y_average) as u32;
-                game_state.x_offset += (4.0 * input.stick_left.x_average) as i32;
-            }
-            false => {
-                if input.move_right.button_is_down {
-                    game_state.x_offset += 1;
-                }
-                if input.move_left.button_is_down {
-                    game_state.x_offset -= 1;
-                }
-            }
-        }
+        let actions = ActionContext::any_device(DEFAULT_BINDINGS).evaluate(inputs);
+        game_state.tone.hz = (256.0 + 128.0 * actions.value(Action::MoveY)) as u32;
+        game_state.x_offset += (4.0 * actions.value(Action::MoveX)) as i32;
 
-        if input.action_down.button_is_down {
+        if actions.is_down(Action::Jump) {
             game_state.y_offset += 1;
         }
+        game_state.sprite_angle = (game_state.sprite_angle + SPRITE_SPIN) % TAU;
     }
     game_output_sound(sound_buffer, &mut game_state.tone);
-    render_weird_gradient(buffer, game_state.x_offset, game_state.y_offset);
+
+    //Note(voided): transient storage is scratch space that only lives for the frame
+    let mut transient_arena = MemoryArena::from_raw(
+        game_memory.transient_storage,
+        game_memory.transient_storage_size,
+    );
+    if !game_state.frame_fits {
+        return;
+    }
+    let frame_target = (buffer.format() != PixelFormat::Bgra8)
+        .then(|| {
+            let (width, height) = (buffer.width() as usize, buffer.height() as usize);
+            RenderTarget::new(&mut transient_arena, width, height)
+        })
+        .flatten();
+    // check_frame_room made sure of it when the buffer changed
+    let Some(mut render_group) = RenderGroup::new(&mut transient_arena, MAX_RENDER_COMMANDS) else {
+        return;
+    };
+
+    render_group.push(RenderCommand::WeirdGradient {
+        x_offset: game_state.x_offset,
+        y_offset: game_state.y_offset,
+    });
+    // lit from the top left if there's room for the light
+    let lights = transient_arena.push([PointLight {
+        position: v3(80.0, 40.0, 40.0),
+        color: v3(1.6, 1.4, 1.2),
+        radius: 160.0,
+    }]);
+    //Note(voided): the render group borrows the game state until the frame is drawn, the tile
+    //hashes sit out the frame so they can be updated at the end.
+    let mut frame_tiles = mem::take(&mut game_state.frame_tiles);
+    push_sprites(
+        &mut render_group,
+        game_state,
+        lights.map(|lights| &lights[..]),
+    );
+
+    if let Some(minimap) = render_minimap(&mut transient_arena, game_state) {
+        let (width, height) = (minimap.width() as f32, minimap.height() as f32);
+        let origin = v2(game_state.buffer_width as f32 - width - 8.0, 8.0);
+        render_group.set_layer(MINIMAP_LAYER);
+        render_group.push_composite(
+            minimap,
+            origin,
+            v2(width, 0.0),
+            v2(0.0, height),
+            MINIMAP_ALPHA,
+        );
+    }
+    if let Some(font) = &game_state.debug_font {
+        let mut overlay = format!("offset {} {}", game_state.x_offset, game_state.y_offset);
+        if let Some(event) = game_state.last_player_event {
+            write!(overlay, "\n{event:?}").expect("writing to a string can't fail");
+        }
+        if let Some(text) = transient_arena.push_str(&overlay) {
+            render_group.set_layer(DEBUG_OVERLAY_LAYER);
+            render_group.push_text(font, text, v2(8.0, 8.0), Alignment::Left);
+        }
+    }
+
+    render_group.sort();
+    let dirty = render_frame(
+        &render_group,
+        frame_target,
+        buffer,
+        &mut frame_tiles,
+        &game_memory.work_queue,
+    );
+    game_state.frame_tiles = frame_tiles;
+    if let Some(dirty) = dirty {
+        buffer.set_dirty(dirty);
+    }
 }
@@ -0,0 +1,147 @@
+// Lighting for sprites with normal maps. A normal map is a bitmap whose color channels hold a
+// normal per texel instead of a color: x right, y down and z out of the screen, each -1..=1
+// stored as 0..=255. It gets stretched over the sprite like the sprite's own pixels.
+// Note(voided): the light adds up in linear space and scales the sprite's linear color, the
+// rasterizer does the sampling and blending.
+
+use utility::image::Bitmap;
+use utility::math::{v3, V3};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight {
+    /// In canvas pixels, z is how high above the sprites the light is. The render group
+    /// transform doesn't apply to lights.
+    pub position: V3,
+    /// Linear, above 1 for lights that wash things out.
+    pub color: V3,
+    /// No light reaches further than this, it fades out on the way.
+    pub radius: f32,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Lighting<'a> {
+    /// Reaches every pixel, whichever way it faces.
+    pub ambient: V3,
+    pub lights: &'a [PointLight],
+}
+
+impl Lighting<'_> {
+    /// How much light reaches a point facing along `normal`, per channel.
+    pub fn light_at(&self, position: V3, normal: V3) -> V3 {
+        let mut total = self.ambient;
+        for light in self.lights {
+            let to_light = light.position - position;
+            let distance = to_light.length();
+            if distance >= light.radius {
+                continue;
+            }
+            let falloff = 1.0 - distance / light.radius;
+            let diffuse = normal.dot(to_light.normalize_or(normal)).max(0.0);
+            total += light.color * (diffuse * falloff * falloff);
+        }
+        total
+    }
+}
+
+/// A normal map texel, opaque so it survives being saved as an image.
+pub fn encode_normal(normal: V3) -> u32 {
+    let channel = |value: f32| ((value.clamp(-1.0, 1.0) * 0.5 + 0.5) * 255.0 + 0.5) as u32;
+    0xFF000000 | channel(normal.x) << 16 | channel(normal.y) << 8 | channel(normal.z)
+}
+
+/// Not normalized, the 8 bit channels lose a little length.
+pub fn decode_normal(texel: u32) -> V3 {
+    let channel = |shift: u32| ((texel >> shift) & 0xFF) as f32 / 255.0 * 2.0 - 1.0;
+    v3(channel(16), channel(8), channel(0))
+}
+
+/// Normals of a dome that fills the bitmap, round sprites look like balls with it. Outside of
+/// the dome the normals lie flat along the rim.
+pub fn dome_normal_map(width: usize, height: usize) -> Bitmap {
+    let pixels = (0..width * height)
+        .map(|index| {
+            let x = ((index % width) as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+            let y = ((index / width) as f32 + 0.5) / height as f32 * 2.0 - 1.0;
+            let rim_squared = x * x + y * y;
+            let normal = if rim_squared < 1.0 {
+                v3(x, y, (1.0 - rim_squared).sqrt())
+            } else {
+                v3(x, y, 0.0).normalize_or(v3(0.0, 0.0, 1.0))
+            };
+            encode_normal(normal)
+        })
+        .collect();
+    Bitmap::new(width, height, pixels).expect("pixels fill the bitmap")
+}
+
+#[cfg(test)]
+mod lighting_tests {
+    use utility::math::{v3, V3};
+
+    use crate::lighting::{decode_normal, dome_normal_map, encode_normal, Lighting, PointLight};
+
+    const UP: V3 = v3(0.0, 0.0, 1.0);
+
+    fn close(a: V3, b: V3) -> bool {
+        (a - b).length() < 0.01
+    }
+
+    #[test]
+    fn normals_round_trip() {
+        for normal in [
+            UP,
+            v3(1.0, 0.0, 0.0),
+            v3(-0.6, 0.0, 0.8),
+            v3(0.0, -1.0, 0.0),
+        ] {
+            assert!(close(decode_normal(encode_normal(normal)), normal));
+        }
+        assert_eq!(encode_normal(UP), 0xFF8080FF);
+    }
+
+    #[test]
+    fn dome_faces_outwards() {
+        let map = dome_normal_map(16, 16);
+        let normal = |x, y| decode_normal(map.pixel(x, y).unwrap());
+        assert!(normal(8, 8).z > 0.95);
+        assert!(normal(0, 8).x < -0.9);
+        assert!(normal(15, 8).x > 0.9);
+        assert!(normal(8, 0).y < -0.9);
+        // the corners are outside of the dome
+        assert!(close(normal(0, 0), v3(-0.707, -0.707, 0.0)));
+    }
+
+    #[test]
+    fn lights_add_up() {
+        let lights = [
+            PointLight {
+                position: v3(0.0, 0.0, 10.0),
+                color: v3(1.0, 0.5, 0.0),
+                radius: 20.0,
+            },
+            PointLight {
+                position: v3(100.0, 0.0, 10.0),
+                color: v3(0.0, 0.0, 1.0),
+                radius: 20.0,
+            },
+        ];
+        let lighting = Lighting {
+            ambient: v3(0.1, 0.1, 0.1),
+            lights: &lights,
+        };
+
+        // straight under the first light, half way out of its radius
+        let lit = lighting.light_at(V3::default(), UP);
+        assert!(close(lit, v3(0.35, 0.225, 0.1)));
+        // facing away from it
+        assert_eq!(
+            lighting.light_at(V3::default(), -1.0 * UP),
+            lighting.ambient
+        );
+        // at an angle, and out of reach of the other light
+        let side = lighting.light_at(v3(10.0, 0.0, 0.0), UP);
+        let expected = 0.1 + (10.0 / 200.0f32.sqrt()) * (1.0 - 200.0f32.sqrt() / 20.0).powi(2);
+        assert!((side.x - expected).abs() < 1e-5);
+        assert_eq!(side.z, 0.1);
+    }
+}
@@ -0,0 +1,142 @@
+// Bump allocation out of the game memory the platform hands us.
+
+use std::ffi::c_void;
+use std::mem::{self, MaybeUninit};
+
+/// Hands out blocks of a fixed piece of memory front to back, everything gets freed at once when
+/// the arena goes away.
+/// Note(voided): nothing pushed into an arena gets dropped, so only put plain data in there.
+pub struct MemoryArena<'a> {
+    memory: &'a mut [MaybeUninit<u8>],
+    used: usize,
+}
+
+impl<'a> MemoryArena<'a> {
+    pub fn new(memory: &'a mut [MaybeUninit<u8>]) -> Self {
+        Self { memory, used: 0 }
+    }
+
+    /// # Safety
+    /// `memory` has to be valid for reads and writes of `size` bytes for `'a`, and nothing else
+    /// may access it during that time.
+    pub unsafe fn from_raw(memory: *mut c_void, size: usize) -> Self {
+        Self::new(std::slice::from_raw_parts_mut(
+            memory.cast::<MaybeUninit<u8>>(),
+            size,
+        ))
+    }
+
+    /// Bytes handed out so far, including the padding for alignment.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.memory.len()
+    }
+
+    fn push_bytes(&mut self, size: usize, align: usize) -> Option<&'a mut [MaybeUninit<u8>]> {
+        let padding = self.memory.as_ptr().align_offset(align);
+        if padding.checked_add(size)? > self.memory.len() {
+            return None;
+        }
+
+        let memory = mem::take(&mut self.memory);
+        let (block, rest) = memory[padding..].split_at_mut(size);
+        self.memory = rest;
+        self.used += padding + size;
+        Some(block)
+    }
+
+    /// `None` if the arena is full.
+    pub fn push<T>(&mut self, value: T) -> Option<&'a mut T> {
+        let block = self.push_bytes(mem::size_of::<T>(), mem::align_of::<T>())?;
+        let value_ptr = block.as_mut_ptr().cast::<T>();
+        // Safety: the block is aligned, big enough for a T and only handed out once
+        unsafe {
+            value_ptr.write(value);
+            Some(&mut *value_ptr)
+        }
+    }
+
+    /// Room for `count` values, which start out uninitialized.
+    pub fn push_array<T>(&mut self, count: usize) -> Option<&'a mut [MaybeUninit<T>]> {
+        let size = mem::size_of::<T>().checked_mul(count)?;
+        let block = self.push_bytes(size, mem::align_of::<T>())?;
+        // Safety: as in push, and MaybeUninit doesn't need initializing
+        Some(unsafe {
+            std::slice::from_raw_parts_mut(block.as_mut_ptr().cast::<MaybeUninit<T>>(), count)
+        })
+    }
+
+    /// Like [`Self::push_array`], with every value set to `value`.
+    pub fn push_filled<T: Copy>(&mut self, count: usize, value: T) -> Option<&'a mut [T]> {
+        let array = self.push_array(count)?;
+        for slot in array.iter_mut() {
+            slot.write(value);
+        }
+        // Safety: every value was just initialized, and MaybeUninit<T> has the layout of T
+        Some(unsafe { std::slice::from_raw_parts_mut(array.as_mut_ptr().cast::<T>(), count) })
+    }
+
+    pub fn push_str(&mut self, text: &str) -> Option<&'a str> {
+        let block = self.push_bytes(text.len(), 1)?;
+        // Safety: u8 and MaybeUninit<u8> have the same layout
+        let bytes = unsafe {
+            let bytes = block.as_mut_ptr().cast::<u8>();
+            bytes.copy_from_nonoverlapping(text.as_ptr(), text.len());
+            std::slice::from_raw_parts(bytes, text.len())
+        };
+        Some(std::str::from_utf8(bytes).expect("copied from a str"))
+    }
+
+    /// An arena for `size` bytes out of this one, for a part of the game that wants its own.
+    pub fn sub_arena(&mut self, size: usize) -> Option<MemoryArena<'a>> {
+        self.push_bytes(size, mem::align_of::<u64>())
+            .map(MemoryArena::new)
+    }
+}
+
+#[cfg(test)]
+mod memory_tests {
+    use std::mem::MaybeUninit;
+
+    use crate::memory::MemoryArena;
+
+    #[test]
+    fn pushes_are_aligned_and_disjoint() {
+        let mut memory = [MaybeUninit::new(0u64); 8];
+        let mut storage = unsafe { MemoryArena::from_raw(memory.as_mut_ptr().cast(), 64) };
+
+        let byte = storage.push(7u8).unwrap();
+        let number = storage.push(0x0102030405060708u64).unwrap();
+        assert_eq!(number as *const u64 as usize % 8, 0);
+        assert_eq!(storage.used(), 16);
+        *byte = 9;
+        assert_eq!((*byte, *number), (9, 0x0102030405060708));
+
+        let text = storage.push_str("hello").unwrap();
+        let array = storage.push_array::<u32>(4).unwrap();
+        assert_eq!(array.len(), 4);
+        assert_eq!(text, "hello");
+        assert_eq!(storage.used(), 40);
+        assert_eq!(storage.remaining(), 24);
+    }
+
+    #[test]
+    fn full_arena() {
+        let mut memory = [MaybeUninit::new(0u64); 2];
+        let mut storage = unsafe { MemoryArena::from_raw(memory.as_mut_ptr().cast(), 16) };
+
+        let mut sub_arena = storage.sub_arena(8).unwrap();
+        assert!(sub_arena.push([0u8; 9]).is_none());
+        assert!(sub_arena.push(1u64).is_some());
+        assert!(sub_arena.push(1u8).is_none());
+
+        assert!(storage.push_array::<u32>(usize::MAX).is_none());
+        assert!(storage.push_array::<u16>(5).is_none());
+        assert_eq!(storage.push_filled(2, 3u16).unwrap(), [3, 3]);
+        assert!(storage.push_array::<u16>(2).is_some());
+        assert_eq!(storage.remaining(), 0);
+    }
+}
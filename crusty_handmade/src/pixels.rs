@@ -0,0 +1,249 @@
+// Safe access to 32 bit pixels, so rendering code doesn't have to walk raw pointers.
+
+/// Mutable view of a rectangle of pixels. Rows are `stride` pixels apart, the view may be a
+/// part of a bigger buffer.
+#[derive(Debug)]
+pub struct PixelsMut<'a> {
+    // Note(voided): starts at the top left pixel of the view and ends after the last pixel of the
+    // last row, so the padding past the last row is never part of it.
+    pixels: &'a mut [u32],
+    width: usize,
+    height: usize,
+    stride: usize,
+}
+
+impl<'a> PixelsMut<'a> {
+    /// `None` if the slice is too small for the given dimensions.
+    pub fn new(pixels: &'a mut [u32], width: usize, height: usize, stride: usize) -> Option<Self> {
+        if width > stride {
+            return None;
+        }
+
+        let len = Self::required_len(width, height, stride);
+        if pixels.len() < len {
+            return None;
+        }
+
+        Some(Self {
+            pixels: &mut pixels[0..len],
+            width,
+            height,
+            stride,
+        })
+    }
+
+    fn required_len(width: usize, height: usize, stride: usize) -> usize {
+        if width == 0 || height == 0 {
+            0
+        } else {
+            (height - 1) * stride + width
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    pub fn row(&self, y: usize) -> Option<&[u32]> {
+        if y >= self.height {
+            return None;
+        }
+        let start = y * self.stride;
+        Some(&self.pixels[start..start + self.width])
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> Option<&mut [u32]> {
+        if y >= self.height {
+            return None;
+        }
+        let start = y * self.stride;
+        Some(&mut self.pixels[start..start + self.width])
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[u32]> + '_ {
+        let width = self.width;
+        self.pixels
+            .chunks(self.stride.max(1))
+            .take(self.height)
+            .map(move |row| &row[0..width])
+    }
+
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [u32]> + '_ {
+        let width = self.width;
+        self.pixels
+            .chunks_mut(self.stride.max(1))
+            .take(self.height)
+            .map(move |row| &mut row[0..width])
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Option<&u32> {
+        if x >= self.width {
+            return None;
+        }
+        self.row(y).map(|row| &row[x])
+    }
+
+    pub fn pixel_mut(&mut self, x: usize, y: usize) -> Option<&mut u32> {
+        if x >= self.width {
+            return None;
+        }
+        self.row_mut(y).map(|row| &mut row[x])
+    }
+
+    /// Sub rectangle of this view, `None` if it does not fit.
+    pub fn view_mut(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Option<PixelsMut<'_>> {
+        if x.checked_add(width)? > self.width || y.checked_add(height)? > self.height {
+            return None;
+        }
+
+        let len = Self::required_len(width, height, self.stride);
+        let start = if len == 0 { 0 } else { y * self.stride + x };
+        Some(PixelsMut {
+            pixels: &mut self.pixels[start..start + len],
+            width,
+            height,
+            stride: self.stride,
+        })
+    }
+
+    /// Like [`Self::view_mut`], but cuts the rectangle down to what's inside this view.
+    pub fn clipped_view_mut(&mut self, x: i32, y: i32, width: i32, height: i32) -> PixelsMut<'_> {
+        let min_x = x.clamp(0, self.width as i32) as usize;
+        let min_y = y.clamp(0, self.height as i32) as usize;
+        let max_x = x.saturating_add(width).clamp(0, self.width as i32) as usize;
+        let max_y = y.saturating_add(height).clamp(0, self.height as i32) as usize;
+
+        self.view_mut(
+            min_x,
+            min_y,
+            max_x.saturating_sub(min_x),
+            max_y.saturating_sub(min_y),
+        )
+        .expect("clipped rectangle is always inside")
+    }
+
+    /// Splits the view into bands of `rows` rows, top to bottom, the last one may be shorter.
+    /// Note(voided): the bands don't overlap, so they can be handed to different threads.
+    pub fn into_bands(self, rows: usize) -> impl Iterator<Item = PixelsMut<'a>> {
+        let rows = rows.max(1);
+        let (width, height, stride) = (self.width, self.height, self.stride);
+        self.pixels
+            .chunks_mut((rows * stride).max(1))
+            .enumerate()
+            .map(move |(index, band)| {
+                let band_height = rows.min(height - index * rows);
+                PixelsMut::new(band, width, band_height, stride).expect("band is inside the view")
+            })
+    }
+
+    pub fn fill(&mut self, color: u32) {
+        for row in self.rows_mut() {
+            row.fill(color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod pixels_tests {
+    use crate::pixels::PixelsMut;
+
+    #[test]
+    fn too_small_slice() {
+        let mut memory = [0u32; 11];
+        assert!(PixelsMut::new(&mut memory, 4, 3, 4).is_none());
+        assert!(PixelsMut::new(&mut memory, 5, 1, 4).is_none());
+        // the last row doesn't need the padding
+        assert!(PixelsMut::new(&mut memory, 3, 3, 4).is_some());
+    }
+
+    #[test]
+    fn rows_skip_padding() {
+        let mut memory = [0u32; 12];
+        let mut pixels = PixelsMut::new(&mut memory, 3, 3, 4).unwrap();
+        for (y, row) in pixels.rows_mut().enumerate() {
+            row.fill(y as u32 + 1);
+        }
+        assert_eq!(memory, [1, 1, 1, 0, 2, 2, 2, 0, 3, 3, 3, 0]);
+    }
+
+    #[test]
+    fn pixel_bounds() {
+        let mut memory = [0u32; 6];
+        let mut pixels = PixelsMut::new(&mut memory, 3, 2, 3).unwrap();
+        *pixels.pixel_mut(2, 1).unwrap() = 7;
+        assert!(pixels.pixel_mut(3, 0).is_none());
+        assert!(pixels.pixel_mut(0, 2).is_none());
+        assert_eq!(pixels.pixel(2, 1), Some(&7));
+        assert_eq!(memory, [0, 0, 0, 0, 0, 7]);
+    }
+
+    #[test]
+    fn sub_view() {
+        let mut memory = [0u32; 16];
+        let mut pixels = PixelsMut::new(&mut memory, 4, 4, 4).unwrap();
+        {
+            let mut view = pixels.view_mut(1, 1, 2, 2).unwrap();
+            assert_eq!((view.width(), view.height()), (2, 2));
+            view.fill(5);
+            assert!(view.pixel_mut(2, 0).is_none());
+            let mut nested = view.view_mut(1, 1, 1, 1).unwrap();
+            nested.fill(9);
+        }
+        assert!(pixels.view_mut(3, 3, 2, 1).is_none());
+        assert!(pixels.view_mut(usize::MAX, 0, 2, 1).is_none());
+        assert_eq!(memory, [0, 0, 0, 0, 0, 5, 5, 0, 0, 5, 9, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn clipped_view() {
+        let mut memory = [0u32; 16];
+        let mut pixels = PixelsMut::new(&mut memory, 4, 4, 4).unwrap();
+        let view = pixels.clipped_view_mut(-2, 3, 4, 10);
+        assert_eq!((view.width(), view.height()), (2, 1));
+
+        let view = pixels.clipped_view_mut(10, 10, 4, 4);
+        assert_eq!((view.width(), view.height()), (0, 0));
+    }
+
+    #[test]
+    fn bands() {
+        let mut memory = [0u32; 20];
+        let pixels = PixelsMut::new(&mut memory, 3, 5, 4).unwrap();
+        let mut heights = vec![];
+        for (index, mut band) in pixels.into_bands(2).enumerate() {
+            heights.push(band.height());
+            band.fill(index as u32 + 1);
+        }
+        assert_eq!(heights, [2, 2, 1]);
+        assert_eq!(
+            memory,
+            [1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2, 0, 3, 3, 3, 0]
+        );
+
+        let mut memory: [u32; 0] = [];
+        let pixels = PixelsMut::new(&mut memory, 0, 0, 0).unwrap();
+        assert_eq!(pixels.into_bands(4).count(), 0);
+    }
+
+    #[test]
+    fn empty_view() {
+        let mut memory: [u32; 0] = [];
+        let mut pixels = PixelsMut::new(&mut memory, 0, 0, 0).unwrap();
+        assert_eq!(pixels.rows_mut().count(), 0);
+        assert!(pixels.pixel_mut(0, 0).is_none());
+    }
+}
@@ -0,0 +1,332 @@
+// Which controller belongs to which player.
+// Note(voided): the platform only hands us slots, so the slot index is the identity of a device.
+// XInput keeps a pad on the same user index when it gets plugged back in, the keyboard is always
+// slot 0.
+
+use crate::actions::{ActionContext, Binding, ControllerSlots};
+use crate::types::GameInput;
+
+pub const MAX_PLAYERS: usize = 4;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerSlot {
+    #[default]
+    Open,
+    Active {
+        controller: usize,
+    },
+    /// The controller went away, the player is paused until it comes back or another controller
+    /// takes over.
+    Disconnected {
+        controller: usize,
+    },
+}
+
+impl PlayerSlot {
+    fn controller(self) -> Option<usize> {
+        match self {
+            PlayerSlot::Open => None,
+            PlayerSlot::Active { controller } | PlayerSlot::Disconnected { controller } => {
+                Some(controller)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerEvent {
+    Joined {
+        player: usize,
+        controller: usize,
+    },
+    Disconnected {
+        player: usize,
+        controller: usize,
+    },
+    Reconnected {
+        player: usize,
+        controller: usize,
+    },
+    Reassigned {
+        player: usize,
+        from: usize,
+        to: usize,
+    },
+    Left {
+        player: usize,
+        controller: usize,
+    },
+}
+
+/// Note(voided): Every controller causes at most one event per update.
+const MAX_EVENTS: usize = 5;
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct PlayerEvents {
+    events: [Option<PlayerEvent>; MAX_EVENTS],
+    count: usize,
+}
+
+impl PlayerEvents {
+    fn push(&mut self, event: PlayerEvent) {
+        debug_assert!(self.count < MAX_EVENTS);
+        self.events[self.count] = Some(event);
+        self.count += 1;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = PlayerEvent> + '_ {
+        self.events[0..self.count].iter().flatten().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct PlayerAssignments {
+    players: [PlayerSlot; MAX_PLAYERS],
+}
+
+impl PlayerAssignments {
+    /// Call once per frame.
+    /// - pressing start on an unclaimed controller joins, or takes over a disconnected player
+    /// - a player whose controller disconnects gets paused
+    /// - a paused player resumes when its controller comes back
+    pub fn update(&mut self, inputs: &GameInput) -> PlayerEvents {
+        let mut events = PlayerEvents::default();
+
+        for (player, slot) in self.players.iter_mut().enumerate() {
+            match *slot {
+                PlayerSlot::Active { controller } if !inputs[controller].is_connected => {
+                    *slot = PlayerSlot::Disconnected { controller };
+                    events.push(PlayerEvent::Disconnected { player, controller });
+                }
+                PlayerSlot::Disconnected { controller } if inputs[controller].is_connected => {
+                    *slot = PlayerSlot::Active { controller };
+                    events.push(PlayerEvent::Reconnected { player, controller });
+                }
+                _ => {}
+            }
+        }
+
+        for (controller, input) in inputs.controllers.iter().enumerate() {
+            if !input.is_connected || !input.start.was_pressed() || self.owns(controller) {
+                continue;
+            }
+
+            let disconnected = self
+                .players
+                .iter()
+                .position(|slot| matches!(slot, PlayerSlot::Disconnected { .. }));
+
+            if let Some(player) = disconnected {
+                let from = self.players[player].controller().unwrap_or(controller);
+                self.players[player] = PlayerSlot::Active { controller };
+                events.push(PlayerEvent::Reassigned {
+                    player,
+                    from,
+                    to: controller,
+                });
+            } else if let Some(player) = self.players.iter().position(|s| *s == PlayerSlot::Open) {
+                self.players[player] = PlayerSlot::Active { controller };
+                events.push(PlayerEvent::Joined { player, controller });
+            }
+        }
+
+        events
+    }
+
+    pub fn leave(&mut self, player: usize) -> Option<PlayerEvent> {
+        let controller = self.players[player].controller()?;
+        self.players[player] = PlayerSlot::Open;
+        Some(PlayerEvent::Left { player, controller })
+    }
+
+    pub fn slot(&self, player: usize) -> PlayerSlot {
+        self.players[player]
+    }
+
+    pub fn player_for_controller(&self, controller: usize) -> Option<usize> {
+        self.players
+            .iter()
+            .position(|slot| slot.controller() == Some(controller))
+    }
+
+    pub fn is_paused(&self, player: usize) -> bool {
+        matches!(self.players[player], PlayerSlot::Disconnected { .. })
+    }
+
+    pub fn any_paused(&self) -> bool {
+        (0..MAX_PLAYERS).any(|player| self.is_paused(player))
+    }
+
+    /// Action context for the controller currently driving the player. Empty while the player
+    /// is paused or the slot is open.
+    pub fn context<'a>(&self, player: usize, bindings: &'a [Binding]) -> ActionContext<'a> {
+        let slots = match self.players[player] {
+            PlayerSlot::Active { controller } => ControllerSlots::single(controller),
+            _ => ControllerSlots::NONE,
+        };
+        ActionContext { slots, bindings }
+    }
+
+    fn owns(&self, controller: usize) -> bool {
+        self.player_for_controller(controller).is_some()
+    }
+}
+
+#[cfg(test)]
+mod players_tests {
+    use crate::actions::{Action, DEFAULT_BINDINGS};
+    use crate::players::{PlayerAssignments, PlayerEvent, PlayerSlot, MAX_PLAYERS};
+    use crate::types::GameInput;
+
+    fn press_start(inputs: &mut GameInput, controller: usize) {
+        inputs[controller].is_connected = true;
+        inputs[controller].start.button_is_down = true;
+        inputs[controller].start.half_transitions = 1;
+    }
+
+    fn events(players: &mut PlayerAssignments, inputs: &GameInput) -> Vec<PlayerEvent> {
+        players.update(inputs).iter().collect()
+    }
+
+    #[test]
+    fn start_joins() {
+        let mut players = PlayerAssignments::default();
+        let mut inputs = GameInput::default();
+        press_start(&mut inputs, 2);
+
+        assert_eq!(
+            events(&mut players, &inputs),
+            [PlayerEvent::Joined {
+                player: 0,
+                controller: 2
+            }]
+        );
+        assert_eq!(players.slot(0), PlayerSlot::Active { controller: 2 });
+
+        // holding start does not join again
+        inputs[2].start.half_transitions = 0;
+        assert!(players.update(&inputs).is_empty());
+    }
+
+    #[test]
+    fn second_controller_gets_next_player() {
+        let mut players = PlayerAssignments::default();
+        let mut inputs = GameInput::default();
+        press_start(&mut inputs, 0);
+        press_start(&mut inputs, 3);
+
+        events(&mut players, &inputs);
+        assert_eq!(players.player_for_controller(0), Some(0));
+        assert_eq!(players.player_for_controller(3), Some(1));
+    }
+
+    #[test]
+    fn disconnect_pauses_and_reconnect_resumes() {
+        let mut players = PlayerAssignments::default();
+        let mut inputs = GameInput::default();
+        press_start(&mut inputs, 1);
+        events(&mut players, &inputs);
+
+        inputs[1] = Default::default();
+        assert_eq!(
+            events(&mut players, &inputs),
+            [PlayerEvent::Disconnected {
+                player: 0,
+                controller: 1
+            }]
+        );
+        assert!(players.is_paused(0));
+        assert!(players.any_paused());
+
+        inputs[1].is_connected = true;
+        assert_eq!(
+            events(&mut players, &inputs),
+            [PlayerEvent::Reconnected {
+                player: 0,
+                controller: 1
+            }]
+        );
+        assert!(!players.is_paused(0));
+    }
+
+    #[test]
+    fn other_controller_takes_over_disconnected_player() {
+        let mut players = PlayerAssignments::default();
+        let mut inputs = GameInput::default();
+        press_start(&mut inputs, 1);
+        events(&mut players, &inputs);
+
+        inputs[1] = Default::default();
+        events(&mut players, &inputs);
+
+        press_start(&mut inputs, 4);
+        assert_eq!(
+            events(&mut players, &inputs),
+            [PlayerEvent::Reassigned {
+                player: 0,
+                from: 1,
+                to: 4
+            }]
+        );
+        assert_eq!(players.slot(0), PlayerSlot::Active { controller: 4 });
+
+        // the old controller is free again and joins as a new player
+        press_start(&mut inputs, 1);
+        inputs[4].start.half_transitions = 0;
+        assert_eq!(
+            events(&mut players, &inputs),
+            [PlayerEvent::Joined {
+                player: 1,
+                controller: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn full_lobby_ignores_start() {
+        let mut players = PlayerAssignments::default();
+        let mut inputs = GameInput::default();
+        for controller in 0..inputs.controllers.len() {
+            press_start(&mut inputs, controller);
+        }
+        assert_eq!(events(&mut players, &inputs).len(), MAX_PLAYERS);
+        assert_eq!(players.player_for_controller(4), None);
+    }
+
+    #[test]
+    fn leave_frees_slot() {
+        let mut players = PlayerAssignments::default();
+        let mut inputs = GameInput::default();
+        press_start(&mut inputs, 2);
+        events(&mut players, &inputs);
+
+        assert_eq!(
+            players.leave(0),
+            Some(PlayerEvent::Left {
+                player: 0,
+                controller: 2
+            })
+        );
+        assert_eq!(players.slot(0), PlayerSlot::Open);
+        assert_eq!(players.leave(0), None);
+    }
+
+    #[test]
+    fn player_context_follows_controller() {
+        let mut players = PlayerAssignments::default();
+        let mut inputs = GameInput::default();
+        press_start(&mut inputs, 3);
+        events(&mut players, &inputs);
+
+        inputs[3].action_down.button_is_down = true;
+        let frame = players.context(0, DEFAULT_BINDINGS).evaluate(&inputs);
+        assert!(frame.is_down(Action::Jump));
+
+        let frame = players.context(1, DEFAULT_BINDINGS).evaluate(&inputs);
+        assert!(!frame.is_down(Action::Jump));
+    }
+}
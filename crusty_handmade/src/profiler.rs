@@ -0,0 +1,159 @@
+// Counts how often and for how many cycles blocks of code run. `timed_block!("name")` at the top
+// of a block times everything until the end of it, the counters add up until they get reset.
+// Note(voided): the counters are atomics, tiles on other threads hit the same blocks.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Cycles on x86_64, nanoseconds everywhere else.
+pub fn cycle_count() -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    // Safety: every x86_64 cpu has rdtsc
+    unsafe {
+        std::arch::x86_64::_rdtsc()
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+        START
+            .get_or_init(std::time::Instant::now)
+            .elapsed()
+            .as_nanos() as u64
+    }
+}
+
+/// One per `timed_block!`, lives in a static next to it.
+pub struct BlockCounter {
+    name: &'static str,
+    hits: AtomicU64,
+    cycles: AtomicU64,
+    registered: AtomicBool,
+}
+
+/// Every counter that has run at least once.
+static COUNTERS: Mutex<Vec<&'static BlockCounter>> = Mutex::new(vec![]);
+
+impl BlockCounter {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            hits: AtomicU64::new(0),
+            cycles: AtomicU64::new(0),
+            registered: AtomicBool::new(false),
+        }
+    }
+}
+
+/// Adds the time since it started to its counter when it's dropped.
+pub struct TimedBlock {
+    counter: &'static BlockCounter,
+    start: u64,
+}
+
+impl TimedBlock {
+    pub fn start(counter: &'static BlockCounter) -> Self {
+        if !counter.registered.swap(true, Ordering::Relaxed) {
+            COUNTERS
+                .lock()
+                .expect("no panics while locked")
+                .push(counter);
+        }
+        Self {
+            counter,
+            start: cycle_count(),
+        }
+    }
+}
+
+impl Drop for TimedBlock {
+    fn drop(&mut self) {
+        let cycles = cycle_count().wrapping_sub(self.start);
+        self.counter.hits.fetch_add(1, Ordering::Relaxed);
+        self.counter.cycles.fetch_add(cycles, Ordering::Relaxed);
+    }
+}
+
+/// Times the rest of the enclosing block.
+#[macro_export]
+macro_rules! timed_block {
+    ($name:expr) => {
+        let _timed_block = {
+            static COUNTER: $crate::profiler::BlockCounter =
+                $crate::profiler::BlockCounter::new($name);
+            $crate::profiler::TimedBlock::start(&COUNTER)
+        };
+    };
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockStats {
+    pub name: &'static str,
+    pub hits: u64,
+    pub cycles: u64,
+}
+
+impl BlockStats {
+    pub fn cycles_per_hit(&self) -> u64 {
+        self.cycles / self.hits.max(1)
+    }
+}
+
+/// What the blocks added up to since the last reset, by name. Blocks with the same name are
+/// added together.
+pub fn block_stats() -> Vec<BlockStats> {
+    let mut stats: Vec<BlockStats> = vec![];
+    for counter in COUNTERS.lock().expect("no panics while locked").iter() {
+        let hits = counter.hits.load(Ordering::Relaxed);
+        let cycles = counter.cycles.load(Ordering::Relaxed);
+        match stats.iter_mut().find(|stats| stats.name == counter.name) {
+            Some(stats) => {
+                stats.hits += hits;
+                stats.cycles += cycles;
+            }
+            None => stats.push(BlockStats {
+                name: counter.name,
+                hits,
+                cycles,
+            }),
+        }
+    }
+    stats.sort_unstable_by_key(|stats| stats.name);
+    stats
+}
+
+pub fn reset_block_stats() {
+    for counter in COUNTERS.lock().expect("no panics while locked").iter() {
+        counter.hits.store(0, Ordering::Relaxed);
+        counter.cycles.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod profiler_tests {
+    use crate::profiler::block_stats;
+
+    fn profiled(iterations: u32) -> u32 {
+        timed_block!("profiler_tests::profiled");
+        (0..iterations).map(|i| i.wrapping_mul(i)).sum()
+    }
+
+    #[test]
+    fn blocks_count_hits() {
+        let find = || {
+            block_stats()
+                .into_iter()
+                .find(|stats| stats.name == "profiler_tests::profiled")
+        };
+        assert!(find().is_none());
+
+        profiled(10);
+        {
+            timed_block!("profiler_tests::profiled");
+            profiled(1000);
+        }
+        let stats = find().unwrap();
+        assert_eq!(stats.hits, 3);
+        assert!(stats.cycles > 0);
+        assert!(stats.cycles_per_hit() <= stats.cycles);
+    }
+}
@@ -0,0 +1,1068 @@
+// Software rasterizer for the basic shapes.
+// Note(voided): Pixel (x, y) covers [x, x + 1) x [y, y + 1) and counts as inside a shape if its
+// center is. Shared edges use a top-left rule, so neighbouring shapes never draw a pixel twice.
+// Every pixel gets computed from the shape alone, never from its neighbours, so it doesn't matter
+// how the target gets clipped or split up, the result is the same.
+
+use utility::color::{pack_srgb8, unpack_srgb8, SrgbTables};
+use utility::image::{Bitmap, BitmapView};
+use utility::math::{lerp, v2, v3, v4, Rect2i, V2, V3, V4};
+
+use crate::lighting::{decode_normal, Lighting};
+use crate::pixels::PixelsMut;
+use crate::simd::{blend_pixel, blend_span, fill_span};
+use crate::timed_block;
+
+/// Pixels to draw into, plus where they are in the coordinates shapes get drawn in.
+/// A canvas can be a part of a bigger image, everything outside of it gets clipped.
+pub struct Canvas<'a> {
+    pixels: PixelsMut<'a>,
+    origin_x: i32,
+    origin_y: i32,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(pixels: PixelsMut<'a>) -> Self {
+        Self::with_origin(pixels, 0, 0)
+    }
+
+    /// `origin_x` and `origin_y` are the draw coordinates of the top left pixel.
+    pub fn with_origin(pixels: PixelsMut<'a>, origin_x: i32, origin_y: i32) -> Self {
+        Self {
+            pixels,
+            origin_x,
+            origin_y,
+        }
+    }
+
+    pub fn bounds(&self) -> Rect2i {
+        Rect2i::with_size(
+            self.origin_x,
+            self.origin_y,
+            self.pixels.width() as i32,
+            self.pixels.height() as i32,
+        )
+    }
+
+    pub fn pixels(&mut self) -> &mut PixelsMut<'a> {
+        &mut self.pixels
+    }
+
+    /// Pixels `min_x..max_x` of row `y`, which need to be inside of the bounds.
+    fn span_mut(&mut self, y: i32, min_x: i32, max_x: i32) -> &mut [u32] {
+        let row = self
+            .pixels
+            .row_mut((y - self.origin_y) as usize)
+            .expect("span has to be clipped");
+        &mut row[(min_x - self.origin_x) as usize..(max_x - self.origin_x) as usize]
+    }
+
+    fn put(&mut self, x: i32, y: i32, color: u32) {
+        if self.bounds().contains(x, y) {
+            self.span_mut(y, x, x + 1)[0] = color;
+        }
+    }
+
+    /// Nothing left of `rect` comes back as the default rectangle, so looping over its rows
+    /// doesn't find spans that end before they start.
+    fn clip(&self, rect: Rect2i) -> Rect2i {
+        let rect = rect.intersect(&self.bounds());
+        if rect.is_empty() {
+            return Rect2i::default();
+        }
+        rect
+    }
+}
+
+/// Linear 0..=1 color to the sRGB encoded 0xAARRGGBB layout of the back buffer. Colors handed to
+/// the draw functions are linear, so gradients get interpolated before they're encoded.
+pub fn pack_color(color: V4) -> u32 {
+    pack_srgb8(color)
+}
+
+pub fn unpack_color(color: u32) -> V4 {
+    unpack_srgb8(color)
+}
+
+/// First pixel whose center is at or after `value`.
+fn round_to_pixel(value: f32) -> i32 {
+    (value - 0.5).ceil() as i32
+}
+
+/// First and one past the last pixel whose center is in `min..max`.
+fn pixel_span(min: f32, max: f32) -> (i32, i32) {
+    (round_to_pixel(min), round_to_pixel(max))
+}
+
+pub fn clear(canvas: &mut Canvas, color: V4) {
+    let color = pack_color(color);
+    for row in canvas.pixels.rows_mut() {
+        fill_span(row, color);
+    }
+}
+
+pub fn draw_rectangle(canvas: &mut Canvas, min: V2, max: V2, color: V4) {
+    let (min_x, max_x) = pixel_span(min.x, max.x);
+    let (min_y, max_y) = pixel_span(min.y, max.y);
+    let rect = canvas.clip(Rect2i::new(min_x, min_y, max_x, max_y));
+    if rect.is_empty() {
+        return;
+    }
+
+    let color = pack_color(color);
+    for y in rect.min_y..rect.max_y {
+        fill_span(canvas.span_mut(y, rect.min_x, rect.max_x), color);
+    }
+}
+
+/// The outline grows inwards from the rectangle's edges.
+pub fn draw_rectangle_outline(canvas: &mut Canvas, min: V2, max: V2, thickness: f32, color: V4) {
+    let thickness = thickness
+        .min((max.x - min.x) / 2.0)
+        .min((max.y - min.y) / 2.0);
+    let inner_min = v2(min.x + thickness, min.y + thickness);
+    let inner_max = v2(max.x - thickness, max.y - thickness);
+
+    draw_rectangle(canvas, min, v2(max.x, inner_min.y), color);
+    draw_rectangle(canvas, v2(min.x, inner_max.y), max, color);
+    draw_rectangle(
+        canvas,
+        v2(min.x, inner_min.y),
+        v2(inner_min.x, inner_max.y),
+        color,
+    );
+    draw_rectangle(
+        canvas,
+        v2(inner_max.x, inner_min.y),
+        v2(max.x, inner_max.y),
+        color,
+    );
+}
+
+/// One pixel wide line, including the pixels both end points are in.
+pub fn draw_line(canvas: &mut Canvas, from: V2, to: V2, color: V4) {
+    let color = pack_color(color);
+    let delta = to - from;
+    let bounds = canvas.bounds();
+
+    if delta.x.abs() >= delta.y.abs() {
+        if delta.x == 0.0 {
+            canvas.put(from.x.floor() as i32, from.y.floor() as i32, color);
+            return;
+        }
+        let (start, end) = if from.x <= to.x {
+            (from, to)
+        } else {
+            (to, from)
+        };
+        let slope = delta.y / delta.x;
+        let first = (start.x.floor() as i32).max(bounds.min_x);
+        let last = (end.x.floor() as i32).min(bounds.max_x - 1);
+        for x in first..=last {
+            let center = (x as f32 + 0.5).clamp(start.x, end.x);
+            let y = start.y + (center - start.x) * slope;
+            canvas.put(x, y.floor() as i32, color);
+        }
+    } else {
+        let (start, end) = if from.y <= to.y {
+            (from, to)
+        } else {
+            (to, from)
+        };
+        let slope = delta.x / delta.y;
+        let first = (start.y.floor() as i32).max(bounds.min_y);
+        let last = (end.y.floor() as i32).min(bounds.max_y - 1);
+        for y in first..=last {
+            let center = (y as f32 + 0.5).clamp(start.y, end.y);
+            let x = start.x + (center - start.y) * slope;
+            canvas.put(x.floor() as i32, y, color);
+        }
+    }
+}
+
+/// Pixels whose centers are in `min..max`, and one more on the far sides, cut down to the canvas.
+/// Note(voided): the corners get clamped to just outside of the canvas before they become pixels,
+/// shapes far off screen would overflow otherwise.
+fn pixel_bounds(canvas: &Canvas, min: V2, max: V2) -> Rect2i {
+    let bounds = canvas.bounds();
+    let clamp_x = |x: f32| x.clamp(bounds.min_x as f32 - 1.0, bounds.max_x as f32 + 1.0);
+    let clamp_y = |y: f32| y.clamp(bounds.min_y as f32 - 1.0, bounds.max_y as f32 + 1.0);
+    let (min_x, max_x) = pixel_span(clamp_x(min.x), clamp_x(max.x));
+    let (min_y, max_y) = pixel_span(clamp_y(min.y), clamp_y(max.y));
+    canvas.clip(Rect2i::new(min_x, min_y, max_x + 1, max_y + 1))
+}
+
+fn circle_bounds(canvas: &Canvas, center: V2, radius: f32) -> Rect2i {
+    let extent = v2(radius, radius);
+    pixel_bounds(canvas, center - extent, center + extent)
+}
+
+fn pixel_center(x: i32, y: i32) -> V2 {
+    v2(x as f32 + 0.5, y as f32 + 0.5)
+}
+
+pub fn draw_circle(canvas: &mut Canvas, center: V2, radius: f32, color: V4) {
+    draw_ring(canvas, center, 0.0, radius, color);
+}
+
+/// The outline grows inwards from the radius, outlines thicker than the radius are filled.
+pub fn draw_circle_outline(
+    canvas: &mut Canvas,
+    center: V2,
+    radius: f32,
+    thickness: f32,
+    color: V4,
+) {
+    draw_ring(canvas, center, (radius - thickness).max(0.0), radius, color);
+}
+
+fn draw_ring(canvas: &mut Canvas, center: V2, inner_radius: f32, outer_radius: f32, color: V4) {
+    let rect = circle_bounds(canvas, center, outer_radius);
+    let inner_squared = inner_radius * inner_radius;
+    let outer_squared = outer_radius * outer_radius;
+    let color = pack_color(color);
+
+    for y in rect.min_y..rect.max_y {
+        let span = canvas.span_mut(y, rect.min_x, rect.max_x);
+        for (x, pixel) in (rect.min_x..rect.max_x).zip(span.iter_mut()) {
+            let distance_squared = (pixel_center(x, y) - center).length_squared();
+            if distance_squared >= inner_squared && distance_squared < outer_squared {
+                *pixel = color;
+            }
+        }
+    }
+}
+
+/// Twice the signed area of `a b p`, positive if `p` is on the inside of the edge `a -> b` of a
+/// clockwise (on screen) triangle.
+fn edge_function(a: V2, b: V2, p: V2) -> f32 {
+    (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+}
+
+fn is_top_left(a: V2, b: V2) -> bool {
+    let edge = b - a;
+    (edge.y == 0.0 && edge.x > 0.0) || edge.y < 0.0
+}
+
+/// Calls `shade` with the barycentric weights of every covered pixel.
+fn rasterize_triangle<F>(canvas: &mut Canvas, vertices: [V2; 3], mut shade: F)
+where
+    F: FnMut([f32; 3]) -> u32,
+{
+    let [a, mut b, mut c] = vertices;
+    let mut area = edge_function(a, b, c);
+    if area < 0.0 {
+        std::mem::swap(&mut b, &mut c);
+        area = -area;
+    }
+    if area == 0.0 {
+        return;
+    }
+
+    let min_x = a.x.min(b.x).min(c.x);
+    let max_x = a.x.max(b.x).max(c.x);
+    let min_y = a.y.min(b.y).min(c.y);
+    let max_y = a.y.max(b.y).max(c.y);
+    let rect = pixel_bounds(canvas, v2(min_x, min_y), v2(max_x, max_y));
+
+    let edges = [(b, c), (c, a), (a, b)];
+    let top_left = edges.map(|(from, to)| is_top_left(from, to));
+    let swapped = vertices[1] != b;
+
+    for y in rect.min_y..rect.max_y {
+        let span = canvas.span_mut(y, rect.min_x, rect.max_x);
+        for (x, pixel) in (rect.min_x..rect.max_x).zip(span.iter_mut()) {
+            let p = pixel_center(x, y);
+            let weights = [0, 1, 2].map(|i| edge_function(edges[i].0, edges[i].1, p));
+            let inside = (0..3).all(|i| weights[i] > 0.0 || (weights[i] == 0.0 && top_left[i]));
+            if inside {
+                let mut weights = weights.map(|w| w / area);
+                if swapped {
+                    weights.swap(1, 2);
+                }
+                *pixel = shade(weights);
+            }
+        }
+    }
+}
+
+pub fn draw_triangle(canvas: &mut Canvas, vertices: [V2; 3], color: V4) {
+    let color = pack_color(color);
+    rasterize_triangle(canvas, vertices, |_| color);
+}
+
+/// Colors get interpolated between the vertices.
+pub fn draw_gradient_triangle(canvas: &mut Canvas, vertices: [V2; 3], colors: [V4; 3]) {
+    rasterize_triangle(canvas, vertices, |[w0, w1, w2]| {
+        pack_color(colors[0] * w0 + colors[1] * w1 + colors[2] * w2)
+    });
+}
+
+/// Blends the bitmap on top of the canvas, its top left corner snapped to the pixel closest to
+/// `position`.
+pub fn draw_bitmap(canvas: &mut Canvas, bitmap: &Bitmap, position: V2) {
+    let whole = Rect2i::new(0, 0, bitmap.width() as i32, bitmap.height() as i32);
+    draw_bitmap_region(canvas, bitmap, whole, position);
+}
+
+/// Like [`draw_bitmap`], but only the `source` part of the bitmap, for atlases.
+pub fn draw_bitmap_region(canvas: &mut Canvas, bitmap: &Bitmap, source: Rect2i, position: V2) {
+    let source = source.intersect(&Rect2i::new(
+        0,
+        0,
+        bitmap.width() as i32,
+        bitmap.height() as i32,
+    ));
+    let min_x = round_to_pixel(position.x);
+    let min_y = round_to_pixel(position.y);
+    // far off screen the size saturates, the rectangle gets clipped away all the same
+    let rect = canvas.clip(Rect2i::with_size(
+        min_x,
+        min_y,
+        source.width(),
+        source.height(),
+    ));
+    if rect.is_empty() {
+        return;
+    }
+
+    for y in rect.min_y..rect.max_y {
+        let source_row = bitmap
+            .row((y - min_y + source.min_y) as usize)
+            .expect("rect is inside the bitmap");
+        let start = (rect.min_x - min_x + source.min_x) as usize;
+        let source = &source_row[start..start + rect.width() as usize];
+        blend_span(canvas.span_mut(y, rect.min_x, rect.max_x), source);
+    }
+}
+
+/// Premultiplied linear color of a texel, transparent outside of the bitmap.
+fn linear_texel(tables: &SrgbTables, bitmap: BitmapView, x: i32, y: i32) -> V4 {
+    if x < 0 || y < 0 {
+        return V4::default();
+    }
+    match bitmap.pixel(x as usize, y as usize) {
+        Some(texel) => v4(
+            tables.decode(texel >> 16),
+            tables.decode(texel >> 8),
+            tables.decode(texel),
+            (texel >> 24) as f32 / 255.0,
+        ),
+        None => V4::default(),
+    }
+}
+
+/// Bilinear sample at texel coordinates, texel centers are on whole numbers.
+fn sample_bilinear(tables: &SrgbTables, bitmap: BitmapView, x: f32, y: f32) -> V4 {
+    let (min_x, min_y) = (x.floor(), y.floor());
+    let (fraction_x, fraction_y) = (x - min_x, y - min_y);
+    let (min_x, min_y) = (min_x as i32, min_y as i32);
+    let top = lerp(
+        linear_texel(tables, bitmap, min_x, min_y),
+        fraction_x,
+        linear_texel(tables, bitmap, min_x + 1, min_y),
+    );
+    let bottom = lerp(
+        linear_texel(tables, bitmap, min_x, min_y + 1),
+        fraction_x,
+        linear_texel(tables, bitmap, min_x + 1, min_y + 1),
+    );
+    lerp(top, fraction_y, bottom)
+}
+
+/// Bilinear sample of a normal map at 0..=1 coordinates across it, the edges repeat outwards.
+fn sample_normal(normal_map: &Bitmap, u: f32, v: f32) -> V3 {
+    let max_x = normal_map.width() as f32 - 1.0;
+    let max_y = normal_map.height() as f32 - 1.0;
+    let x = (u * normal_map.width() as f32 - 0.5).clamp(0.0, max_x);
+    let y = (v * normal_map.height() as f32 - 0.5).clamp(0.0, max_y);
+    let (min_x, min_y) = (x.floor(), y.floor());
+    let (fraction_x, fraction_y) = (x - min_x, y - min_y);
+    let normal = |x: f32, y: f32| {
+        let texel = normal_map.pixel(x.min(max_x) as usize, y.min(max_y) as usize);
+        decode_normal(texel.expect("coordinates are clamped"))
+    };
+    let top = lerp(normal(min_x, min_y), fraction_x, normal(min_x + 1.0, min_y));
+    let bottom = lerp(
+        normal(min_x, min_y + 1.0),
+        fraction_x,
+        normal(min_x + 1.0, min_y + 1.0),
+    );
+    lerp(top, fraction_y, bottom)
+}
+
+/// Draws the bitmap onto the parallelogram with corners `origin`, `origin + x_axis` and
+/// `origin + y_axis`: its top edge goes along `x_axis` and its left edge along `y_axis`, which
+/// covers rotation, scale and shear. Texels get filtered bilinearly in linear space.
+/// Note(voided): pixels on the edges are covered by how far their center is inside, in pixels,
+/// so the edges are smooth without blurring when the bitmap gets magnified. Without a transform
+/// the result is the same as [`draw_bitmap`].
+/// TODO(voided): one pixel at a time, a SIMD version like the spans once it shows up in profiles
+pub fn draw_bitmap_transformed(
+    canvas: &mut Canvas,
+    bitmap: &Bitmap,
+    origin: V2,
+    x_axis: V2,
+    y_axis: V2,
+) {
+    timed_block!("draw_bitmap_transformed");
+    draw_bitmap_sampled(canvas, bitmap.view(), origin, x_axis, y_axis, 1.0, None);
+}
+
+/// Like [`draw_bitmap_transformed`], for images that aren't bitmaps of their own, like render
+/// targets. `alpha` fades the whole image, 0 leaves the canvas as it is.
+pub fn composite(
+    canvas: &mut Canvas,
+    source: BitmapView,
+    origin: V2,
+    x_axis: V2,
+    y_axis: V2,
+    alpha: f32,
+) {
+    timed_block!("composite");
+    let alpha = alpha.clamp(0.0, 1.0);
+    draw_bitmap_sampled(canvas, source, origin, x_axis, y_axis, alpha, None);
+}
+
+/// Like [`draw_bitmap_transformed`], with the colors scaled by the light that reaches them. The
+/// normal map gets stretched over the bitmap and turns with the axes.
+pub fn draw_bitmap_lit(
+    canvas: &mut Canvas,
+    bitmap: &Bitmap,
+    normal_map: &Bitmap,
+    origin: V2,
+    x_axis: V2,
+    y_axis: V2,
+    lighting: &Lighting,
+) {
+    timed_block!("draw_bitmap_lit");
+    if normal_map.width() == 0 || normal_map.height() == 0 {
+        return;
+    }
+    let lit = Some((normal_map, lighting));
+    draw_bitmap_sampled(canvas, bitmap.view(), origin, x_axis, y_axis, 1.0, lit);
+}
+
+fn draw_bitmap_sampled(
+    canvas: &mut Canvas,
+    bitmap: BitmapView,
+    origin: V2,
+    x_axis: V2,
+    y_axis: V2,
+    alpha: f32,
+    lit: Option<(&Bitmap, &Lighting)>,
+) {
+    let determinant = x_axis.x * y_axis.y - x_axis.y * y_axis.x;
+    if bitmap.width() == 0 || bitmap.height() == 0 || determinant == 0.0 {
+        return;
+    }
+    let (width, height) = (bitmap.width() as f32, bitmap.height() as f32);
+
+    let corners = [
+        origin,
+        origin + x_axis,
+        origin + y_axis,
+        origin + x_axis + y_axis,
+    ];
+    let (min_x, max_x) = corners.iter().fold((f32::MAX, f32::MIN), |(min, max), c| {
+        (min.min(c.x), max.max(c.x))
+    });
+    let (min_y, max_y) = corners.iter().fold((f32::MAX, f32::MIN), |(min, max), c| {
+        (min.min(c.y), max.max(c.y))
+    });
+    // half a pixel more on every side for the partly covered ones
+    let rect = pixel_bounds(
+        canvas,
+        v2(min_x - 0.5, min_y - 0.5),
+        v2(max_x + 0.5, max_y + 0.5),
+    );
+
+    // normal maps turn with the axes, but don't stretch
+    let x_direction = x_axis * (1.0 / x_axis.length());
+    let y_direction = y_axis * (1.0 / y_axis.length());
+    // how many pixels apart the edges along y_axis are, and the ones along x_axis
+    let u_pixels = determinant.abs() / y_axis.length();
+    let v_pixels = determinant.abs() / x_axis.length();
+
+    let tables = SrgbTables::get();
+    for y in rect.min_y..rect.max_y {
+        let span = canvas.span_mut(y, rect.min_x, rect.max_x);
+        for (x, pixel) in (rect.min_x..rect.max_x).zip(span.iter_mut()) {
+            // where the pixel center is in the bitmap, solved for the two axes
+            let d = pixel_center(x, y) - origin;
+            let u = (d.x * y_axis.y - d.y * y_axis.x) / determinant;
+            let v = (x_axis.x * d.y - x_axis.y * d.x) / determinant;
+            let edge_distance = (u.min(1.0 - u) * u_pixels).min(v.min(1.0 - v) * v_pixels);
+            let coverage = (edge_distance + 0.5).min(1.0);
+            if coverage <= 0.0 {
+                continue;
+            }
+
+            // pixels hanging over the edge get the color of the texels on it
+            let texel_x = (u * width - 0.5).clamp(0.0, width - 1.0);
+            let texel_y = (v * height - 0.5).clamp(0.0, height - 1.0);
+            // premultiplied, so fading scales every channel
+            let mut source = sample_bilinear(tables, bitmap, texel_x, texel_y) * (alpha * coverage);
+            // blending nothing gives back the same pixel, the bytes round trip exactly
+            if source == V4::default() {
+                continue;
+            }
+            if let Some((normal_map, lighting)) = lit {
+                let normal = sample_normal(normal_map, u, v);
+                let turned = x_direction * normal.x + y_direction * normal.y;
+                let normal = v3(turned.x, turned.y, normal.z).normalize_or(v3(0.0, 0.0, 1.0));
+                let center = pixel_center(x, y);
+                let light = lighting.light_at(v3(center.x, center.y, 0.0), normal);
+                source = v4(
+                    source.r() * light.x,
+                    source.g() * light.y,
+                    source.b() * light.z,
+                    source.a(),
+                );
+            }
+            let source = ((source.a().clamp(0.0, 1.0) * 255.0 + 0.5) as u32) << 24
+                | tables.encode(source.r()) << 16
+                | tables.encode(source.g()) << 8
+                | tables.encode(source.b());
+            *pixel = blend_pixel(tables, source, *pixel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod rasterizer_tests {
+    use utility::image::{parse_image, Bitmap};
+    use utility::math::{v2, v3, v4, Rect2i, V2, V4};
+
+    use crate::golden::{check_golden, compare, Tolerance};
+    use crate::lighting::{dome_normal_map, encode_normal, Lighting, PointLight};
+    use crate::pixels::PixelsMut;
+    use crate::rasterizer::{
+        clear, composite, draw_bitmap, draw_bitmap_lit, draw_bitmap_region,
+        draw_bitmap_transformed, draw_circle, draw_circle_outline, draw_gradient_triangle,
+        draw_line, draw_rectangle, draw_rectangle_outline, draw_triangle, pack_color, unpack_color,
+        Canvas,
+    };
+
+    const WHITE: V4 = v4(1.0, 1.0, 1.0, 1.0);
+    const BLACK: V4 = v4(0.0, 0.0, 0.0, 1.0);
+
+    /// Renders into a small buffer and turns it into ascii, `#` for white and `.` for black.
+    fn render<F>(width: usize, height: usize, draw: F) -> String
+    where
+        F: FnOnce(&mut Canvas),
+    {
+        let mut memory = vec![0; width * height];
+        let mut canvas = Canvas::new(PixelsMut::new(&mut memory, width, height, width).unwrap());
+        clear(&mut canvas, BLACK);
+        draw(&mut canvas);
+        ascii(&memory, width)
+    }
+
+    fn ascii(memory: &[u32], width: usize) -> String {
+        memory
+            .chunks(width)
+            .map(|row| {
+                row.iter()
+                    .map(|pixel| match *pixel {
+                        p if p == pack_color(WHITE) => '#',
+                        p if p == pack_color(BLACK) => '.',
+                        _ => '?',
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn golden(rows: &[&str]) -> String {
+        rows.join("\n")
+    }
+
+    #[test]
+    fn color_packing() {
+        assert_eq!(pack_color(v4(1.0, 0.5, 0.0, 1.0)), 0xFFFFBC00);
+        assert_eq!(pack_color(v4(2.0, -1.0, 0.0, 0.0)), 0x00FF0000);
+        assert_eq!(pack_color(unpack_color(0x80402010)), 0x80402010);
+    }
+
+    #[test]
+    fn rectangle_rounds_to_pixel_centers() {
+        let image = render(6, 5, |canvas| {
+            draw_rectangle(canvas, v2(1.4, 1.5), v2(4.5, 3.6), WHITE);
+        });
+        assert_eq!(
+            image,
+            golden(&["......", ".###..", ".###..", ".###..", "......"])
+        );
+    }
+
+    #[test]
+    fn rectangle_clips() {
+        let image = render(4, 3, |canvas| {
+            draw_rectangle(canvas, v2(-10.0, 1.0), v2(2.0, 100.0), WHITE);
+            draw_rectangle(canvas, v2(3.0, -5.0), v2(50.0, 1.0), WHITE);
+        });
+        assert_eq!(image, golden(&["...#", "##..", "##.."]));
+    }
+
+    #[test]
+    fn far_offscreen_shapes_draw_nothing() {
+        let image = render(4, 3, |canvas| {
+            draw_circle(canvas, v2(3e9, 3e9), 10.0, WHITE);
+            // right of the canvas, but on its rows
+            draw_circle(canvas, v2(20.0, 1.0), 3.0, WHITE);
+            draw_circle_outline(canvas, v2(-3e9, 1.0), 5.0, 1.0, WHITE);
+            draw_triangle(
+                canvas,
+                [v2(3e9, 0.0), v2(3e9 + 1e6, 0.0), v2(3e9, 1e6)],
+                WHITE,
+            );
+            draw_triangle(
+                canvas,
+                [v2(-3e9, -3e9), v2(-3e9 + 1e6, -3e9), v2(-3e9, -3e9 + 1e6)],
+                WHITE,
+            );
+        });
+        assert_eq!(image, golden(&["....", "....", "...."]));
+    }
+
+    #[test]
+    fn rectangle_outline() {
+        let image = render(6, 5, |canvas| {
+            draw_rectangle_outline(canvas, v2(0.0, 0.0), v2(6.0, 5.0), 1.0, WHITE);
+        });
+        assert_eq!(
+            image,
+            golden(&["######", "#....#", "#....#", "#....#", "######"])
+        );
+    }
+
+    #[test]
+    fn lines() {
+        let image = render(6, 6, |canvas| {
+            draw_line(canvas, v2(0.5, 0.5), v2(5.5, 0.5), WHITE);
+            draw_line(canvas, v2(0.2, 1.5), v2(5.9, 5.5), WHITE);
+            draw_line(canvas, v2(0.5, 5.9), v2(0.5, 2.0), WHITE);
+        });
+        assert_eq!(
+            image,
+            golden(&["######", "#.....", "##....", "#.##..", "#...#.", "#....#"])
+        );
+    }
+
+    #[test]
+    fn line_clips_without_changing_pixels() {
+        let image = render(6, 4, |canvas| {
+            draw_line(canvas, v2(-1000.0, -1000.0), v2(1000.0, 1000.0), WHITE);
+        });
+        assert_eq!(image, golden(&["#.....", ".#....", "..#...", "...#.."]));
+    }
+
+    #[test]
+    fn circles() {
+        let image = render(7, 7, |canvas| {
+            draw_circle(canvas, v2(3.5, 3.5), 3.5, WHITE);
+        });
+        assert_eq!(
+            image,
+            golden(&["..###..", ".#####.", "#######", "#######", "#######", ".#####.", "..###..",])
+        );
+
+        let image = render(7, 7, |canvas| {
+            draw_circle_outline(canvas, v2(3.5, 3.5), 3.5, 1.0, WHITE);
+        });
+        assert_eq!(
+            image,
+            golden(&["..###..", ".#...#.", "#.....#", "#.....#", "#.....#", ".#...#.", "..###..",])
+        );
+
+        let thick = render(7, 7, |canvas| {
+            draw_circle_outline(canvas, v2(3.5, 3.5), 3.5, 6.0, WHITE);
+        });
+        let filled = render(7, 7, |canvas| {
+            draw_circle(canvas, v2(3.5, 3.5), 3.5, WHITE);
+        });
+        assert_eq!(thick, filled);
+    }
+
+    #[test]
+    fn triangle_winding_does_not_matter() {
+        let clockwise = render(6, 6, |canvas| {
+            draw_triangle(canvas, [v2(0.0, 0.0), v2(6.0, 0.0), v2(0.0, 6.0)], WHITE);
+        });
+        let counter_clockwise = render(6, 6, |canvas| {
+            draw_triangle(canvas, [v2(0.0, 0.0), v2(0.0, 6.0), v2(6.0, 0.0)], WHITE);
+        });
+        assert_eq!(clockwise, counter_clockwise);
+        assert_eq!(
+            clockwise,
+            golden(&["#####.", "####..", "###...", "##....", "#.....", "......"])
+        );
+    }
+
+    #[test]
+    fn shared_triangle_edges_draw_pixels_once() {
+        let mut memory = vec![0u32; 36];
+        let mut canvas = Canvas::new(PixelsMut::new(&mut memory, 6, 6, 6).unwrap());
+        let quad = [v2(0.0, 0.0), v2(6.0, 0.0), v2(6.0, 6.0), v2(0.0, 6.0)];
+        // count the writes, every pixel has to be hit exactly once
+        let mut counts = [0; 36];
+        for triangle in [[quad[0], quad[1], quad[2]], [quad[0], quad[2], quad[3]]] {
+            clear(&mut canvas, BLACK);
+            draw_triangle(&mut canvas, triangle, WHITE);
+            for (count, pixel) in counts.iter_mut().zip(canvas.pixels().rows().flatten()) {
+                if *pixel == pack_color(WHITE) {
+                    *count += 1;
+                }
+            }
+        }
+        assert!(counts.iter().all(|count| *count == 1));
+    }
+
+    #[test]
+    fn gradient_triangle_interpolates() {
+        let mut memory = vec![0u32; 64];
+        let mut canvas = Canvas::new(PixelsMut::new(&mut memory, 8, 8, 8).unwrap());
+        let red = v4(1.0, 0.0, 0.0, 1.0);
+        let green = v4(0.0, 1.0, 0.0, 1.0);
+        let blue = v4(0.0, 0.0, 1.0, 1.0);
+        draw_gradient_triangle(
+            &mut canvas,
+            [v2(0.5, 0.5), v2(7.5, 0.5), v2(0.5, 7.5)],
+            [red, green, blue],
+        );
+
+        // the other two vertices are on the bottom right edge, which isn't drawn
+        let pixels = canvas.pixels();
+        assert_eq!(*pixels.pixel(0, 0).unwrap(), pack_color(red));
+        let top = unpack_color(*pixels.pixel(3, 0).unwrap());
+        assert!((top.r() - 4.0 / 7.0).abs() < 0.01);
+        assert!((top.g() - 3.0 / 7.0).abs() < 0.01);
+        let left = unpack_color(*pixels.pixel(0, 3).unwrap());
+        assert!((left.r() - 4.0 / 7.0).abs() < 0.01);
+        assert!((left.b() - 3.0 / 7.0).abs() < 0.01);
+        assert_eq!(*pixels.pixel(7, 0).unwrap(), 0);
+        assert_eq!(*pixels.pixel(7, 7).unwrap(), 0);
+    }
+
+    #[test]
+    fn split_canvas_matches_whole() {
+        fn scene(canvas: &mut Canvas) {
+            clear(canvas, BLACK);
+            draw_circle(canvas, v2(5.3, 4.1), 3.7, WHITE);
+            draw_line(canvas, v2(0.0, 9.0), v2(9.7, 0.2), WHITE);
+            draw_triangle(canvas, [v2(1.0, 7.0), v2(9.0, 5.5), v2(4.0, 9.8)], WHITE);
+        }
+
+        let mut whole = vec![0u32; 100];
+        scene(&mut Canvas::new(
+            PixelsMut::new(&mut whole, 10, 10, 10).unwrap(),
+        ));
+
+        let mut split = vec![0u32; 100];
+        let (top, bottom) = split.split_at_mut(30);
+        scene(&mut Canvas::with_origin(
+            PixelsMut::new(top, 10, 3, 10).unwrap(),
+            0,
+            0,
+        ));
+        scene(&mut Canvas::with_origin(
+            PixelsMut::new(bottom, 10, 7, 10).unwrap(),
+            0,
+            3,
+        ));
+
+        assert_eq!(ascii(&whole, 10), ascii(&split, 10));
+    }
+
+    #[test]
+    fn bitmap_is_clipped() {
+        let bitmap = Bitmap::new(3, 3, vec![0xFFFFFFFF; 9]).unwrap();
+        let image = render(4, 4, |canvas| {
+            draw_bitmap(canvas, &bitmap, v2(-1.6, 2.4));
+            draw_bitmap(canvas, &bitmap, v2(3.0, -2.0));
+            draw_bitmap(canvas, &bitmap, v2(10.0, 0.0));
+        });
+        assert_eq!(image, golden(&["...#", "....", "#...", "#..."]));
+    }
+
+    #[test]
+    fn bitmap_region() {
+        let white = 0xFFFFFFFF;
+        #[rustfmt::skip]
+        let bitmap = Bitmap::new(4, 3, vec![
+            0, 0, 0, 0,
+            0, white, white, 0,
+            0, white, 0, 0,
+        ]).unwrap();
+        let image = render(4, 4, |canvas| {
+            draw_bitmap_region(canvas, &bitmap, Rect2i::new(1, 1, 3, 3), v2(2.0, 1.0));
+            // the part outside of the bitmap gets ignored
+            draw_bitmap_region(canvas, &bitmap, Rect2i::new(2, 1, 9, 9), v2(0.0, 3.0));
+        });
+        assert_eq!(image, golden(&["....", "..##", "..#.", "#..."]));
+    }
+
+    #[test]
+    fn far_offscreen_transformed_bitmaps_draw_nothing() {
+        let bitmap = Bitmap::new(3, 3, vec![0xFFFFFFFF; 9]).unwrap();
+        let image = render(4, 4, |canvas| {
+            let (x_axis, y_axis) = (v2(3.0, 0.0), v2(0.0, 3.0));
+            draw_bitmap_transformed(canvas, &bitmap, v2(3e9, 3e9), x_axis, y_axis);
+            draw_bitmap_transformed(canvas, &bitmap, v2(-3e9, 0.0), x_axis, y_axis);
+            // huge axes, pointing away from the canvas
+            draw_bitmap_transformed(canvas, &bitmap, v2(-1.0, 0.0), v2(-1e10, 0.0), y_axis);
+            draw_bitmap_transformed(canvas, &bitmap, v2(0.0, 5.0), x_axis, v2(0.0, 1e10));
+        });
+        assert_eq!(image, golden(&["....", "....", "....", "...."]));
+    }
+
+    #[test]
+    fn far_offscreen_bitmaps_draw_nothing() {
+        let bitmap = Bitmap::new(3, 3, vec![0xFFFFFFFF; 9]).unwrap();
+        let image = render(4, 4, |canvas| {
+            draw_bitmap(canvas, &bitmap, v2(3e9, 3e9));
+            draw_bitmap(canvas, &bitmap, v2(-3e9, -3e9));
+            // rounds to just under i32::MAX
+            draw_bitmap(canvas, &bitmap, v2(2147483520.0, 0.0));
+            draw_bitmap_region(canvas, &bitmap, Rect2i::new(1, 1, 3, 3), v2(1.0, 3e9));
+        });
+        assert_eq!(image, golden(&["....", "....", "....", "...."]));
+    }
+
+    #[test]
+    fn bitmap_alpha_blending() {
+        let bitmap = Bitmap::new(3, 1, vec![0x00000000, 0x80800000, 0xFF0000FF]).unwrap();
+        let mut memory = vec![0xFF00FF00; 3];
+        let mut canvas = Canvas::new(PixelsMut::new(&mut memory, 3, 1, 3).unwrap());
+        draw_bitmap(&mut canvas, &bitmap, v2(0.0, 0.0));
+        assert_eq!(memory, [0xFF00FF00, 0xFF80BB00, 0xFF0000FF]);
+    }
+
+    fn test_sprite() -> Bitmap {
+        parse_image(include_bytes!("../../data/test_sprite.bmp")).unwrap()
+    }
+
+    /// Lopsided enough that every transform shows: a red stripe down the left, a yellow one along
+    /// the top, half transparent blue inside and a notch cut out of the top right corner.
+    fn lopsided_sprite() -> Bitmap {
+        let (width, height) = (24, 16);
+        let pixels = (0..width * height)
+            .map(|index| {
+                let (x, y) = (index % width, index / width);
+                match (x, y) {
+                    (18.., ..6) => 0x00000000,
+                    (..4, _) => 0xFFFF0000,
+                    (_, ..3) => 0xFFFFFF00,
+                    _ => 0x800000BC,
+                }
+            })
+            .collect();
+        Bitmap::new(width, height, pixels).unwrap()
+    }
+
+    /// A 64x64 checkerboard with whatever `draw` adds on top.
+    fn checkerboard_scene<F>(draw: F) -> Bitmap
+    where
+        F: FnOnce(&mut Canvas),
+    {
+        let mut memory = vec![0; 64 * 64];
+        let mut canvas = Canvas::new(PixelsMut::new(&mut memory, 64, 64, 64).unwrap());
+        clear(&mut canvas, v4(0.05, 0.05, 0.05, 1.0));
+        for y in 0..8 {
+            for x in (y % 2..8).step_by(2) {
+                let min = v2(x as f32 * 8.0, y as f32 * 8.0);
+                draw_rectangle(&mut canvas, min, min + v2(8.0, 8.0), v4(0.2, 0.2, 0.2, 1.0));
+            }
+        }
+        draw(&mut canvas);
+        Bitmap::new(64, 64, memory).unwrap()
+    }
+
+    #[test]
+    fn transformed_bitmap_without_transform() {
+        let sprite = test_sprite();
+        let size = 32.0;
+        let blit = checkerboard_scene(|canvas| draw_bitmap(canvas, &sprite, v2(7.0, 13.0)));
+        let transformed = checkerboard_scene(|canvas| {
+            draw_bitmap_transformed(canvas, &sprite, v2(7.0, 13.0), v2(size, 0.0), v2(0.0, size))
+        });
+        // the blend rounds a little differently
+        let rounding = Tolerance {
+            channel: 1,
+            pixels: 64 * 64,
+        };
+        assert!(compare(&blit, &transformed, rounding).is_none());
+
+        // degenerate axes draw nothing
+        let flat = checkerboard_scene(|canvas| {
+            draw_bitmap_transformed(canvas, &sprite, v2(7.0, 13.0), v2(size, 0.0), v2(size, 0.0))
+        });
+        assert!(compare(&checkerboard_scene(|_| {}), &flat, Tolerance::EXACT).is_none());
+    }
+
+    #[test]
+    fn magnified_edges_stay_sharp() {
+        let white = Bitmap::new(2, 2, vec![0xFFFFFFFF; 4]).unwrap();
+        let background = checkerboard_scene(|_| {});
+        let magnified = |origin: V2| {
+            checkerboard_scene(|canvas| {
+                draw_bitmap_transformed(canvas, &white, origin, v2(40.0, 0.0), v2(0.0, 40.0))
+            })
+        };
+
+        let aligned = magnified(v2(4.0, 4.0));
+        for (x, expected) in [(3, background.pixel(3, 20)), (4, Some(0xFFFFFFFF))] {
+            assert_eq!(aligned.pixel(x, 20), expected);
+        }
+        assert_eq!(aligned.pixel(43, 20), Some(0xFFFFFFFF));
+        assert_eq!(aligned.pixel(44, 20), background.pixel(44, 20));
+
+        // half a pixel over, only the pixel the edge goes through is in between
+        let half = magnified(v2(4.5, 4.0));
+        assert_eq!(half.pixel(3, 20), background.pixel(3, 20));
+        assert_ne!(half.pixel(4, 20), background.pixel(4, 20));
+        assert_ne!(half.pixel(4, 20), Some(0xFFFFFFFF));
+        assert_eq!(half.pixel(5, 20), Some(0xFFFFFFFF));
+    }
+
+    #[test]
+    fn composite_fades_out() {
+        let sprite = lopsided_sprite();
+        let (origin, x_axis, y_axis) = (v2(10.5, 6.0), v2(30.0, 20.0), v2(-12.0, 40.0));
+        let transformed = checkerboard_scene(|canvas| {
+            draw_bitmap_transformed(canvas, &sprite, origin, x_axis, y_axis)
+        });
+        let opaque = checkerboard_scene(|canvas| {
+            composite(canvas, sprite.view(), origin, x_axis, y_axis, 1.0)
+        });
+        assert!(compare(&transformed, &opaque, Tolerance::EXACT).is_none());
+
+        let invisible = checkerboard_scene(|canvas| {
+            composite(canvas, sprite.view(), origin, x_axis, y_axis, 0.0)
+        });
+        assert!(compare(&checkerboard_scene(|_| {}), &invisible, Tolerance::EXACT).is_none());
+
+        // half way, every pixel lands between the background and the opaque sprite
+        let half = checkerboard_scene(|canvas| {
+            composite(canvas, sprite.view(), origin, x_axis, y_axis, 0.5)
+        });
+        let background = checkerboard_scene(|_| {});
+        let between = |a: u32, b: u32, c: u32| {
+            [0, 8, 16].iter().all(|shift| {
+                let (a, b, c) = (
+                    (a >> shift) & 0xFF,
+                    (b >> shift) & 0xFF,
+                    (c >> shift) & 0xFF,
+                );
+                a.min(c) <= b && b <= a.max(c)
+            })
+        };
+        let pixels = background
+            .pixels()
+            .iter()
+            .zip(half.pixels())
+            .zip(opaque.pixels());
+        for ((&background, &half), &opaque) in pixels {
+            assert!(between(background, half, opaque));
+        }
+        assert_ne!(half.pixels(), background.pixels());
+    }
+
+    #[test]
+    fn golden_transformed_bitmaps() {
+        let sprite = lopsided_sprite();
+        let center = v2(32.0, 32.0);
+        let rotated = |degrees: f32, width: f32, height: f32| {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            let x_axis = v2(cos, sin) * width;
+            let y_axis = v2(-sin, cos) * height;
+            (center - x_axis * 0.5 - y_axis * 0.5, x_axis, y_axis)
+        };
+        let cases: [(&str, (V2, V2, V2)); 5] = [
+            ("transform_rotate", rotated(30.0, 48.0, 48.0)),
+            ("transform_rotate_squash", rotated(120.0, 56.0, 24.0)),
+            (
+                "transform_magnify",
+                (v2(4.0, 4.0), v2(56.0, 0.0), v2(0.0, 56.0)),
+            ),
+            (
+                "transform_minify",
+                (v2(20.3, 20.7), v2(13.0, 0.0), v2(0.0, 13.0)),
+            ),
+            (
+                "transform_shear",
+                (v2(8.0, 8.0), v2(40.0, 0.0), v2(16.0, 48.0)),
+            ),
+        ];
+        for (name, (origin, x_axis, y_axis)) in cases {
+            let image = checkerboard_scene(|canvas| {
+                draw_bitmap_transformed(canvas, &sprite, origin, x_axis, y_axis)
+            });
+            check_golden(name, &image, Tolerance::EXACT);
+        }
+    }
+
+    #[test]
+    fn flat_normals_in_white_light_change_nothing() {
+        let sprite = lopsided_sprite();
+        let flat = Bitmap::new(1, 1, vec![encode_normal(v3(0.0, 0.0, 1.0))]).unwrap();
+        let white = Lighting {
+            ambient: v3(1.0, 1.0, 1.0),
+            lights: &[],
+        };
+        let (origin, x_axis, y_axis) = (v2(5.5, 9.0), v2(40.0, 12.0), v2(-6.0, 30.0));
+        let unlit = checkerboard_scene(|canvas| {
+            draw_bitmap_transformed(canvas, &sprite, origin, x_axis, y_axis)
+        });
+        let lit = checkerboard_scene(|canvas| {
+            draw_bitmap_lit(canvas, &sprite, &flat, origin, x_axis, y_axis, &white)
+        });
+        assert!(compare(&unlit, &lit, Tolerance::EXACT).is_none());
+
+        let dark = Lighting::default();
+        let black = checkerboard_scene(|canvas| {
+            draw_bitmap_lit(canvas, &sprite, &flat, origin, x_axis, y_axis, &dark)
+        });
+        // lit by nothing, but still covering the checkerboard
+        assert!(compare(&unlit, &black, Tolerance::EXACT).is_some());
+        assert_eq!(black.pixel(5, 24), Some(0xFF000000));
+    }
+
+    #[test]
+    fn golden_lit_bitmaps() {
+        let sprite = test_sprite();
+        let normals = dome_normal_map(sprite.width(), sprite.height());
+        let warm = PointLight {
+            position: v3(8.0, 8.0, 24.0),
+            color: v3(1.5, 1.2, 0.9),
+            radius: 80.0,
+        };
+        let blue = PointLight {
+            position: v3(60.0, 40.0, 8.0),
+            color: v3(0.0, 0.3, 1.5),
+            radius: 40.0,
+        };
+        let ambient = v3(0.1, 0.1, 0.15);
+        let rotated = |degrees: f32, size: f32| {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            let x_axis = v2(cos, sin) * size;
+            let y_axis = v2(-sin, cos) * size;
+            (v2(32.0, 32.0) - x_axis * 0.5 - y_axis * 0.5, x_axis, y_axis)
+        };
+        // Note(voided): the ball is round, so turning it may not move the light on it
+        let cases = [
+            ("lit_one_light", &[warm][..], rotated(0.0, 48.0)),
+            ("lit_two_lights", &[warm, blue][..], rotated(0.0, 48.0)),
+            ("lit_turned", &[warm, blue][..], rotated(135.0, 40.0)),
+        ];
+        for (name, lights, (origin, x_axis, y_axis)) in cases {
+            let lighting = Lighting { ambient, lights };
+            let image = checkerboard_scene(|canvas| {
+                draw_bitmap_lit(canvas, &sprite, &normals, origin, x_axis, y_axis, &lighting)
+            });
+            check_golden(name, &image, Tolerance::EXACT);
+        }
+    }
+}
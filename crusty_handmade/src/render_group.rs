@@ -0,0 +1,632 @@
+// Push buffer between the game and the rasterizer: the game pushes what it wants drawn into a
+// render group in transient storage, the renderer sorts it and draws it into whatever it gets.
+// Note(voided): nothing in here touches pixels until render, so the same group can get drawn
+// into a different backend, split across threads or drawn into a render target.
+
+use std::mem::MaybeUninit;
+use std::sync::Mutex;
+
+use utility::font::{Alignment, Font};
+use utility::image::{Bitmap, BitmapView};
+use utility::math::{v2, V2, V4};
+use utility::work_queue::WorkQueue;
+
+use crate::lighting::Lighting;
+use crate::memory::MemoryArena;
+use crate::pixels::PixelsMut;
+use crate::rasterizer::{
+    clear, composite, draw_bitmap, draw_bitmap_lit, draw_bitmap_region, draw_bitmap_transformed,
+    draw_rectangle, Canvas,
+};
+use crate::simd::gradient_span;
+
+/// Maps the coordinates commands get pushed in to pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub offset: V2,
+    pub scale: f32,
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        offset: v2(0.0, 0.0),
+        scale: 1.0,
+    };
+
+    pub fn apply(&self, point: V2) -> V2 {
+        self.offset + point * self.scale
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RenderCommand<'a> {
+    Clear {
+        color: V4,
+    },
+    /// The gradient the game has been drawing since day 4, offset by pixels.
+    WeirdGradient {
+        x_offset: i32,
+        y_offset: i32,
+    },
+    Rectangle {
+        min: V2,
+        max: V2,
+        color: V4,
+    },
+    /// Note(voided): only moved by the transform, so it stays pixel exact and takes the fast
+    /// path. Use TransformedBitmap for anything that scales.
+    Bitmap {
+        bitmap: &'a Bitmap,
+        position: V2,
+    },
+    /// Bilinear filtered onto the parallelogram the axes span from `origin`, see
+    /// [`draw_bitmap_transformed`].
+    TransformedBitmap {
+        bitmap: &'a Bitmap,
+        origin: V2,
+        x_axis: V2,
+        y_axis: V2,
+    },
+    /// A TransformedBitmap lit through its normal map, see [`draw_bitmap_lit`].
+    LitBitmap {
+        bitmap: &'a Bitmap,
+        normal_map: &'a Bitmap,
+        origin: V2,
+        x_axis: V2,
+        y_axis: V2,
+        lighting: Lighting<'a>,
+    },
+    /// A finished render target, or any other borrowed image, faded by `alpha` and placed like
+    /// a TransformedBitmap, see [`composite`].
+    Composite {
+        source: BitmapView<'a>,
+        origin: V2,
+        x_axis: V2,
+        y_axis: V2,
+        alpha: f32,
+    },
+    /// Laid out with the font's own metrics, like bitmaps the transform only moves it.
+    Text {
+        font: &'a Font,
+        text: &'a str,
+        position: V2,
+        alignment: Alignment,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RenderEntry<'a> {
+    /// Lower layers get drawn first, entries in the same layer in the order they were pushed.
+    pub layer: i32,
+    order: u32,
+    pub transform: Transform,
+    pub command: RenderCommand<'a>,
+}
+
+/// Fixed size list of commands, allocated out of an arena.
+pub struct RenderGroup<'a> {
+    entries: &'a mut [MaybeUninit<RenderEntry<'a>>],
+    count: usize,
+    dropped: usize,
+    transform: Transform,
+    layer: i32,
+}
+
+impl<'a> RenderGroup<'a> {
+    /// `None` if the arena doesn't have room for `capacity` commands.
+    pub fn new(arena: &mut MemoryArena<'a>, capacity: usize) -> Option<Self> {
+        Some(Self {
+            entries: arena.push_array(capacity)?,
+            count: 0,
+            dropped: 0,
+            transform: Transform::IDENTITY,
+            layer: 0,
+        })
+    }
+
+    /// Used for every command pushed after this.
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+    }
+
+    pub fn set_layer(&mut self, layer: i32) {
+        self.layer = layer;
+    }
+
+    /// Note(voided): a full group drops the command instead of failing the frame, the count of
+    /// dropped commands says the capacity needs bumping.
+    pub fn push(&mut self, command: RenderCommand<'a>) {
+        let Some(slot) = self.entries.get_mut(self.count) else {
+            self.dropped += 1;
+            return;
+        };
+        slot.write(RenderEntry {
+            layer: self.layer,
+            order: self.count as u32,
+            transform: self.transform,
+            command,
+        });
+        self.count += 1;
+    }
+
+    pub fn push_clear(&mut self, color: V4) {
+        self.push(RenderCommand::Clear { color });
+    }
+
+    pub fn push_rectangle(&mut self, min: V2, max: V2, color: V4) {
+        self.push(RenderCommand::Rectangle { min, max, color });
+    }
+
+    pub fn push_bitmap(&mut self, bitmap: &'a Bitmap, position: V2) {
+        self.push(RenderCommand::Bitmap { bitmap, position });
+    }
+
+    /// The bitmap's top left corner goes to `origin`, its top and left edges along the axes.
+    pub fn push_transformed_bitmap(
+        &mut self,
+        bitmap: &'a Bitmap,
+        origin: V2,
+        x_axis: V2,
+        y_axis: V2,
+    ) {
+        self.push(RenderCommand::TransformedBitmap {
+            bitmap,
+            origin,
+            x_axis,
+            y_axis,
+        });
+    }
+
+    /// The lights have to outlive the group, like everything else pushed.
+    pub fn push_lit_bitmap(
+        &mut self,
+        bitmap: &'a Bitmap,
+        normal_map: &'a Bitmap,
+        origin: V2,
+        x_axis: V2,
+        y_axis: V2,
+        lighting: Lighting<'a>,
+    ) {
+        self.push(RenderCommand::LitBitmap {
+            bitmap,
+            normal_map,
+            origin,
+            x_axis,
+            y_axis,
+            lighting,
+        });
+    }
+
+    /// `source` is usually a [`crate::render_target::RenderTarget`] that has been drawn into
+    /// and finished.
+    pub fn push_composite(
+        &mut self,
+        source: BitmapView<'a>,
+        origin: V2,
+        x_axis: V2,
+        y_axis: V2,
+        alpha: f32,
+    ) {
+        self.push(RenderCommand::Composite {
+            source,
+            origin,
+            x_axis,
+            y_axis,
+            alpha,
+        });
+    }
+
+    /// `text` has to outlive the group, strings built during the frame can go into the arena
+    /// with [`MemoryArena::push_str`].
+    pub fn push_text(&mut self, font: &'a Font, text: &'a str, position: V2, alignment: Alignment) {
+        self.push(RenderCommand::Text {
+            font,
+            text,
+            position,
+            alignment,
+        });
+    }
+
+    pub fn entries(&self) -> &[RenderEntry<'a>] {
+        // Safety: push initialized the first `count` entries
+        unsafe { std::slice::from_raw_parts(self.entries.as_ptr().cast(), self.count) }
+    }
+
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Puts the entries in the order they get drawn in.
+    pub fn sort(&mut self) {
+        // Safety: as in entries
+        let entries = unsafe {
+            std::slice::from_raw_parts_mut(
+                self.entries.as_mut_ptr().cast::<RenderEntry<'a>>(),
+                self.count,
+            )
+        };
+        // Note(voided): the push order is part of the key, so the unstable sort is stable and
+        // doesn't need to allocate
+        entries.sort_unstable_by_key(|entry| (entry.layer, entry.order));
+    }
+
+    /// Draws the sorted entries into the canvas, anything outside of it gets clipped.
+    pub fn render(&self, canvas: &mut Canvas) {
+        for entry in self.entries() {
+            execute(canvas, entry);
+        }
+    }
+
+    /// Like [`Self::render`], but splits the pixels into tiles of `tile_height` rows that the
+    /// queue's threads draw at the same time, this one included.
+    /// Note(voided): the rasterizer only looks at a pixel's own position, so the result is the
+    /// same as drawing everything at once.
+    pub fn render_tiled(&self, pixels: PixelsMut, tile_height: usize, work_queue: &WorkQueue) {
+        self.render_tiled_with(pixels, tile_height, work_queue, &|_, _| {});
+    }
+
+    /// Like [`Self::render_tiled`], and hands every finished tile and its index to `finish_tile`
+    /// on the thread that drew it, while its pixels are still in the cache.
+    pub fn render_tiled_with(
+        &self,
+        pixels: PixelsMut,
+        tile_height: usize,
+        work_queue: &WorkQueue,
+        finish_tile: &(dyn Fn(usize, &PixelsMut) + Sync),
+    ) {
+        let tiles = Mutex::new(pixels.into_bands(tile_height).enumerate());
+        let work = || loop {
+            // the lock is only held to take the next tile
+            let next_tile = tiles.lock().expect("no panics while locked").next();
+            let Some((index, tile)) = next_tile else {
+                break;
+            };
+            let origin_y = (index * tile_height.max(1)) as i32;
+            let mut canvas = Canvas::with_origin(tile, 0, origin_y);
+            self.render(&mut canvas);
+            finish_tile(index, canvas.pixels());
+        };
+
+        // every thread takes tiles until there are none left
+        work_queue.for_each(work_queue.thread_count(), &|_| work());
+    }
+}
+
+fn render_weird_gradient(canvas: &mut Canvas, x_offset: i32, y_offset: i32) {
+    let bounds = canvas.bounds();
+    for (y, row) in (bounds.min_y..).zip(canvas.pixels().rows_mut()) {
+        let green = (y + y_offset) as u32 & 0xFF;
+        gradient_span(row, bounds.min_x + x_offset, green);
+    }
+}
+
+fn execute(canvas: &mut Canvas, entry: &RenderEntry) {
+    let transform = &entry.transform;
+    match entry.command {
+        RenderCommand::Clear { color } => clear(canvas, color),
+        RenderCommand::WeirdGradient { x_offset, y_offset } => {
+            render_weird_gradient(canvas, x_offset, y_offset)
+        }
+        RenderCommand::Rectangle { min, max, color } => {
+            draw_rectangle(canvas, transform.apply(min), transform.apply(max), color)
+        }
+        RenderCommand::Bitmap { bitmap, position } => {
+            draw_bitmap(canvas, bitmap, transform.apply(position))
+        }
+        RenderCommand::TransformedBitmap {
+            bitmap,
+            origin,
+            x_axis,
+            y_axis,
+        } => draw_bitmap_transformed(
+            canvas,
+            bitmap,
+            transform.apply(origin),
+            x_axis * transform.scale,
+            y_axis * transform.scale,
+        ),
+        RenderCommand::LitBitmap {
+            bitmap,
+            normal_map,
+            origin,
+            x_axis,
+            y_axis,
+            lighting,
+        } => draw_bitmap_lit(
+            canvas,
+            bitmap,
+            normal_map,
+            transform.apply(origin),
+            x_axis * transform.scale,
+            y_axis * transform.scale,
+            &lighting,
+        ),
+        RenderCommand::Composite {
+            source,
+            origin,
+            x_axis,
+            y_axis,
+            alpha,
+        } => composite(
+            canvas,
+            source,
+            transform.apply(origin),
+            x_axis * transform.scale,
+            y_axis * transform.scale,
+            alpha,
+        ),
+        // Note(voided): every tile lays the text out again, which is cheap next to drawing it
+        RenderCommand::Text {
+            font,
+            text,
+            position,
+            alignment,
+        } => {
+            for placed in font.layout(text, transform.apply(position), alignment) {
+                draw_bitmap_region(canvas, font.atlas(), placed.glyph.atlas, placed.position);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod render_group_tests {
+    use std::mem::MaybeUninit;
+
+    use utility::font::{Alignment, Font, FontMetrics, Glyph};
+    use utility::image::Bitmap;
+    use utility::math::{v2, v4, Rect2i, V4};
+    use utility::work_queue::WorkQueue;
+
+    use crate::memory::MemoryArena;
+    use crate::pixels::PixelsMut;
+    use crate::rasterizer::{pack_color, Canvas};
+    use crate::render_group::{RenderCommand, RenderGroup, Transform};
+    use crate::render_target::RenderTarget;
+
+    const RED: V4 = v4(1.0, 0.0, 0.0, 1.0);
+    const BLUE: V4 = v4(0.0, 0.0, 1.0, 1.0);
+
+    fn render(group: &mut RenderGroup, width: usize, height: usize) -> Vec<u32> {
+        let mut memory = vec![0; width * height];
+        group.sort();
+        group.render(&mut Canvas::new(
+            PixelsMut::new(&mut memory, width, height, width).unwrap(),
+        ));
+        memory
+    }
+
+    #[test]
+    fn layers_sort_before_push_order() {
+        let mut memory = [MaybeUninit::new(0u8); 1024];
+        let mut arena = MemoryArena::new(&mut memory);
+        let mut group = RenderGroup::new(&mut arena, 8).unwrap();
+
+        group.set_layer(1);
+        group.push_rectangle(v2(0.0, 0.0), v2(2.0, 1.0), RED);
+        group.set_layer(0);
+        group.push_clear(v4(0.0, 0.0, 0.0, 1.0));
+        group.push_rectangle(v2(1.0, 0.0), v2(3.0, 1.0), BLUE);
+
+        let pixels = render(&mut group, 3, 1);
+        let (red, blue) = (pack_color(RED), pack_color(BLUE));
+        assert_eq!(pixels, [red, red, blue]);
+        assert!(matches!(
+            group.entries()[0].command,
+            RenderCommand::Clear { .. }
+        ));
+    }
+
+    #[test]
+    fn transform_applies_to_later_commands() {
+        let mut memory = [MaybeUninit::new(0u8); 1024];
+        let mut arena = MemoryArena::new(&mut memory);
+        let mut group = RenderGroup::new(&mut arena, 8).unwrap();
+        let sprite = Bitmap::new(1, 1, vec![0xFFFFFFFF]).unwrap();
+
+        group.push_rectangle(v2(0.0, 0.0), v2(1.0, 1.0), RED);
+        group.set_transform(Transform {
+            offset: v2(1.0, 1.0),
+            scale: 2.0,
+        });
+        group.push_rectangle(v2(0.0, 0.0), v2(1.0, 1.0), BLUE);
+        group.push_bitmap(&sprite, v2(0.0, 0.5));
+
+        let (red, blue) = (pack_color(RED), pack_color(BLUE));
+        #[rustfmt::skip]
+        assert_eq!(render(&mut group, 4, 4), [
+            red, 0, 0, 0,
+            0, blue, blue, 0,
+            0, 0xFFFFFFFF, blue, 0,
+            0, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn text_draws_glyphs_from_the_atlas() {
+        // a 1x2 bar for 'i' and a 2x1 one for '-', on a baseline 2 pixels down
+        let white = 0xFFFFFFFF;
+        let atlas = Bitmap::new(3, 2, vec![white, white, white, white, 0, 0]).unwrap();
+        let metrics = FontMetrics {
+            line_height: 3.0,
+            ascent: 2.0,
+            glyphs: vec![
+                (
+                    'i',
+                    Glyph {
+                        atlas: Rect2i::new(0, 0, 1, 2),
+                        offset: v2(0.0, -2.0),
+                        advance: 2.0,
+                    },
+                ),
+                (
+                    '-',
+                    Glyph {
+                        atlas: Rect2i::new(1, 0, 3, 1),
+                        offset: v2(0.0, -1.0),
+                        advance: 3.0,
+                    },
+                ),
+            ],
+            kerning: vec![('i', '-', -1.0)],
+        };
+        let font = Font::new(atlas, metrics).unwrap();
+
+        let mut memory = [MaybeUninit::new(0u8); 1024];
+        let mut arena = MemoryArena::new(&mut memory);
+        let text = arena.push_str("i-\n-i").unwrap();
+        let mut group = RenderGroup::new(&mut arena, 2).unwrap();
+        group.set_transform(Transform {
+            offset: v2(1.0, 0.0),
+            scale: 1.0,
+        });
+        group.push_text(&font, text, v2(4.0, 0.0), Alignment::Right);
+
+        #[rustfmt::skip]
+        assert_eq!(render(&mut group, 5, 5), [
+            0, white, 0, 0, 0,
+            0, white, white, white, 0,
+            0, 0, 0, 0, 0,
+            0, 0, 0, white, 0,
+            white, white, 0, white, 0,
+        ]);
+    }
+
+    #[test]
+    fn transformed_bitmaps_get_scaled() {
+        let mut memory = [MaybeUninit::new(0u8); 1024];
+        let mut arena = MemoryArena::new(&mut memory);
+        let mut group = RenderGroup::new(&mut arena, 2).unwrap();
+        let white = 0xFFFFFFFF;
+        let sprite = Bitmap::new(2, 2, vec![white; 4]).unwrap();
+
+        group.set_transform(Transform {
+            offset: v2(1.0, 1.0),
+            scale: 2.0,
+        });
+        group.push_transformed_bitmap(&sprite, v2(0.0, 0.0), v2(1.0, 0.0), v2(0.0, 1.0));
+
+        #[rustfmt::skip]
+        assert_eq!(render(&mut group, 4, 4), [
+            0, 0, 0, 0,
+            0, white, white, 0,
+            0, white, white, 0,
+            0, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn targets_get_composited_with_alpha() {
+        let mut memory = [MaybeUninit::new(0u8); 1024];
+        let mut arena = MemoryArena::new(&mut memory);
+        let mut target = RenderTarget::new(&mut arena, 2, 2).unwrap();
+        let mut offscreen = RenderGroup::new(&mut arena, 1).unwrap();
+        offscreen.push_rectangle(v2(0.0, 0.0), v2(2.0, 1.0), RED);
+        offscreen.sort();
+        offscreen.render(&mut target.canvas());
+        let image = target.finish();
+
+        let mut group = RenderGroup::new(&mut arena, 3).unwrap();
+        let black = v4(0.0, 0.0, 0.0, 1.0);
+        group.push_clear(black);
+        group.set_transform(Transform {
+            offset: v2(1.0, 1.0),
+            scale: 2.0,
+        });
+        group.push_composite(image, v2(0.0, 0.0), v2(1.0, 0.0), v2(0.0, 1.0), 1.0);
+        group.push_composite(image, v2(0.0, 0.5), v2(1.0, 0.0), v2(0.0, 1.0), 0.5);
+
+        // the target's empty bottom row leaves the canvas alone
+        let (red, black) = (pack_color(RED), pack_color(black));
+        let half = pack_color(v4(0.5, 0.0, 0.0, 1.0));
+        #[rustfmt::skip]
+        assert_eq!(render(&mut group, 4, 4), [
+            black, black, black, black,
+            black, red, red, black,
+            black, half, half, black,
+            black, black, black, black,
+        ]);
+    }
+
+    #[test]
+    fn full_group_drops_commands() {
+        let mut memory = [MaybeUninit::new(0u8); 1024];
+        let mut arena = MemoryArena::new(&mut memory);
+        let mut group = RenderGroup::new(&mut arena, 2).unwrap();
+        for _ in 0..5 {
+            group.push_clear(RED);
+        }
+        assert_eq!(group.entries().len(), 2);
+        assert_eq!(group.dropped(), 3);
+
+        let mut tiny = [MaybeUninit::new(0u8); 8];
+        assert!(RenderGroup::new(&mut MemoryArena::new(&mut tiny), 2).is_none());
+    }
+
+    #[test]
+    fn gradient_follows_canvas_origin() {
+        let mut memory = [MaybeUninit::new(0u8); 1024];
+        let mut arena = MemoryArena::new(&mut memory);
+        let mut group = RenderGroup::new(&mut arena, 1).unwrap();
+        group.push(RenderCommand::WeirdGradient {
+            x_offset: 1,
+            y_offset: 2,
+        });
+
+        let mut pixels = vec![0; 2];
+        group.render(&mut Canvas::with_origin(
+            PixelsMut::new(&mut pixels, 1, 2, 1).unwrap(),
+            3,
+            4,
+        ));
+        assert_eq!(pixels, [0x0604, 0x0704]);
+    }
+
+    #[test]
+    fn tiled_matches_single_threaded() {
+        let mut memory = [MaybeUninit::new(0u8); 4096];
+        let mut arena = MemoryArena::new(&mut memory);
+        let mut group = RenderGroup::new(&mut arena, 32).unwrap();
+        let sprite = Bitmap::new(
+            3,
+            2,
+            vec![
+                0x80402010, 0xFF00FF00, 0, 0x10101010, 0xFFFFFFFF, 0x7F7F0000,
+            ],
+        )
+        .unwrap();
+
+        group.push(RenderCommand::WeirdGradient {
+            x_offset: 3,
+            y_offset: -7,
+        });
+        group.push_rectangle(v2(-3.0, 2.5), v2(11.2, 9.7), RED);
+        group.push_bitmap(&sprite, v2(4.4, 6.5));
+        group.set_transform(Transform {
+            offset: v2(2.0, 13.0),
+            scale: 1.5,
+        });
+        group.push_rectangle(v2(0.3, 0.0), v2(5.0, 3.1), v4(0.2, 0.4, 0.6, 1.0));
+        group.push_bitmap(&sprite, v2(1.0, 1.0));
+        group.sort();
+
+        let (width, height) = (17, 23);
+        let mut expected = vec![0; width * height];
+        group.render(&mut Canvas::new(
+            PixelsMut::new(&mut expected, width, height, width).unwrap(),
+        ));
+
+        for (tile_height, thread_count) in [(1, 4), (4, 3), (5, 1), (7, 8), (100, 2)] {
+            let mut actual = vec![0; width * height];
+            group.render_tiled(
+                PixelsMut::new(&mut actual, width, height, width).unwrap(),
+                tile_height,
+                &WorkQueue::new(thread_count - 1),
+            );
+            assert_eq!(
+                actual, expected,
+                "{tile_height} rows, {thread_count} threads"
+            );
+        }
+    }
+}
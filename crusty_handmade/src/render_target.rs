@@ -0,0 +1,87 @@
+// Offscreen images to render into instead of the back buffer, for minimaps, cached ground chunks
+// and ui layers. A target gets its pixels out of an arena, a render group draws into it like into
+// the back buffer, and once it's finished a Composite command blends it into another target or the
+// back buffer, faded and transformed like any other image.
+// Note(voided): the pixels are premultiplied 0xAARRGGBB like bitmaps, and start out transparent,
+// so only what got drawn shows up when compositing. Clears and rectangles write their color as
+// it is, translucent ones need a premultiplied color to come out right.
+
+use utility::image::BitmapView;
+
+use crate::memory::MemoryArena;
+use crate::pixels::PixelsMut;
+use crate::rasterizer::Canvas;
+
+pub struct RenderTarget<'a> {
+    pixels: &'a mut [u32],
+    width: usize,
+    height: usize,
+}
+
+impl<'a> RenderTarget<'a> {
+    /// `None` if the arena doesn't have room for the pixels.
+    pub fn new(arena: &mut MemoryArena<'a>, width: usize, height: usize) -> Option<Self> {
+        Some(Self {
+            pixels: arena.push_filled(width.checked_mul(height)?, 0)?,
+            width,
+            height,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// For [`crate::render_group::RenderGroup::render_tiled`].
+    pub fn pixels(&mut self) -> PixelsMut<'_> {
+        PixelsMut::new(self.pixels, self.width, self.height, self.width)
+            .expect("the target has width * height pixels")
+    }
+
+    /// The top left pixel is at 0, 0.
+    pub fn canvas(&mut self) -> Canvas<'_> {
+        Canvas::new(self.pixels())
+    }
+
+    /// Done drawing, what's left is an image that lives as long as the arena.
+    pub fn finish(self) -> BitmapView<'a> {
+        BitmapView::new(self.width, self.height, self.pixels)
+            .expect("the target has width * height pixels")
+    }
+}
+
+#[cfg(test)]
+mod render_target_tests {
+    use std::mem::MaybeUninit;
+
+    use utility::math::{v2, v4};
+
+    use crate::memory::MemoryArena;
+    use crate::rasterizer::{draw_rectangle, pack_color};
+    use crate::render_target::RenderTarget;
+
+    #[test]
+    fn targets_start_out_transparent() {
+        let mut memory = [MaybeUninit::new(0xFFu8); 256];
+        let mut arena = MemoryArena::new(&mut memory);
+        assert!(RenderTarget::new(&mut arena, 9, 8).is_none());
+        assert!(RenderTarget::new(&mut arena, usize::MAX, 2).is_none());
+
+        let mut target = RenderTarget::new(&mut arena, 8, 4).unwrap();
+        let red = v4(1.0, 0.0, 0.0, 1.0);
+        draw_rectangle(&mut target.canvas(), v2(2.0, 1.0), v2(4.0, 3.0), red);
+
+        let image = target.finish();
+        assert_eq!((image.width(), image.height()), (8, 4));
+        assert_eq!(image.pixel(0, 0), Some(0));
+        assert_eq!(image.pixel(3, 2), Some(pack_color(red)));
+        assert_eq!(
+            image.pixels().iter().filter(|&&pixel| pixel != 0).count(),
+            4
+        );
+    }
+}
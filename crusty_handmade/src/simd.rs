@@ -0,0 +1,479 @@
+// The innermost pixel loops, with SSE2 and AVX2 versions picked at runtime and a scalar version
+// that is the reference for what they have to output.
+// Note(voided): every version has to produce exactly the same bits as the scalar one, the tests
+// check them against each other.
+
+use utility::color::SrgbTables;
+
+/// Which version of the loops to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionSet {
+    Scalar,
+    Sse2,
+    Avx2,
+}
+
+impl InstructionSet {
+    pub const ALL: [Self; 3] = [Self::Scalar, Self::Sse2, Self::Avx2];
+
+    pub fn is_supported(self) -> bool {
+        match self {
+            Self::Scalar => true,
+            #[cfg(target_arch = "x86_64")]
+            Self::Sse2 => std::arch::is_x86_feature_detected!("sse2"),
+            #[cfg(target_arch = "x86_64")]
+            Self::Avx2 => std::arch::is_x86_feature_detected!("avx2"),
+            #[cfg(not(target_arch = "x86_64"))]
+            _ => false,
+        }
+    }
+
+    /// The fastest one this cpu has.
+    /// Note(voided): the detection is cached by std, so this is cheap enough to ask per span.
+    pub fn best() -> Self {
+        [Self::Avx2, Self::Sse2]
+            .into_iter()
+            .find(|set| set.is_supported())
+            .unwrap_or(Self::Scalar)
+    }
+}
+
+const INVERSE_255: f32 = 1.0 / 255.0;
+
+/// Premultiplied "over", `source` on top of `dest`, blended in linear space.
+pub fn blend_premultiplied(source: u32, dest: u32) -> u32 {
+    blend_pixel(SrgbTables::get(), source, dest)
+}
+
+/// Like [`blend_premultiplied`], with the tables at hand for loops over many pixels.
+/// Note(voided): the SIMD versions do the same float operations in the same order, so they
+/// round the same way. No fused multiply adds in here.
+pub fn blend_pixel(tables: &SrgbTables, source: u32, dest: u32) -> u32 {
+    let source_alpha = source >> 24;
+    let inverse_alpha = (255 - source_alpha) as f32 * INVERSE_255;
+    let alpha = (source_alpha as f32 + (dest >> 24) as f32 * inverse_alpha + 0.5) as u32;
+    let channel = |shift: u32| {
+        let linear = tables.decode(source >> shift) + tables.decode(dest >> shift) * inverse_alpha;
+        tables.encode(linear) << shift
+    };
+    alpha.min(0xFF) << 24 | channel(16) | channel(8) | channel(0)
+}
+
+/// The pixel of the weird gradient at `x`, `green` is already offset and wrapped.
+fn gradient_pixel(x: i32, green: u32) -> u32 {
+    green << 8 | (x as u32 & 0xFF)
+}
+
+pub fn fill_span(span: &mut [u32], color: u32) {
+    fill_span_with(InstructionSet::best(), span, color);
+}
+
+/// `span[i]` gets the gradient for `first_x + i`, see [`gradient_pixel`].
+pub fn gradient_span(span: &mut [u32], first_x: i32, green: u32) {
+    gradient_span_with(InstructionSet::best(), span, first_x, green);
+}
+
+/// Blends `source` on top of `dest`, as far as the shorter one goes.
+pub fn blend_span(dest: &mut [u32], source: &[u32]) {
+    blend_span_with(InstructionSet::best(), dest, source);
+}
+
+/// Like [`fill_span`] with a specific instruction set, unsupported ones fall back to scalar.
+pub fn fill_span_with(set: InstructionSet, span: &mut [u32], color: u32) {
+    match set {
+        // Safety: is_supported checked the cpu has the instructions
+        #[cfg(target_arch = "x86_64")]
+        InstructionSet::Avx2 if set.is_supported() => unsafe { x86::fill_span_avx2(span, color) },
+        #[cfg(target_arch = "x86_64")]
+        InstructionSet::Sse2 if set.is_supported() => unsafe { x86::fill_span_sse2(span, color) },
+        _ => span.fill(color),
+    }
+}
+
+pub fn gradient_span_with(set: InstructionSet, span: &mut [u32], first_x: i32, green: u32) {
+    match set {
+        // Safety: as in fill_span_with
+        #[cfg(target_arch = "x86_64")]
+        InstructionSet::Avx2 if set.is_supported() => unsafe {
+            x86::gradient_span_avx2(span, first_x, green)
+        },
+        #[cfg(target_arch = "x86_64")]
+        InstructionSet::Sse2 if set.is_supported() => unsafe {
+            x86::gradient_span_sse2(span, first_x, green)
+        },
+        _ => gradient_span_scalar(span, first_x, green),
+    }
+}
+
+/// Note(voided): blending looks the channels up in the sRGB tables. SSE2 has no gathers, so its
+/// version does the lookups one at a time and only the math in between four at a time.
+pub fn blend_span_with(set: InstructionSet, dest: &mut [u32], source: &[u32]) {
+    let len = dest.len().min(source.len());
+    let (dest, source) = (&mut dest[..len], &source[..len]);
+    let tables = SrgbTables::get();
+    match set {
+        // Safety: as in fill_span_with
+        #[cfg(target_arch = "x86_64")]
+        InstructionSet::Avx2 if set.is_supported() => unsafe {
+            x86::blend_span_avx2(tables, dest, source)
+        },
+        #[cfg(target_arch = "x86_64")]
+        InstructionSet::Sse2 if set.is_supported() => unsafe {
+            x86::blend_span_sse2(tables, dest, source)
+        },
+        _ => blend_span_scalar(tables, dest, source),
+    }
+}
+
+fn gradient_span_scalar(span: &mut [u32], first_x: i32, green: u32) {
+    for (x, pixel) in (0..).map(|i| first_x.wrapping_add(i)).zip(span.iter_mut()) {
+        *pixel = gradient_pixel(x, green);
+    }
+}
+
+fn blend_span_scalar(tables: &SrgbTables, dest: &mut [u32], source: &[u32]) {
+    for (dest, &source) in dest.iter_mut().zip(source) {
+        *dest = blend_pixel(tables, source, *dest);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use utility::color::{SrgbTables, LINEAR_STEPS};
+
+    use crate::simd::{blend_span_scalar, gradient_span_scalar, INVERSE_255};
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn fill_span_sse2(span: &mut [u32], color: u32) {
+        let color_x4 = _mm_set1_epi32(color as i32);
+        let mut chunks = span.chunks_exact_mut(4);
+        for chunk in &mut chunks {
+            _mm_storeu_si128(chunk.as_mut_ptr().cast(), color_x4);
+        }
+        chunks.into_remainder().fill(color);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn fill_span_avx2(span: &mut [u32], color: u32) {
+        let color_x8 = _mm256_set1_epi32(color as i32);
+        let mut chunks = span.chunks_exact_mut(8);
+        for chunk in &mut chunks {
+            _mm256_storeu_si256(chunk.as_mut_ptr().cast(), color_x8);
+        }
+        chunks.into_remainder().fill(color);
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn gradient_span_sse2(span: &mut [u32], first_x: i32, green: u32) {
+        let green_x4 = _mm_set1_epi32((green << 8) as i32);
+        let blue_mask = _mm_set1_epi32(0xFF);
+        let step = _mm_set1_epi32(4);
+        let mut x = _mm_setr_epi32(
+            first_x,
+            first_x.wrapping_add(1),
+            first_x.wrapping_add(2),
+            first_x.wrapping_add(3),
+        );
+
+        let mut chunks = span.chunks_exact_mut(4);
+        for chunk in &mut chunks {
+            let pixels = _mm_or_si128(green_x4, _mm_and_si128(x, blue_mask));
+            _mm_storeu_si128(chunk.as_mut_ptr().cast(), pixels);
+            x = _mm_add_epi32(x, step);
+        }
+        let done = (span.len() / 4 * 4) as i32;
+        let remainder = span.chunks_exact_mut(4).into_remainder();
+        gradient_span_scalar(remainder, first_x.wrapping_add(done), green);
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn gradient_span_avx2(span: &mut [u32], first_x: i32, green: u32) {
+        let green_x8 = _mm256_set1_epi32((green << 8) as i32);
+        let blue_mask = _mm256_set1_epi32(0xFF);
+        let step = _mm256_set1_epi32(8);
+        let mut x = _mm256_add_epi32(
+            _mm256_set1_epi32(first_x),
+            _mm256_setr_epi32(0, 1, 2, 3, 4, 5, 6, 7),
+        );
+
+        let mut chunks = span.chunks_exact_mut(8);
+        for chunk in &mut chunks {
+            let pixels = _mm256_or_si256(green_x8, _mm256_and_si256(x, blue_mask));
+            _mm256_storeu_si256(chunk.as_mut_ptr().cast(), pixels);
+            x = _mm256_add_epi32(x, step);
+        }
+        let done = (span.len() / 8 * 8) as i32;
+        let remainder = span.chunks_exact_mut(8).into_remainder();
+        gradient_span_scalar(remainder, first_x.wrapping_add(done), green);
+    }
+
+    /// One channel of four pixels, decoded, blended and encoded again.
+    #[target_feature(enable = "sse2")]
+    unsafe fn blend_channel_sse2<const SHIFT: u32>(
+        tables: &SrgbTables,
+        source: &[u32],
+        dest: &[u32],
+        inverse_alpha: __m128,
+    ) -> __m128i {
+        let decode = |pixels: &[u32]| {
+            _mm_setr_ps(
+                tables.decode(pixels[0] >> SHIFT),
+                tables.decode(pixels[1] >> SHIFT),
+                tables.decode(pixels[2] >> SHIFT),
+                tables.decode(pixels[3] >> SHIFT),
+            )
+        };
+        let linear = _mm_add_ps(decode(source), _mm_mul_ps(decode(dest), inverse_alpha));
+        let linear = _mm_min_ps(_mm_max_ps(linear, _mm_setzero_ps()), _mm_set1_ps(1.0));
+        let index = _mm_cvttps_epi32(_mm_add_ps(
+            _mm_mul_ps(linear, _mm_set1_ps((LINEAR_STEPS - 1) as f32)),
+            _mm_set1_ps(0.5),
+        ));
+
+        let mut indices = [0i32; 4];
+        _mm_storeu_si128(indices.as_mut_ptr().cast(), index);
+        let [a, b, c, d] = indices.map(|index| (tables.to_srgb[index as usize] << SHIFT) as i32);
+        _mm_setr_epi32(a, b, c, d)
+    }
+
+    /// Four pixels at a time, see blend_pixel for what it has to match.
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn blend_span_sse2(tables: &SrgbTables, dest: &mut [u32], source: &[u32]) {
+        let mut dest_chunks = dest.chunks_exact_mut(4);
+        let mut source_chunks = source.chunks_exact(4);
+        for (dest, source) in (&mut dest_chunks).zip(&mut source_chunks) {
+            let source_x4 = _mm_loadu_si128(source.as_ptr().cast());
+            let dest_x4 = _mm_loadu_si128(dest.as_ptr().cast());
+
+            let source_alpha = _mm_srli_epi32::<24>(source_x4);
+            let inverse_alpha = _mm_mul_ps(
+                _mm_cvtepi32_ps(_mm_sub_epi32(_mm_set1_epi32(255), source_alpha)),
+                _mm_set1_ps(INVERSE_255),
+            );
+            let alpha = _mm_add_ps(
+                _mm_add_ps(
+                    _mm_cvtepi32_ps(source_alpha),
+                    _mm_mul_ps(
+                        _mm_cvtepi32_ps(_mm_srli_epi32::<24>(dest_x4)),
+                        inverse_alpha,
+                    ),
+                ),
+                _mm_set1_ps(0.5),
+            );
+            // no _mm_min_epi32 before SSE4.1
+            let alpha = _mm_cvttps_epi32(alpha);
+            let max_alpha = _mm_set1_epi32(0xFF);
+            let too_big = _mm_cmpgt_epi32(alpha, max_alpha);
+            let alpha = _mm_or_si128(
+                _mm_and_si128(too_big, max_alpha),
+                _mm_andnot_si128(too_big, alpha),
+            );
+
+            let blended = _mm_or_si128(
+                _mm_or_si128(
+                    _mm_slli_epi32::<24>(alpha),
+                    blend_channel_sse2::<16>(tables, source, dest, inverse_alpha),
+                ),
+                _mm_or_si128(
+                    blend_channel_sse2::<8>(tables, source, dest, inverse_alpha),
+                    blend_channel_sse2::<0>(tables, source, dest, inverse_alpha),
+                ),
+            );
+            _mm_storeu_si128(dest.as_mut_ptr().cast(), blended);
+        }
+        blend_span_scalar(
+            tables,
+            dest_chunks.into_remainder(),
+            source_chunks.remainder(),
+        );
+    }
+
+    /// One channel of eight pixels, decoded, blended and encoded again.
+    #[target_feature(enable = "avx2")]
+    unsafe fn blend_channel_avx2<const SHIFT: i32>(
+        tables: &SrgbTables,
+        source: __m256i,
+        dest: __m256i,
+        inverse_alpha: __m256,
+    ) -> __m256i {
+        let mask = _mm256_set1_epi32(0xFF);
+        let source = _mm256_and_si256(_mm256_srli_epi32::<SHIFT>(source), mask);
+        let dest = _mm256_and_si256(_mm256_srli_epi32::<SHIFT>(dest), mask);
+        let source = _mm256_i32gather_ps::<4>(tables.to_linear.as_ptr(), source);
+        let dest = _mm256_i32gather_ps::<4>(tables.to_linear.as_ptr(), dest);
+
+        let linear = _mm256_add_ps(source, _mm256_mul_ps(dest, inverse_alpha));
+        let linear = _mm256_min_ps(
+            _mm256_max_ps(linear, _mm256_setzero_ps()),
+            _mm256_set1_ps(1.0),
+        );
+        let index = _mm256_cvttps_epi32(_mm256_add_ps(
+            _mm256_mul_ps(linear, _mm256_set1_ps((LINEAR_STEPS - 1) as f32)),
+            _mm256_set1_ps(0.5),
+        ));
+        let srgb = _mm256_i32gather_epi32::<4>(tables.to_srgb.as_ptr().cast(), index);
+        _mm256_slli_epi32::<SHIFT>(srgb)
+    }
+
+    /// Eight pixels at a time, see blend_pixel for what it has to match.
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn blend_span_avx2(tables: &SrgbTables, dest: &mut [u32], source: &[u32]) {
+        let mut dest_chunks = dest.chunks_exact_mut(8);
+        let mut source_chunks = source.chunks_exact(8);
+        for (dest, source) in (&mut dest_chunks).zip(&mut source_chunks) {
+            let source_x8 = _mm256_loadu_si256(source.as_ptr().cast());
+            let dest_x8 = _mm256_loadu_si256(dest.as_ptr().cast());
+
+            let source_alpha = _mm256_srli_epi32::<24>(source_x8);
+            let inverse_alpha = _mm256_mul_ps(
+                _mm256_cvtepi32_ps(_mm256_sub_epi32(_mm256_set1_epi32(255), source_alpha)),
+                _mm256_set1_ps(INVERSE_255),
+            );
+            let alpha = _mm256_add_ps(
+                _mm256_add_ps(
+                    _mm256_cvtepi32_ps(source_alpha),
+                    _mm256_mul_ps(
+                        _mm256_cvtepi32_ps(_mm256_srli_epi32::<24>(dest_x8)),
+                        inverse_alpha,
+                    ),
+                ),
+                _mm256_set1_ps(0.5),
+            );
+            let alpha = _mm256_min_epi32(_mm256_cvttps_epi32(alpha), _mm256_set1_epi32(0xFF));
+
+            let blended = _mm256_or_si256(
+                _mm256_or_si256(
+                    _mm256_slli_epi32::<24>(alpha),
+                    blend_channel_avx2::<16>(tables, source_x8, dest_x8, inverse_alpha),
+                ),
+                _mm256_or_si256(
+                    blend_channel_avx2::<8>(tables, source_x8, dest_x8, inverse_alpha),
+                    blend_channel_avx2::<0>(tables, source_x8, dest_x8, inverse_alpha),
+                ),
+            );
+            _mm256_storeu_si256(dest.as_mut_ptr().cast(), blended);
+        }
+        blend_span_scalar(
+            tables,
+            dest_chunks.into_remainder(),
+            source_chunks.remainder(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod simd_tests {
+    use crate::simd::{
+        blend_premultiplied, blend_span_with, fill_span_with, gradient_span_with, InstructionSet,
+    };
+
+    /// xorshift, so the inputs are the same every run.
+    fn random_pixels(count: usize, mut state: u32) -> Vec<u32> {
+        (0..count)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state
+            })
+            .collect()
+    }
+
+    fn supported() -> impl Iterator<Item = InstructionSet> {
+        InstructionSet::ALL
+            .into_iter()
+            .filter(|set| set.is_supported())
+    }
+
+    #[test]
+    fn scalar_is_always_there() {
+        assert!(InstructionSet::Scalar.is_supported());
+        assert!(InstructionSet::best().is_supported());
+    }
+
+    #[test]
+    fn fill_matches_scalar() {
+        for set in supported() {
+            for len in 0..40 {
+                let mut span = vec![0; len + 2];
+                fill_span_with(set, &mut span[1..len + 1], 0xDEADBEEF);
+                let mut expected = vec![0xDEADBEEF; len + 2];
+                expected[0] = 0;
+                expected[len + 1] = 0;
+                assert_eq!(span, expected, "{set:?} {len}");
+            }
+        }
+    }
+
+    #[test]
+    fn gradient_matches_scalar() {
+        for first_x in [0, -3, 250, i32::MAX - 5, i32::MIN] {
+            for len in [0, 1, 3, 4, 7, 8, 9, 31, 300] {
+                let mut expected = vec![0; len];
+                gradient_span_with(InstructionSet::Scalar, &mut expected, first_x, 0x7F);
+                for set in supported() {
+                    let mut span = vec![0; len];
+                    gradient_span_with(set, &mut span, first_x, 0x7F);
+                    assert_eq!(span, expected, "{set:?} {first_x} {len}");
+                }
+            }
+        }
+
+        let mut span = vec![0; 3];
+        gradient_span_with(InstructionSet::Scalar, &mut span, 254, 0x12);
+        assert_eq!(span, [0x12FE, 0x12FF, 0x1200]);
+    }
+
+    #[test]
+    fn blend_matches_scalar() {
+        // every alpha against every dest channel value
+        let mut source: Vec<u32> = (0..=255u32)
+            .flat_map(|alpha| (0..=255u32).map(move |value| (alpha << 24) | (value * 0x010101 / 2)))
+            .collect();
+        let mut dest: Vec<u32> = (0..=255u32)
+            .flat_map(|_| (0..=255u32).map(|value| 0xFF000000 | (value * 0x010101)))
+            .collect();
+        // and ones that aren't valid premultiplied colors, where the clamping kicks in
+        source.extend(random_pixels(1001, 0x12345));
+        dest.extend(random_pixels(1001, 0x6789A));
+
+        #[cfg(target_arch = "x86_64")]
+        assert!(
+            InstructionSet::Sse2.is_supported(),
+            "x86_64 always has SSE2"
+        );
+
+        // starting one pixel in, so the last chunk is a partial one for every width
+        for start in [0, 1] {
+            let mut expected = dest[start..].to_vec();
+            blend_span_with(InstructionSet::Scalar, &mut expected, &source[start..]);
+            for set in supported() {
+                let mut blended = dest[start..].to_vec();
+                blend_span_with(set, &mut blended, &source[start..]);
+                let first_difference = blended.iter().zip(&expected).position(|(a, b)| a != b);
+                assert_eq!(first_difference, None, "{set:?} from {start}");
+            }
+        }
+    }
+
+    #[test]
+    fn blend_reference() {
+        assert_eq!(blend_premultiplied(0xFF102030, 0xFFFFFFFF), 0xFF102030);
+        assert_eq!(blend_premultiplied(0x00000000, 0x80402010), 0x80402010);
+        // half of the blue shows through, which is a lot brighter than half of 0xFF in sRGB
+        assert_eq!(blend_premultiplied(0x80400000, 0xFF0000FF), 0xFF4000BB);
+        // invalid premultiplied source saturates
+        assert_eq!(blend_premultiplied(0x00FF0000, 0xFFFF0000), 0xFFFF0000);
+    }
+
+    #[test]
+    fn blend_uses_shorter_span() {
+        for set in supported() {
+            let mut dest = vec![0xFF000000; 10];
+            blend_span_with(set, &mut dest, &[0xFFFFFFFF; 9]);
+            assert_eq!(dest[8..], [0xFFFFFFFF, 0xFF000000], "{set:?}");
+        }
+    }
+}
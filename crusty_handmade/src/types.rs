@@ -1,5 +1,16 @@
 use std::ffi::c_void;
+use std::ops::{Index, IndexMut};
+use std::path::PathBuf;
 use utility::audio::SineAudioSource;
+use utility::font::Font;
+use utility::image::{Bitmap, BitmapView};
+use utility::math::Rect2i;
+use utility::work_queue::WorkQueue;
+
+pub use crate::dirty_rects::DirtyRects;
+use crate::dirty_rects::TileHashes;
+use crate::pixels::PixelsMut;
+use crate::players::{PlayerAssignments, PlayerEvent};
 
 pub struct GameMemory {
     pub is_initalized: bool,
@@ -7,6 +18,45 @@ pub struct GameMemory {
     pub permanent_storage: *mut c_void, // init to 0
     pub transient_storage_size: usize,
     pub transient_storage: *mut c_void,
+    /// Directory the game loads its data files from.
+    pub asset_root: PathBuf,
+    /// Threads to split the rendering across.
+    pub work_queue: WorkQueue,
+}
+
+/// Zeroed game memory on the heap, for running the game without the platform's page
+/// allocations, like headless runs and tests.
+pub struct HeapGameMemory {
+    // Note(voided): only here to keep the memory alive. u64 so the storage is aligned for
+    // anything the game puts in there.
+    _permanent_storage: Vec<u64>,
+    _transient_storage: Vec<u64>,
+    pub memory: GameMemory,
+}
+
+impl HeapGameMemory {
+    pub fn new(
+        permanent_storage_size: usize,
+        transient_storage_size: usize,
+        asset_root: impl Into<PathBuf>,
+    ) -> Self {
+        let mut permanent_storage = vec![0u64; permanent_storage_size.div_ceil(8)];
+        let mut transient_storage = vec![0u64; transient_storage_size.div_ceil(8)];
+        let memory = GameMemory {
+            is_initalized: false,
+            permanent_storage_size,
+            permanent_storage: permanent_storage.as_mut_ptr().cast::<c_void>(),
+            transient_storage_size,
+            transient_storage: transient_storage.as_mut_ptr().cast::<c_void>(),
+            asset_root: asset_root.into(),
+            work_queue: WorkQueue::for_all_cores(),
+        };
+        Self {
+            _permanent_storage: permanent_storage,
+            _transient_storage: transient_storage,
+            memory,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -14,23 +64,252 @@ pub struct GameState {
     pub tone: SineAudioSource,
     pub x_offset: i32,
     pub y_offset: i32,
+    pub players: PlayerAssignments,
+    pub test_sprite: Option<Bitmap>,
+    /// Made to fit the test sprite when it loads.
+    pub test_sprite_normals: Option<Bitmap>,
+    pub sprite_angle: f32,
+    /// Draws the debug overlay, which is left out without it.
+    pub debug_font: Option<Font>,
+    /// Shown in the debug overlay until the next one.
+    pub last_player_event: Option<PlayerEvent>,
+    /// Size of the back buffer the game last laid things out for.
+    pub buffer_width: i32,
+    pub buffer_height: i32,
+    /// The last frame, to tell the platform what the next one changed.
+    pub frame_tiles: TileHashes,
+    /// Transient storage has room for the render group, and to draw the frame in for buffers the
+    /// renderer can't draw into directly. Checked when the buffer size changes.
+    pub frame_fits: bool,
+}
+
+/// How the pixels of a [`GameOffscreenBuffer`] are laid out in memory, so a platform can hand the
+/// game the layout it presents.
+/// TODO(voided): the renderer only writes [`PixelFormat::Bgra8`], the others get converted from
+/// it after every frame. Spans that write them directly would save that pass.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Bytes blue, green, red and a fourth one that's left as whatever the renderer put there,
+    /// 0x00RRGGBB as little endian u32s. What a 32 bit BI_RGB dib section is, and what the
+    /// renderer draws in, so the game draws into it directly.
+    #[default]
+    Bgra8,
+    /// Bytes red, green, blue and alpha, which is always opaque. The layout of most image
+    /// formats and GL textures.
+    Rgba8,
+    /// 16 bit little endian, 5 bits of red at the top, then 6 of green and 5 of blue.
+    Rgb565,
+}
+
+impl PixelFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "bgra8" => Some(Self::Bgra8),
+            "rgba8" => Some(Self::Rgba8),
+            "rgb565" => Some(Self::Rgb565),
+            _ => None,
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Bgra8 | Self::Rgba8 => 4,
+            Self::Rgb565 => 2,
+        }
+    }
+
+    /// A 0xAARRGGBB pixel from the renderer in this format, in the low bytes.
+    pub fn encode(self, pixel: u32) -> u32 {
+        match self {
+            Self::Bgra8 => pixel,
+            Self::Rgba8 => swap_red_and_blue(pixel) | 0xFF000000,
+            Self::Rgb565 => {
+                let channel = |shift: u32, bits: u32| {
+                    let max = (1 << bits) - 1;
+                    (((pixel >> shift) & 0xFF) * max + 127) / 255
+                };
+                channel(16, 5) << 11 | channel(8, 6) << 5 | channel(0, 5)
+            }
+        }
+    }
+
+    /// Undoes [`Self::encode`], as an opaque 0xFFRRGGBB pixel. RGB565 loses the low bits of
+    /// every channel.
+    pub fn decode(self, value: u32) -> u32 {
+        match self {
+            Self::Bgra8 => value | 0xFF000000,
+            Self::Rgba8 => swap_red_and_blue(value) | 0xFF000000,
+            Self::Rgb565 => {
+                let channel = |shift: u32, bits: u32| {
+                    let max = (1 << bits) - 1;
+                    (((value >> shift) & max) * 255 + max / 2) / max
+                };
+                0xFF000000 | channel(11, 5) << 16 | channel(5, 6) << 8 | channel(0, 5)
+            }
+        }
+    }
+}
+
+fn swap_red_and_blue(pixel: u32) -> u32 {
+    pixel & 0xFF00FF00 | (pixel & 0xFF) << 16 | (pixel >> 16) & 0xFF
 }
 
 pub struct GameOffscreenBuffer {
-    pub memory: *mut c_void,
-    pub width: i32,
-    pub height: i32,
-    pub bytes_per_pixel: i32,
+    memory: *mut c_void,
+    width: i32,
+    height: i32,
+    format: PixelFormat,
+    dirty: DirtyRects,
 }
 
 impl GameOffscreenBuffer {
+    /// `None` if the buffer is not something we can render into: null or misaligned memory or
+    /// negative dimensions.
+    ///
+    /// # Safety
+    /// `memory` has to be valid for reads and writes of `width * height * bytes_per_pixel` bytes
+    /// for as long as the buffer is alive, and nothing else may access it during that time.
+    pub unsafe fn new(
+        memory: *mut c_void,
+        width: i32,
+        height: i32,
+        format: PixelFormat,
+    ) -> Option<Self> {
+        let bytes_per_pixel = format.bytes_per_pixel() as i32;
+        let is_valid = !memory.is_null()
+            && (memory as usize).is_multiple_of(bytes_per_pixel as usize)
+            && width >= 0
+            && height >= 0
+            && width
+                .checked_mul(height)
+                .and_then(|p| p.checked_mul(bytes_per_pixel))
+                .is_some();
+
+        is_valid.then(|| Self {
+            memory,
+            width,
+            height,
+            format,
+            dirty: DirtyRects::everything(Rect2i::new(0, 0, width, height)),
+        })
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    pub fn bytes_per_pixel(&self) -> i32 {
+        self.format.bytes_per_pixel() as i32
+    }
+
+    /// What the game changed since the last frame, the whole buffer until it says otherwise.
+    /// Note(voided): only right if the last frame is what the platform presented, anything else
+    /// has to present all of it.
+    pub fn dirty(&self) -> &DirtyRects {
+        &self.dirty
+    }
+
+    pub fn set_dirty(&mut self, dirty: DirtyRects) {
+        self.dirty = dirty;
+    }
+
+    /// The pixels to draw into directly, `None` unless the buffer is [`PixelFormat::Bgra8`].
+    /// Anything else has to be drawn somewhere else and written with [`Self::write_pixels`].
+    pub fn pixels(&mut self) -> Option<PixelsMut<'_>> {
+        if self.format != PixelFormat::Bgra8 {
+            return None;
+        }
+        let width = self.width as usize;
+        let height = self.height as usize;
+        // Safety: checked in new
+        let memory =
+            unsafe { std::slice::from_raw_parts_mut(self.memory.cast::<u32>(), width * height) };
+        Some(PixelsMut::new(memory, width, height, width).expect("buffer was checked in new"))
+    }
+
+    fn bytes(&self) -> &[u8] {
+        // Safety: checked in new
+        unsafe { std::slice::from_raw_parts(self.memory.cast::<u8>(), self.memory_size() as usize) }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        // Safety: checked in new
+        unsafe {
+            std::slice::from_raw_parts_mut(self.memory.cast::<u8>(), self.memory_size() as usize)
+        }
+    }
+
+    /// Converts 0xAARRGGBB pixels from the renderer into the buffer's format. Only the part
+    /// that fits into both gets written.
+    pub fn write_pixels(&mut self, source: BitmapView) {
+        let format = self.format;
+        let bytes_per_pixel = format.bytes_per_pixel();
+        let pitch = self.pitch() as usize;
+        if pitch == 0 {
+            return;
+        }
+        let rows = self.bytes_mut().chunks_exact_mut(pitch);
+        for (y, row) in rows.enumerate().take(source.height()) {
+            let source_row = source.row(y).expect("y is inside the source");
+            for (pixel, &color) in row.chunks_exact_mut(bytes_per_pixel).zip(source_row) {
+                let value = format.encode(color).to_le_bytes();
+                pixel.copy_from_slice(&value[..bytes_per_pixel]);
+            }
+        }
+    }
+
+    /// Every pixel as an opaque 0xFFRRGGBB one.
+    fn decoded(&self) -> impl Iterator<Item = u32> + '_ {
+        let bytes_per_pixel = self.format.bytes_per_pixel();
+        self.bytes()
+            .chunks_exact(bytes_per_pixel)
+            .map(move |pixel| {
+                let mut value = [0; 4];
+                value[..bytes_per_pixel].copy_from_slice(pixel);
+                self.format.decode(u32::from_le_bytes(value))
+            })
+    }
+
+    /// Opaque copy of the pixels, whatever the format.
+    pub fn to_bitmap(&self) -> Bitmap {
+        Bitmap::new(
+            self.width as usize,
+            self.height as usize,
+            self.decoded().collect(),
+        )
+        .expect("buffer was checked in new")
+    }
+
+    /// The pixels as 0x??RRGGBB, read where they are for [`PixelFormat::Bgra8`], whose fourth
+    /// byte is whatever the renderer left there. Other formats get converted into `scratch`,
+    /// which keeps its allocation for the next frame.
+    pub fn view<'a>(&'a self, scratch: &'a mut Vec<u32>) -> BitmapView<'a> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let pixels: &[u32] = if self.format == PixelFormat::Bgra8 {
+            // Safety: checked in new
+            unsafe { std::slice::from_raw_parts(self.memory.cast::<u32>(), width * height) }
+        } else {
+            scratch.clear();
+            scratch.extend(self.decoded());
+            scratch
+        };
+        BitmapView::new(width, height, pixels).expect("buffer was checked in new")
+    }
+
     pub fn pitch(&self) -> isize {
-        (self.width * self.bytes_per_pixel) as isize
+        (self.width * self.bytes_per_pixel()) as isize
     }
 
-    #[allow(unused)]
     pub fn memory_size(&self) -> i32 {
-        self.bytes_per_pixel * self.width * self.height
+        self.bytes_per_pixel() * self.width * self.height
     }
 }
 
@@ -39,9 +318,57 @@ pub struct GameSoundBuffer<'a> {
     pub buffer: &'a mut [f32],
 }
 
-pub type GameInput = [GameControllerInput; 5];
+#[derive(Default, Debug, Clone)]
+pub struct GameInput {
+    /// Note(voided): false while the window is in the background. The platform releases all
+    /// keyboard buttons when focus gets lost, so nothing stays stuck down.
+    pub has_focus: bool,
+    /// The platform gave the back buffer a new size since the last frame, anything laid out for
+    /// the old size needs redoing.
+    pub buffer_resized: bool,
+    pub controllers: [GameControllerInput; 5],
+}
+
+impl Index<usize> for GameInput {
+    type Output = GameControllerInput;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.controllers[index]
+    }
+}
 
-#[derive(Default, Debug)]
+impl IndexMut<usize> for GameInput {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        &mut self.controllers[index]
+    }
+}
+
+/// Note(voided): slot 0 of [`GameInput`] is always the keyboard, the rest are gamepads.
+pub const KEYBOARD_SLOT: usize = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonId {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    ActionUp,
+    ActionDown,
+    ActionLeft,
+    ActionRight,
+    ShoulderLeft,
+    ShoulderRight,
+    Start,
+    Back,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StickAxis {
+    LeftX,
+    LeftY,
+}
+
+#[derive(Default, Debug, Clone)]
 pub struct GameControllerInput {
     pub is_analog: bool,
     pub is_connected: bool,
@@ -95,16 +422,363 @@ impl GameControllerInput {
             &mut self.back,
         ]
     }
+
+    pub fn button(&self, id: ButtonId) -> &ButtonInput {
+        match id {
+            ButtonId::MoveUp => &self.move_up,
+            ButtonId::MoveDown => &self.move_down,
+            ButtonId::MoveLeft => &self.move_left,
+            ButtonId::MoveRight => &self.move_right,
+            ButtonId::ActionUp => &self.action_up,
+            ButtonId::ActionDown => &self.action_down,
+            ButtonId::ActionLeft => &self.action_left,
+            ButtonId::ActionRight => &self.action_right,
+            ButtonId::ShoulderLeft => &self.shoulder_left,
+            ButtonId::ShoulderRight => &self.shoulder_right,
+            ButtonId::Start => &self.start,
+            ButtonId::Back => &self.back,
+        }
+    }
+
+    pub fn button_mut(&mut self, id: ButtonId) -> &mut ButtonInput {
+        match id {
+            ButtonId::MoveUp => &mut self.move_up,
+            ButtonId::MoveDown => &mut self.move_down,
+            ButtonId::MoveLeft => &mut self.move_left,
+            ButtonId::MoveRight => &mut self.move_right,
+            ButtonId::ActionUp => &mut self.action_up,
+            ButtonId::ActionDown => &mut self.action_down,
+            ButtonId::ActionLeft => &mut self.action_left,
+            ButtonId::ActionRight => &mut self.action_right,
+            ButtonId::ShoulderLeft => &mut self.shoulder_left,
+            ButtonId::ShoulderRight => &mut self.shoulder_right,
+            ButtonId::Start => &mut self.start,
+            ButtonId::Back => &mut self.back,
+        }
+    }
+
+    pub fn axis(&self, axis: StickAxis) -> f32 {
+        match axis {
+            StickAxis::LeftX => self.stick_left.x_average,
+            StickAxis::LeftY => self.stick_left.y_average,
+        }
+    }
+
+    pub fn any_button_down(&self) -> bool {
+        self.buttons().iter().any(|button| button.button_is_down)
+    }
+
+    pub fn any_button_pressed(&self) -> bool {
+        self.buttons().iter().any(|button| button.was_pressed())
+    }
+
+    pub fn any_button_released(&self) -> bool {
+        self.buttons().iter().any(|button| button.was_released())
+    }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct ButtonInput {
     pub button_is_down: bool,
     pub half_transitions: u32,
 }
 
-#[derive(Default, Debug)]
+/// Note(voided): `half_transitions` counts every down/up flip during the frame, so together with
+/// the end state we can reconstruct the state at the start of the frame and from there how many
+/// presses and releases happened, even if the key got tapped several times within one frame.
+impl ButtonInput {
+    pub fn started_down(&self) -> bool {
+        self.button_is_down != (self.half_transitions % 2 == 1)
+    }
+
+    pub fn press_count(&self) -> u32 {
+        if self.started_down() {
+            self.half_transitions / 2
+        } else {
+            self.half_transitions.div_ceil(2)
+        }
+    }
+
+    pub fn release_count(&self) -> u32 {
+        if self.started_down() {
+            self.half_transitions.div_ceil(2)
+        } else {
+            self.half_transitions / 2
+        }
+    }
+
+    pub fn was_pressed(&self) -> bool {
+        self.press_count() > 0
+    }
+
+    pub fn was_released(&self) -> bool {
+        self.release_count() > 0
+    }
+}
+
+/// Tracks a button across frames, for queries a single frame of [`ButtonInput`] can't answer.
+/// Lives in the game state and gets updated once per frame.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ButtonHistory {
+    held_frames: u32,
+    frames_since_press: Option<u32>,
+    double_tapped: bool,
+}
+
+impl ButtonHistory {
+    /// `double_tap_frames` is the maximum amount of frames between two presses that still counts
+    /// as a double tap.
+    pub fn update(&mut self, button: &ButtonInput, double_tap_frames: u32) {
+        let presses = button.press_count();
+
+        self.double_tapped = match (presses, self.frames_since_press) {
+            (0, _) => false,
+            (1, None) => false,
+            (1, Some(frames)) => frames < double_tap_frames,
+            _ => true,
+        };
+
+        self.frames_since_press = if self.double_tapped {
+            //Note(voided): don't let a third tap count as another double tap
+            None
+        } else if presses > 0 {
+            Some(0)
+        } else {
+            self.frames_since_press
+                .map(|frames| frames.saturating_add(1))
+        };
+
+        self.held_frames = match (button.button_is_down, presses) {
+            (false, _) => 0,
+            (true, 0) => self.held_frames.saturating_add(1),
+            (true, _) => 1,
+        };
+    }
+
+    /// Frames the button has been held down for, including the current one. 0 if it is up.
+    pub fn held_frames(&self) -> u32 {
+        self.held_frames
+    }
+
+    pub fn held_seconds(&self, seconds_per_frame: f32) -> f32 {
+        self.held_frames as f32 * seconds_per_frame
+    }
+
+    pub fn was_double_tapped(&self) -> bool {
+        self.double_tapped
+    }
+}
+
+#[derive(Default, Debug, Clone)]
 pub struct StickInput {
     pub x_average: f32,
     pub y_average: f32,
 }
+
+#[cfg(test)]
+mod types_tests {
+    use utility::image::BitmapView;
+
+    use crate::types::{ButtonHistory, ButtonInput, GameOffscreenBuffer, PixelFormat};
+    use std::ffi::c_void;
+    use std::ptr::null_mut;
+
+    fn button(button_is_down: bool, half_transitions: u32) -> ButtonInput {
+        ButtonInput {
+            button_is_down,
+            half_transitions,
+        }
+    }
+
+    #[test]
+    fn held_without_transitions() {
+        let b = button(true, 0);
+        assert!(b.started_down());
+        assert_eq!((b.press_count(), b.release_count()), (0, 0));
+        assert!(!b.was_pressed());
+        assert!(!b.was_released());
+    }
+
+    #[test]
+    fn single_press() {
+        let b = button(true, 1);
+        assert!(!b.started_down());
+        assert_eq!((b.press_count(), b.release_count()), (1, 0));
+    }
+
+    #[test]
+    fn single_release() {
+        let b = button(false, 1);
+        assert!(b.started_down());
+        assert_eq!((b.press_count(), b.release_count()), (0, 1));
+    }
+
+    #[test]
+    fn tap_within_one_frame() {
+        let b = button(false, 2);
+        assert!(!b.started_down());
+        assert!(b.was_pressed());
+        assert!(b.was_released());
+        assert_eq!((b.press_count(), b.release_count()), (1, 1));
+    }
+
+    #[test]
+    fn release_and_press_within_one_frame() {
+        let b = button(true, 2);
+        assert!(b.started_down());
+        assert_eq!((b.press_count(), b.release_count()), (1, 1));
+    }
+
+    #[test]
+    fn many_taps_within_one_frame() {
+        let b = button(true, 5);
+        assert!(!b.started_down());
+        assert_eq!((b.press_count(), b.release_count()), (3, 2));
+
+        let b = button(false, 6);
+        assert_eq!((b.press_count(), b.release_count()), (3, 3));
+    }
+
+    #[test]
+    fn hold_duration() {
+        let mut history = ButtonHistory::default();
+        history.update(&button(true, 1), 8);
+        assert_eq!(history.held_frames(), 1);
+        history.update(&button(true, 0), 8);
+        history.update(&button(true, 0), 8);
+        assert_eq!(history.held_frames(), 3);
+        assert_eq!(history.held_seconds(0.5), 1.5);
+
+        // released and pressed again within one frame starts over
+        history.update(&button(true, 2), 8);
+        assert_eq!(history.held_frames(), 1);
+
+        history.update(&button(false, 1), 8);
+        assert_eq!(history.held_frames(), 0);
+    }
+
+    #[test]
+    fn double_tap_across_frames() {
+        let mut history = ButtonHistory::default();
+        history.update(&button(false, 2), 3);
+        assert!(!history.was_double_tapped());
+        history.update(&button(false, 0), 3);
+        history.update(&button(false, 0), 3);
+        history.update(&button(true, 1), 3);
+        assert!(history.was_double_tapped());
+
+        // third tap does not count again
+        history.update(&button(false, 1), 3);
+        history.update(&button(true, 1), 3);
+        assert!(!history.was_double_tapped());
+    }
+
+    #[test]
+    fn double_tap_too_slow() {
+        let mut history = ButtonHistory::default();
+        history.update(&button(false, 2), 2);
+        history.update(&button(false, 0), 2);
+        history.update(&button(false, 0), 2);
+        history.update(&button(false, 2), 2);
+        assert!(!history.was_double_tapped());
+    }
+
+    #[test]
+    fn double_tap_within_one_frame() {
+        let mut history = ButtonHistory::default();
+        history.update(&button(false, 4), 0);
+        assert!(history.was_double_tapped());
+        history.update(&button(false, 0), 0);
+        assert!(!history.was_double_tapped());
+    }
+
+    #[test]
+    fn offscreen_buffer_invariants() {
+        let mut memory = vec![0u32; 6];
+        let ptr = memory.as_mut_ptr().cast::<c_void>();
+        unsafe {
+            assert!(GameOffscreenBuffer::new(null_mut(), 3, 2, PixelFormat::Bgra8).is_none());
+            assert!(GameOffscreenBuffer::new(ptr.byte_add(1), 3, 2, PixelFormat::Bgra8).is_none());
+            assert!(GameOffscreenBuffer::new(ptr.byte_add(2), 3, 2, PixelFormat::Rgba8).is_none());
+            assert!(GameOffscreenBuffer::new(ptr, -3, 2, PixelFormat::Bgra8).is_none());
+            assert!(GameOffscreenBuffer::new(ptr.byte_add(2), 3, 2, PixelFormat::Rgb565).is_some());
+
+            let mut buffer = GameOffscreenBuffer::new(ptr, 3, 2, PixelFormat::Bgra8).unwrap();
+            *buffer.pixels().unwrap().pixel_mut(1, 1).unwrap() = 0xFF00FF;
+            assert_eq!(buffer.pitch(), 12);
+        }
+        assert_eq!(memory, [0, 0, 0, 0, 0xFF00FF, 0]);
+    }
+
+    #[test]
+    fn pixel_formats_round_trip() {
+        let pixel = 0x80FF8040;
+        assert_eq!(PixelFormat::Bgra8.encode(pixel), pixel);
+        assert_eq!(PixelFormat::Rgba8.encode(pixel), 0xFF4080FF);
+        // red 31, green 32 and blue 8
+        assert_eq!(PixelFormat::Rgb565.encode(pixel), 0xFC08);
+        assert_eq!(PixelFormat::Rgb565.decode(0xFC08), 0xFFFF8242);
+
+        for format in [PixelFormat::Bgra8, PixelFormat::Rgba8] {
+            assert_eq!(format.decode(format.encode(pixel)), pixel | 0xFF000000);
+        }
+        // every 16 bit value survives decoding and encoding again
+        for value in 0..=0xFFFF {
+            let format = PixelFormat::Rgb565;
+            assert_eq!(format.encode(format.decode(value)), value);
+        }
+    }
+
+    #[test]
+    fn buffers_convert_from_the_renderer() {
+        let source = [0x00FF0000, 0xFF00FF00, 0x800000FF, 0xFFFFFFFF];
+        let source = BitmapView::new(2, 2, &source).unwrap();
+
+        let mut rgba = vec![0u32; 4];
+        let mut buffer =
+            unsafe { GameOffscreenBuffer::new(rgba.as_mut_ptr().cast(), 2, 2, PixelFormat::Rgba8) }
+                .unwrap();
+        assert!(buffer.pixels().is_none());
+        buffer.write_pixels(source);
+        let opaque: Vec<u32> = source.pixels().iter().map(|p| p | 0xFF000000).collect();
+        assert_eq!(buffer.to_bitmap().pixels(), opaque);
+        assert_eq!(
+            rgba.iter()
+                .flat_map(|p| p.to_le_bytes())
+                .collect::<Vec<u8>>()[..8],
+            [0xFF, 0, 0, 0xFF, 0, 0xFF, 0, 0xFF]
+        );
+
+        // a smaller buffer takes the top left of the source
+        let mut rgb565 = vec![0u16; 1];
+        let mut buffer = unsafe {
+            GameOffscreenBuffer::new(rgb565.as_mut_ptr().cast(), 1, 1, PixelFormat::Rgb565)
+        }
+        .unwrap();
+        buffer.write_pixels(source);
+        assert_eq!(buffer.to_bitmap().pixels(), [0xFFFF0000]);
+        assert_eq!(rgb565, [0xF800]);
+    }
+
+    #[test]
+    fn views_only_convert_other_formats() {
+        let mut bgra = vec![0x80FF0000u32, 0x0000FF00];
+        let buffer =
+            unsafe { GameOffscreenBuffer::new(bgra.as_mut_ptr().cast(), 2, 1, PixelFormat::Bgra8) }
+                .unwrap();
+        let mut scratch = vec![];
+        let view = buffer.view(&mut scratch);
+        assert_eq!(view.pixels().as_ptr(), bgra.as_ptr());
+        assert_eq!(view.pixels(), [0x80FF0000, 0x0000FF00]);
+        assert!(scratch.is_empty());
+
+        let mut rgb565 = vec![0xF800u16, 0x001F];
+        let buffer = unsafe {
+            GameOffscreenBuffer::new(rgb565.as_mut_ptr().cast(), 2, 1, PixelFormat::Rgb565)
+        }
+        .unwrap();
+        let mut scratch = vec![0xDEADBEEF; 7];
+        assert_eq!(buffer.view(&mut scratch).pixels(), [0xFFFF0000, 0xFF0000FF]);
+        assert_eq!(scratch.len(), 2);
+    }
+}
@@ -0,0 +1,298 @@
+// Bakes a TrueType font into the glyph atlases and metrics files the game's text renderer reads,
+// so the game never has to rasterize outlines itself. Runs anywhere, no window needed.
+// `cargo run -p font_baker -- <font.ttf> <output prefix> [--sizes 12,16,24] [--chars 32-126,160-255]`
+// Every size becomes `<output prefix>_<size>.png` and `<output prefix>_<size>.font`.
+
+use std::io;
+use std::path::PathBuf;
+
+use fontdue::FontSettings;
+use utility::debug_io::{debug_platform_read_entire_file, debug_platform_write_entire_file};
+use utility::font::{FontMetrics, Glyph};
+use utility::image::png::encode_png;
+use utility::image::{premultiply, Bitmap};
+use utility::math::{v2, Rect2i};
+
+const DEFAULT_SIZE: u32 = 16;
+/// Printable ASCII.
+const DEFAULT_CHARS: &str = "32-126";
+/// Empty pixels around every glyph, so neighbours never bleed into each other.
+const PADDING: i32 = 1;
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    font: PathBuf,
+    output: String,
+    sizes: Vec<u32>,
+    chars: Vec<char>,
+}
+
+/// Comma separated code points and inclusive ranges of them, `32-126,169`.
+fn parse_chars(ranges: &str) -> Option<Vec<char>> {
+    let mut chars = vec![];
+    for range in ranges.split(',') {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let first: u32 = first.trim().parse().ok()?;
+        let last: u32 = last.trim().parse().ok()?;
+        // Note(voided): the surrogates in the middle aren't chars, they get skipped
+        chars.extend((first..=last).filter_map(char::from_u32));
+    }
+    chars.sort_unstable();
+    chars.dedup();
+    Some(chars)
+}
+
+fn parse_args<I>(args: I) -> Result<Options, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+    let mut positional = vec![];
+    let mut sizes = vec![DEFAULT_SIZE];
+    let mut chars = parse_chars(DEFAULT_CHARS).expect("the default characters parse");
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--sizes" => {
+                sizes = args
+                    .next()
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(|size| size.trim().parse().ok().filter(|&size| size > 0))
+                    .collect::<Option<_>>()
+                    .ok_or("--sizes expects pixel sizes above 0, like 12,16")?;
+            }
+            "--chars" => {
+                chars = parse_chars(&args.next().unwrap_or_default())
+                    .ok_or("--chars expects code points and ranges, like 32-126,169")?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown argument {arg}")),
+            _ => positional.push(arg),
+        }
+    }
+
+    match <[String; 2]>::try_from(positional) {
+        Ok([font, output]) => Ok(Options {
+            font: PathBuf::from(font),
+            output,
+            sizes,
+            chars,
+        }),
+        Err(_) => Err("expected a font file and an output prefix".to_string()),
+    }
+}
+
+/// Shelf packing: the glyphs go left to right in rows, tallest first, a new row starts when one
+/// doesn't fit anymore. Returns where each glyph goes, in the order they were passed in, and the
+/// height the atlas needs.
+fn pack(sizes: &[(i32, i32)], atlas_width: i32) -> (Vec<(i32, i32)>, i32) {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&index| (-sizes[index].1, -sizes[index].0));
+
+    let mut positions = vec![(0, 0); sizes.len()];
+    let (mut x, mut y, mut row_height) = (PADDING, PADDING, 0);
+    for index in order {
+        let (width, height) = sizes[index];
+        if x + width + PADDING > atlas_width && x > PADDING {
+            x = PADDING;
+            y += row_height + PADDING;
+            row_height = 0;
+        }
+        positions[index] = (x, y);
+        x += width + PADDING;
+        row_height = row_height.max(height);
+    }
+    (positions, y + row_height + PADDING)
+}
+
+/// Smallest power of two that is wider than the widest glyph and leaves the atlas roughly square.
+fn atlas_width(sizes: &[(i32, i32)]) -> i32 {
+    let area: i64 = sizes
+        .iter()
+        .map(|&(width, height)| (width + PADDING) as i64 * (height + PADDING) as i64)
+        .sum();
+    let widest = sizes.iter().map(|&(width, _)| width).max().unwrap_or(0);
+    let side = (area as f64).sqrt().ceil() as i32;
+    (side.max(widest + 2 * PADDING).max(1) as u32).next_power_of_two() as i32
+}
+
+/// Rasterizes `chars` at `size` pixels per em. The atlas is white, coverage goes into the alpha.
+fn bake(font: &fontdue::Font, size: u32, chars: &[char]) -> (Bitmap, FontMetrics) {
+    let px = size as f32;
+    let chars: Vec<char> = chars
+        .iter()
+        .copied()
+        .filter(|&c| font.lookup_glyph_index(c) != 0)
+        .collect();
+    let rasterized: Vec<_> = chars.iter().map(|&c| font.rasterize(c, px)).collect();
+    let sizes: Vec<(i32, i32)> = rasterized
+        .iter()
+        .map(|(metrics, _)| (metrics.width as i32, metrics.height as i32))
+        .collect();
+
+    let width = atlas_width(&sizes);
+    let (positions, height) = pack(&sizes, width);
+    let mut pixels = vec![0; (width * height) as usize];
+    let mut glyphs = vec![];
+    for ((&c, (metrics, coverage)), &(x, y)) in chars.iter().zip(&rasterized).zip(&positions) {
+        for (row, coverage_row) in coverage.chunks_exact(metrics.width.max(1)).enumerate() {
+            let start = (y as usize + row) * width as usize + x as usize;
+            for (pixel, &coverage) in pixels[start..].iter_mut().zip(coverage_row) {
+                *pixel = premultiply((coverage as u32) << 24 | 0xFFFFFF);
+            }
+        }
+        glyphs.push((
+            c,
+            Glyph {
+                atlas: Rect2i::with_size(x, y, metrics.width as i32, metrics.height as i32),
+                // Note(voided): fontdue measures up from the baseline to the bottom of the glyph
+                offset: v2(
+                    metrics.xmin as f32,
+                    -(metrics.ymin + metrics.height as i32) as f32,
+                ),
+                advance: metrics.advance_width,
+            },
+        ));
+    }
+
+    let mut kerning = vec![];
+    for &first in &chars {
+        for &second in &chars {
+            match font.horizontal_kern(first, second, px) {
+                Some(pixels) if pixels != 0.0 => kerning.push((first, second, pixels)),
+                _ => {}
+            }
+        }
+    }
+
+    let line = font.horizontal_line_metrics(px);
+    let metrics = FontMetrics {
+        line_height: line.map_or(px, |line| line.new_line_size),
+        ascent: line.map_or(px, |line| line.ascent),
+        glyphs,
+        kerning,
+    };
+    let atlas = Bitmap::new(width as usize, height as usize, pixels).expect("sizes match");
+    (atlas, metrics)
+}
+
+fn run(options: &Options) -> io::Result<()> {
+    let data = debug_platform_read_entire_file(&options.font)?;
+    let font = fontdue::Font::from_bytes(data, FontSettings::default())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    let missing = options
+        .chars
+        .iter()
+        .filter(|&&c| font.lookup_glyph_index(c) == 0)
+        .count();
+    if missing > 0 {
+        println!("The font has no glyphs for {missing} of the characters, they're left out");
+    }
+
+    for &size in &options.sizes {
+        let (atlas, metrics) = bake(&font, size, &options.chars);
+        let atlas_path = format!("{}_{size}.png", options.output);
+        let metrics_path = format!("{}_{size}.font", options.output);
+        debug_platform_write_entire_file(&atlas_path, encode_png(&atlas))?;
+        let font_name = options
+            .font
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let header = format!("# {font_name} at {size} pixels, baked by font_baker\n");
+        debug_platform_write_entire_file(&metrics_path, header + &metrics.to_string())?;
+        println!(
+            "{atlas_path}: {}x{} atlas, {} glyphs, {} kerning pairs",
+            atlas.width(),
+            atlas.height(),
+            metrics.glyphs.len(),
+            metrics.kerning.len()
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{err}");
+            eprintln!(
+                "usage: font_baker <font.ttf> <output prefix> [--sizes 12,16] [--chars 32-126]"
+            );
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = run(&options) {
+        eprintln!("Failed to bake {}: {err}", options.font.display());
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod font_baker_tests {
+    use std::path::PathBuf;
+
+    use utility::math::Rect2i;
+
+    use crate::{atlas_width, pack, parse_args, parse_chars, PADDING};
+
+    fn parse(args: &[&str]) -> Result<crate::Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn arguments() {
+        let options = parse(&["font.ttf", "data/fonts/ui", "--sizes", "12,24"]).unwrap();
+        assert_eq!(options.font, PathBuf::from("font.ttf"));
+        assert_eq!(options.output, "data/fonts/ui");
+        assert_eq!(options.sizes, [12, 24]);
+        assert_eq!(options.chars.len(), 95);
+
+        let options = parse(&["--chars", "65-67,66,55295-57344", "a.ttf", "out"]).unwrap();
+        assert_eq!(options.chars, ['A', 'B', 'C', '\u{D7FF}', '\u{E000}']);
+        assert_eq!(options.sizes, [16]);
+
+        assert!(parse(&["font.ttf"]).is_err());
+        assert!(parse(&["font.ttf", "out", "--sizes", "0"]).is_err());
+        assert!(parse(&["font.ttf", "out", "--bold"]).is_err());
+        assert_eq!(parse_chars("32-x"), None);
+    }
+
+    #[test]
+    fn packed_glyphs_never_overlap() {
+        let sizes: Vec<(i32, i32)> = (0..60)
+            .map(|index| ((index * 7) % 13, (index * 5) % 11 + 1))
+            .collect();
+        let width = atlas_width(&sizes);
+        let (positions, height) = pack(&sizes, width);
+
+        let atlas = Rect2i::new(PADDING, PADDING, width - PADDING, height - PADDING);
+        let rects: Vec<Rect2i> = positions
+            .iter()
+            .zip(&sizes)
+            .map(|(&(x, y), &(w, h))| Rect2i::with_size(x, y, w, h))
+            .collect();
+        for (index, rect) in rects.iter().enumerate() {
+            assert_eq!(rect.intersect(&atlas), *rect, "glyph {index} is outside");
+            // grown by the padding, they still may not touch
+            let padded = Rect2i::new(
+                rect.min_x - PADDING,
+                rect.min_y - PADDING,
+                rect.max_x + PADDING,
+                rect.max_y + PADDING,
+            );
+            for other in rects[index + 1..].iter().filter(|other| !other.is_empty()) {
+                assert!(rect.is_empty() || padded.intersect(other).is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn full_rows_wrap() {
+        assert_eq!(atlas_width(&[(100, 2)]), 128);
+        let (positions, height) = pack(&[(5, 4), (30, 5), (30, 5)], 40);
+        assert_eq!(positions, [(32, 7), (1, 1), (1, 7)]);
+        assert_eq!(height, 13);
+    }
+}
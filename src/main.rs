@@ -1,3 +1,6 @@
+// Note(voided): win32 is the only windowed platform layer so far, elsewhere only --headless runs.
+#![cfg_attr(not(windows), allow(unused))]
+
 mod platform;
 
 /*
@@ -12,7 +15,6 @@ TODO(voided): This is not a final platform layer!!!
 - Fullscreen
 - setcursor
 - QueryCancelAutoPlay
-- wm actiave app
 - blit speed improvement
 - hardware acceleration
 - get keyboard layout (international wasd)
@@ -0,0 +1,368 @@
+// Streams every presented frame into a video file and the game's sound into a matching WAV, so
+// clips can be put together offline, for example with
+// `ffmpeg -i clip.y4m -i clip.wav clip.mp4`.
+// Note(voided): frames get written as they come, the video assumes they all took exactly one
+// frame time. Dumps of throttled background frames will play back too fast.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use utility::image::BitmapView;
+
+use crate::game::GameOffscreenBuffer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoFormat {
+    /// YUV4MPEG2 with 4:2:0 chroma, which most video tools read directly.
+    Y4m,
+    /// Bare 8 bit R G B triples, the size and frame rate have to be passed to whatever reads it.
+    RawRgb,
+}
+
+impl VideoFormat {
+    /// Y4M for `.y4m` files, raw RGB for everything else.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("y4m") => Self::Y4m,
+            _ => Self::RawRgb,
+        }
+    }
+}
+
+fn rgb(pixel: u32) -> [i32; 3] {
+    [
+        (pixel >> 16 & 0xFF) as i32,
+        (pixel >> 8 & 0xFF) as i32,
+        (pixel & 0xFF) as i32,
+    ]
+}
+
+/// BT.601 luma in studio range, 16..=235.
+fn luma([r, g, b]: [i32; 3]) -> u8 {
+    (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8
+}
+
+/// BT.601 blue and red difference in studio range, 16..=240.
+fn chroma([r, g, b]: [i32; 3]) -> [u8; 2] {
+    [
+        (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8,
+        (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8,
+    ]
+}
+
+pub struct VideoWriter<W: Write> {
+    writer: W,
+    format: VideoFormat,
+    width: usize,
+    height: usize,
+    frame: Vec<u8>,
+}
+
+impl<W: Write> VideoWriter<W> {
+    pub fn new(
+        mut writer: W,
+        format: VideoFormat,
+        width: usize,
+        height: usize,
+        frames_per_second: u32,
+    ) -> io::Result<Self> {
+        if format == VideoFormat::Y4m {
+            writeln!(
+                writer,
+                "YUV4MPEG2 W{width} H{height} F{frames_per_second}:1 Ip A1:1 C420jpeg"
+            )?;
+        }
+        Ok(Self {
+            writer,
+            format,
+            width,
+            height,
+            frame: vec![],
+        })
+    }
+
+    /// Converts the 0x??RRGGBB pixels and appends them. Every frame has to be the same size.
+    pub fn write_frame(&mut self, pixels: BitmapView) -> io::Result<()> {
+        if (pixels.width(), pixels.height()) != (self.width, self.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the frame size changed during the dump",
+            ));
+        }
+
+        self.frame.clear();
+        match self.format {
+            VideoFormat::RawRgb => {
+                for row in pixels.rows() {
+                    for &pixel in row {
+                        self.frame.extend(rgb(pixel).map(|channel| channel as u8));
+                    }
+                }
+            }
+            VideoFormat::Y4m => {
+                self.writer.write_all(b"FRAME\n")?;
+                for row in pixels.rows() {
+                    self.frame.extend(row.iter().map(|&pixel| luma(rgb(pixel))));
+                }
+
+                // Note(voided): one chroma sample per 2x2 block, from the average color.
+                // Odd sizes get half blocks at the edges.
+                let chroma_width = self.width.div_ceil(2);
+                let chroma_height = self.height.div_ceil(2);
+                let mut blue = Vec::with_capacity(chroma_width * chroma_height);
+                let mut red = Vec::with_capacity(chroma_width * chroma_height);
+                for block_y in 0..chroma_height {
+                    for block_x in 0..chroma_width {
+                        let mut sum = [0; 3];
+                        let mut count = 0;
+                        for y in block_y * 2..(block_y * 2 + 2).min(self.height) {
+                            let row = pixels.row(y).expect("y is in range");
+                            for &pixel in &row[block_x * 2..(block_x * 2 + 2).min(self.width)] {
+                                let color = rgb(pixel);
+                                for channel in 0..3 {
+                                    sum[channel] += color[channel];
+                                }
+                                count += 1;
+                            }
+                        }
+                        let [u, v] = chroma(sum.map(|channel| (channel + count / 2) / count));
+                        blue.push(u);
+                        red.push(v);
+                    }
+                }
+                self.frame.extend_from_slice(&blue);
+                self.frame.extend_from_slice(&red);
+            }
+        }
+        self.writer.write_all(&self.frame)
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+const WAV_HEADER_SIZE: u32 = 44;
+
+/// 16 bit PCM WAV. The sizes in the header get filled in by [`WavWriter::finish`].
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    data_size: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        let block_align = channels * 2;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(WAV_HEADER_SIZE - 8).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(Self {
+            writer,
+            data_size: 0,
+        })
+    }
+
+    /// Interleaved samples in -1..=1, anything outside gets clipped.
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        let size = samples.len() as u32 * 2;
+        if self.data_size.checked_add(size + WAV_HEADER_SIZE).is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "too much audio for a wav file",
+            ));
+        }
+        for &sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_size += size;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer
+            .write_all(&(self.data_size + WAV_HEADER_SIZE - 8).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+struct DumpFiles {
+    video: VideoWriter<BufWriter<File>>,
+    audio: WavWriter<BufWriter<File>>,
+}
+
+/// A video file and a WAV next to it with the same name. Both get created with the first frame,
+/// once the frame size and sample rate are known.
+pub struct FrameDump {
+    path: PathBuf,
+    frames_per_second: u32,
+    files: Option<DumpFiles>,
+    /// Frames in other formats than BGRA8 get converted in here.
+    converted: Vec<u32>,
+}
+
+impl FrameDump {
+    pub fn new(path: PathBuf, frames_per_second: u32) -> Self {
+        Self {
+            path,
+            frames_per_second,
+            files: None,
+            converted: vec![],
+        }
+    }
+
+    pub fn audio_path(&self) -> PathBuf {
+        self.path.with_extension("wav")
+    }
+
+    /// `samples` is the interleaved stereo sound the game produced for this frame.
+    pub fn write_frame(
+        &mut self,
+        buffer: &GameOffscreenBuffer,
+        samples: &[f32],
+        sample_rate: u32,
+    ) -> io::Result<()> {
+        let files = match &mut self.files {
+            Some(files) => files,
+            None => {
+                let video = VideoWriter::new(
+                    BufWriter::new(File::create(&self.path)?),
+                    VideoFormat::from_path(&self.path),
+                    buffer.width() as usize,
+                    buffer.height() as usize,
+                    self.frames_per_second,
+                )?;
+                let audio = WavWriter::new(
+                    BufWriter::new(File::create(self.audio_path())?),
+                    sample_rate,
+                    2,
+                )?;
+                self.files.insert(DumpFiles { video, audio })
+            }
+        };
+
+        files.video.write_frame(buffer.view(&mut self.converted))?;
+        files.audio.write_samples(samples)
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        if let Some(files) = self.files {
+            files.video.finish()?;
+            files.audio.finish()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod frame_dump_tests {
+    use std::io::Cursor;
+    use std::path::Path;
+
+    use utility::image::BitmapView;
+
+    use crate::platform::frame_dump::{VideoFormat, VideoWriter, WavWriter};
+
+    fn write_frames(format: VideoFormat, width: usize, frames: &[Vec<u32>]) -> Vec<u8> {
+        let height = frames[0].len() / width;
+        let mut writer = VideoWriter::new(vec![], format, width, height, 30).unwrap();
+        for frame in frames {
+            let pixels = BitmapView::new(width, height, frame).unwrap();
+            writer.write_frame(pixels).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            VideoFormat::from_path(Path::new("a/clip.Y4M")),
+            VideoFormat::Y4m
+        );
+        assert_eq!(
+            VideoFormat::from_path(Path::new("clip.rgb")),
+            VideoFormat::RawRgb
+        );
+        assert_eq!(
+            VideoFormat::from_path(Path::new("clip")),
+            VideoFormat::RawRgb
+        );
+    }
+
+    #[test]
+    fn raw_rgb_ignores_x() {
+        let data = write_frames(VideoFormat::RawRgb, 2, &[vec![0xAA102030, 0x00FF0080]]);
+        assert_eq!(data, [0x10, 0x20, 0x30, 0xFF, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn y4m_frames() {
+        // white, black, red, blue on top, a gray row below, 3 wide so there's a half block
+        let frame = vec![
+            0x00FFFFFF, 0x00000000, 0x00FF0000, 0x00808080, 0x00808080, 0x00808080,
+        ];
+        let data = write_frames(VideoFormat::Y4m, 3, &[frame.clone(), frame]);
+
+        let header = b"YUV4MPEG2 W3 H2 F30:1 Ip A1:1 C420jpeg\n";
+        assert_eq!(&data[..header.len()], header);
+        let frame_size = 6 + 3 * 2 + 2 * 2;
+        assert_eq!(data.len(), header.len() + 2 * frame_size);
+
+        let frame = &data[header.len()..header.len() + frame_size];
+        assert_eq!(&frame[..6], b"FRAME\n");
+        assert_eq!(&frame[6..12], [235, 16, 82, 126, 126, 126]);
+        // the first block averages white, black and gray, the second red and gray
+        assert_eq!(&frame[12..14], [128, 109]);
+        assert_eq!(&frame[14..16], [128, 184]);
+    }
+
+    #[test]
+    fn frame_size_has_to_stay_the_same() {
+        let mut writer = VideoWriter::new(vec![], VideoFormat::RawRgb, 2, 1, 30).unwrap();
+        let frame = [0u32; 2];
+        let pixels = BitmapView::new(1, 2, &frame).unwrap();
+        assert!(writer.write_frame(pixels).is_err());
+    }
+
+    #[test]
+    fn wav_header_gets_patched() {
+        let mut writer = WavWriter::new(Cursor::new(vec![]), 48000, 2).unwrap();
+        writer.write_samples(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+        writer.write_samples(&[0.5, -0.5]).unwrap();
+        let data = writer.finish().unwrap().into_inner();
+
+        assert_eq!(data.len(), 44 + 12);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(data[4..8].try_into().unwrap()), 36 + 12);
+        assert_eq!(&data[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes(data[24..28].try_into().unwrap()), 48000);
+        assert_eq!(
+            u32::from_le_bytes(data[28..32].try_into().unwrap()),
+            48000 * 4
+        );
+        assert_eq!(&data[36..40], b"data");
+        assert_eq!(u32::from_le_bytes(data[40..44].try_into().unwrap()), 12);
+
+        let samples: Vec<i16> = data[44..]
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect();
+        assert_eq!(samples, [0, 32767, -32767, 32767, 16384, -16384]);
+    }
+}
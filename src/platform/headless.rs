@@ -0,0 +1,110 @@
+// Runs the game without a window, sound device or real input, for captures and bug reports.
+// Note(voided): frames run as fast as possible, the game still thinks it runs at FRAMES_PER_SECOND.
+
+use utility::megabytes;
+
+use crate::game::{
+    game_update_and_render, GameInput, GameOffscreenBuffer, GameSoundBuffer, HeapGameMemory,
+};
+use crate::platform::input_recording::{parse_trace, replay};
+use crate::platform::screenshot::save_screenshot;
+use crate::platform::{debug_platform_read_entire_file, PlatformOptions};
+
+pub const BUFFER_WIDTH: i32 = 1280;
+pub const BUFFER_HEIGHT: i32 = 720;
+pub const FRAMES_PER_SECOND: u32 = 30;
+pub const SAMPLES_PER_SECOND: u32 = 48000;
+/// Frames to run if neither `--frames` nor a replay says otherwise.
+const DEFAULT_FRAME_COUNT: u64 = 60;
+
+/// Input for every frame, from the replayed trace first and empty, focused input after it.
+fn load_inputs(options: &PlatformOptions) -> Vec<GameInput> {
+    let Some(path) = &options.replay_raw_input else {
+        return vec![];
+    };
+    let trace = debug_platform_read_entire_file(path)
+        .and_then(|data| String::from_utf8(data).map_err(std::io::Error::other))
+        .and_then(|trace| parse_trace(&trace));
+    match trace {
+        Ok(events) => replay(&events),
+        Err(err) => {
+            println!("Failed to read raw input trace {}: {err}", path.display());
+            vec![]
+        }
+    }
+}
+
+pub fn headless_main(options: &PlatformOptions) {
+    let inputs = load_inputs(options);
+    let frame_count = options.frames.unwrap_or(if inputs.is_empty() {
+        DEFAULT_FRAME_COUNT
+    } else {
+        inputs.len() as u64
+    });
+    let idle_input = GameInput {
+        has_focus: true,
+        ..Default::default()
+    };
+
+    let mut game_memory = HeapGameMemory::new(megabytes(64), megabytes(256), ".");
+    // u32s, so the pixels are aligned for every format
+    let mut back_buffer = vec![0u32; (BUFFER_WIDTH * BUFFER_HEIGHT) as usize];
+    let mut sound_buffer_mem = vec![0.0; (SAMPLES_PER_SECOND / FRAMES_PER_SECOND * 2) as usize];
+
+    let mut frame_dump = options.frame_dump(FRAMES_PER_SECOND);
+
+    for frame_index in 0..frame_count {
+        let input = inputs.get(frame_index as usize).unwrap_or(&idle_input);
+        // Safety: the buffer is only used by this frame and doesn't outlive back_buffer
+        let mut buffer = unsafe {
+            GameOffscreenBuffer::new(
+                back_buffer.as_mut_ptr().cast(),
+                BUFFER_WIDTH,
+                BUFFER_HEIGHT,
+                options.pixel_format,
+            )
+        }
+        .expect("back buffer is not usable by the game");
+        let mut sound_buffer = GameSoundBuffer {
+            samples_rate: SAMPLES_PER_SECOND,
+            buffer: &mut sound_buffer_mem,
+        };
+
+        unsafe {
+            game_update_and_render(
+                &mut game_memory.memory,
+                input,
+                &mut buffer,
+                &mut sound_buffer,
+            );
+        }
+
+        if let Some(dump) = frame_dump.as_mut() {
+            if let Err(err) = dump.write_frame(&buffer, sound_buffer.buffer, SAMPLES_PER_SECOND) {
+                println!("Stopped dumping frames: {err}");
+                frame_dump = None;
+            }
+        }
+
+        if options.capture_frames.contains(&frame_index) {
+            let format = options.screenshot_format;
+            match save_screenshot(
+                &mut buffer,
+                options.screenshot_directory(),
+                format,
+                Some(frame_index),
+            ) {
+                Ok(path) => println!("Captured frame {frame_index} to {}", path.display()),
+                Err(err) => println!("Failed to capture frame {frame_index}: {err}"),
+            }
+        }
+    }
+
+    if let Some(dump) = frame_dump {
+        let audio_path = dump.audio_path();
+        match dump.finish() {
+            Ok(()) => println!("Dumped {frame_count} frames and {}", audio_path.display()),
+            Err(err) => println!("Failed to finish the frame dump: {err}"),
+        }
+    }
+}
@@ -0,0 +1,393 @@
+// Platform independent part of turning raw device state into GameInput.
+// The platform layers only translate their native events into these types, so the processing
+// itself can be replayed and tested without a window or a controller.
+
+use crate::game::{ButtonInput, GameControllerInput, StickInput};
+
+pub const GAMEPAD_DPAD_UP: u16 = 0x0001;
+pub const GAMEPAD_DPAD_DOWN: u16 = 0x0002;
+pub const GAMEPAD_DPAD_LEFT: u16 = 0x0004;
+pub const GAMEPAD_DPAD_RIGHT: u16 = 0x0008;
+pub const GAMEPAD_START: u16 = 0x0010;
+pub const GAMEPAD_BACK: u16 = 0x0020;
+pub const GAMEPAD_LEFT_SHOULDER: u16 = 0x0100;
+pub const GAMEPAD_RIGHT_SHOULDER: u16 = 0x0200;
+pub const GAMEPAD_A: u16 = 0x1000;
+pub const GAMEPAD_B: u16 = 0x2000;
+pub const GAMEPAD_X: u16 = 0x4000;
+pub const GAMEPAD_Y: u16 = 0x8000;
+
+pub const STICK_DEADZONE: f32 = 0.1;
+pub const STICK_THRESHOLD: f32 = 0.5;
+
+macro_rules! has_bitflag {
+    ($value:expr, $flag:expr) => {
+        $flag & $value == $flag
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    W,
+    A,
+    S,
+    D,
+    Q,
+    E,
+    Up,
+    Down,
+    Left,
+    Right,
+    Escape,
+    Space,
+}
+
+impl Key {
+    pub const ALL: [Key; 12] = [
+        Key::W,
+        Key::A,
+        Key::S,
+        Key::D,
+        Key::Q,
+        Key::E,
+        Key::Up,
+        Key::Down,
+        Key::Left,
+        Key::Right,
+        Key::Escape,
+        Key::Space,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Key::W => "w",
+            Key::A => "a",
+            Key::S => "s",
+            Key::D => "d",
+            Key::Q => "q",
+            Key::E => "e",
+            Key::Up => "up",
+            Key::Down => "down",
+            Key::Left => "left",
+            Key::Right => "right",
+            Key::Escape => "escape",
+            Key::Space => "space",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Key> {
+        Key::ALL.into_iter().find(|key| key.name() == name)
+    }
+
+    fn button(self, keyboard: &mut GameControllerInput) -> &mut ButtonInput {
+        match self {
+            Key::W => &mut keyboard.move_up,
+            Key::A => &mut keyboard.move_left,
+            Key::S => &mut keyboard.move_down,
+            Key::D => &mut keyboard.move_right,
+            Key::Q => &mut keyboard.shoulder_left,
+            Key::E => &mut keyboard.shoulder_right,
+            Key::Up => &mut keyboard.action_up,
+            Key::Down => &mut keyboard.action_down,
+            Key::Left => &mut keyboard.action_left,
+            Key::Right => &mut keyboard.action_right,
+            Key::Escape => &mut keyboard.start,
+            Key::Space => &mut keyboard.back,
+        }
+    }
+}
+
+/// Raw state of a gamepad as the device reports it. Buttons use the XInput bit layout.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RawGamepadState {
+    pub buttons: u16,
+    pub stick_left_x: i16,
+    pub stick_left_y: i16,
+}
+
+/// Keyboard buttons keep their state across frames, only the transitions start over.
+pub fn begin_keyboard_frame(
+    old_keyboard: &GameControllerInput,
+    new_keyboard: &mut GameControllerInput,
+) {
+    *new_keyboard = Default::default();
+    for (new_button, old_button) in new_keyboard
+        .buttons_mut()
+        .into_iter()
+        .zip(old_keyboard.buttons())
+    {
+        new_button.button_is_down = old_button.button_is_down;
+    }
+
+    new_keyboard.is_connected = true;
+}
+
+/// For when the window loses focus, we won't hear about keys released in another window.
+pub fn release_keyboard(keyboard: &mut GameControllerInput) {
+    for button in keyboard.buttons_mut() {
+        if button.button_is_down {
+            process_keyboard_message(button, false);
+        }
+    }
+}
+
+pub fn process_key(keyboard: &mut GameControllerInput, key: Key, key_is_down: bool) {
+    process_keyboard_message(key.button(keyboard), key_is_down);
+}
+
+/// Events that don't change the key state are ignored, a replayed trace can repeat them.
+pub fn process_keyboard_message(new_button_state: &mut ButtonInput, key_is_down: bool) {
+    if new_button_state.button_is_down == key_is_down {
+        return;
+    }
+    new_button_state.button_is_down = key_is_down;
+    new_button_state.half_transitions += 1;
+}
+
+pub fn process_digital_button(
+    buttons: u16,
+    button: u16,
+    old_state: &ButtonInput,
+    new_state: &mut ButtonInput,
+) {
+    new_state.button_is_down = has_bitflag!(buttons, button);
+    new_state.half_transitions = if old_state.button_is_down != new_state.button_is_down {
+        1
+    } else {
+        0
+    };
+}
+
+fn normalize_i16(x: i16) -> f32 {
+    x as f32 / i16::MAX as f32
+}
+
+pub fn process_stick(stick_position: i16, stick_deadzone: f32) -> f32 {
+    let position_normalized = normalize_i16(stick_position);
+
+    if position_normalized.abs() >= stick_deadzone {
+        position_normalized.signum() * (position_normalized.abs() - stick_deadzone)
+            / (1.0 - stick_deadzone)
+    } else {
+        0.0
+    }
+}
+
+/// `gamepad` is `None` when the controller is not plugged in.
+pub fn process_gamepad(
+    gamepad: Option<&RawGamepadState>,
+    old_input: &GameControllerInput,
+    new_input: &mut GameControllerInput,
+) {
+    let Some(gamepad) = gamepad else {
+        //Note(Voided): Controller is not available.
+        new_input.is_connected = false;
+        return;
+    };
+
+    //Note(voided): Controller is plugged in.
+    new_input.is_connected = true;
+
+    new_input.stick_left = StickInput {
+        x_average: process_stick(gamepad.stick_left_x, STICK_DEADZONE),
+        y_average: process_stick(gamepad.stick_left_y, STICK_DEADZONE),
+    };
+
+    if new_input.stick_left.x_average != 0.0 || new_input.stick_left.y_average != 0.0 {
+        new_input.is_analog = true;
+    }
+
+    if has_bitflag!(gamepad.buttons, GAMEPAD_DPAD_UP) {
+        new_input.stick_left.y_average = 1.0;
+        new_input.is_analog = false;
+    } else if has_bitflag!(gamepad.buttons, GAMEPAD_DPAD_DOWN) {
+        new_input.stick_left.y_average = -1.0;
+        new_input.is_analog = false;
+    }
+
+    if has_bitflag!(gamepad.buttons, GAMEPAD_DPAD_LEFT) {
+        new_input.stick_left.x_average = -1.0;
+        new_input.is_analog = false;
+    } else if has_bitflag!(gamepad.buttons, GAMEPAD_DPAD_RIGHT) {
+        new_input.stick_left.x_average = 1.0;
+        new_input.is_analog = false;
+    }
+
+    let mut fake_buttons: u16 = 0;
+    const FAKE_BUTTON_UP: u16 = 1;
+    const FAKE_BUTTON_DOWN: u16 = 2;
+    const FAKE_BUTTON_LEFT: u16 = 4;
+    const FAKE_BUTTON_RIGHT: u16 = 8;
+
+    if new_input.stick_left.y_average >= STICK_THRESHOLD {
+        fake_buttons += FAKE_BUTTON_UP;
+    } else if new_input.stick_left.y_average.abs() >= STICK_THRESHOLD {
+        fake_buttons += FAKE_BUTTON_DOWN;
+    }
+
+    if new_input.stick_left.x_average >= STICK_THRESHOLD {
+        fake_buttons += FAKE_BUTTON_RIGHT;
+    } else if new_input.stick_left.x_average.abs() >= STICK_THRESHOLD {
+        fake_buttons += FAKE_BUTTON_LEFT;
+    }
+
+    let digital_buttons = [
+        (fake_buttons, FAKE_BUTTON_UP),
+        (fake_buttons, FAKE_BUTTON_DOWN),
+        (fake_buttons, FAKE_BUTTON_LEFT),
+        (fake_buttons, FAKE_BUTTON_RIGHT),
+        (gamepad.buttons, GAMEPAD_Y),
+        (gamepad.buttons, GAMEPAD_A),
+        (gamepad.buttons, GAMEPAD_X),
+        (gamepad.buttons, GAMEPAD_B),
+        (gamepad.buttons, GAMEPAD_LEFT_SHOULDER),
+        (gamepad.buttons, GAMEPAD_RIGHT_SHOULDER),
+        (gamepad.buttons, GAMEPAD_START),
+        (gamepad.buttons, GAMEPAD_BACK),
+    ];
+
+    //Note(voided): same order as GameControllerInput::buttons
+    for ((new_button, old_button), (buttons, flag)) in new_input
+        .buttons_mut()
+        .into_iter()
+        .zip(old_input.buttons())
+        .zip(digital_buttons)
+    {
+        process_digital_button(buttons, flag, old_button, new_button);
+    }
+}
+
+#[cfg(test)]
+mod input_tests {
+    use crate::game::{ButtonInput, GameControllerInput};
+    use crate::platform::input::{
+        begin_keyboard_frame, process_gamepad, process_key, process_stick, release_keyboard, Key,
+        RawGamepadState, GAMEPAD_A, GAMEPAD_DPAD_LEFT, STICK_DEADZONE,
+    };
+
+    #[test]
+    fn stick_deadzone() {
+        assert_eq!(process_stick(1000, STICK_DEADZONE), 0.0);
+        assert_eq!(process_stick(i16::MAX, STICK_DEADZONE), 1.0);
+        assert_eq!(process_stick(-i16::MAX, STICK_DEADZONE), -1.0);
+        let half = process_stick(i16::MAX / 2, STICK_DEADZONE);
+        assert!((half - 0.4444).abs() < 0.001);
+    }
+
+    #[test]
+    fn keyboard_keeps_held_keys() {
+        let mut old = GameControllerInput::default();
+        let mut new = GameControllerInput::default();
+        begin_keyboard_frame(&old, &mut new);
+        process_key(&mut new, Key::W, true);
+        process_key(&mut new, Key::Space, true);
+        process_key(&mut new, Key::Space, false);
+        assert_eq!(new.move_up.half_transitions, 1);
+        assert_eq!(new.back.half_transitions, 2);
+
+        std::mem::swap(&mut old, &mut new);
+        begin_keyboard_frame(&old, &mut new);
+        assert!(new.move_up.button_is_down);
+        assert_eq!(new.move_up.half_transitions, 0);
+        assert!(!new.back.button_is_down);
+        assert!(new.is_connected);
+    }
+
+    #[test]
+    fn release_keyboard_on_focus_loss() {
+        let old = GameControllerInput::default();
+        let mut new = GameControllerInput::default();
+        begin_keyboard_frame(&old, &mut new);
+        process_key(&mut new, Key::D, true);
+        process_key(&mut new, Key::Q, true);
+        process_key(&mut new, Key::Q, false);
+        release_keyboard(&mut new);
+
+        assert!(!new.move_right.button_is_down);
+        assert_eq!(new.move_right.half_transitions, 2);
+        assert!(!new.shoulder_left.button_is_down);
+        assert_eq!(new.shoulder_left.half_transitions, 2);
+        assert!(!new.any_button_down());
+    }
+
+    #[test]
+    fn repeated_key_events_are_ignored() {
+        let old = GameControllerInput::default();
+        let mut new = GameControllerInput::default();
+        begin_keyboard_frame(&old, &mut new);
+        process_key(&mut new, Key::A, true);
+        process_key(&mut new, Key::A, true);
+        process_key(&mut new, Key::S, false);
+
+        assert!(new.move_left.button_is_down);
+        assert_eq!(new.move_left.half_transitions, 1);
+        assert!(!new.move_down.button_is_down);
+        assert_eq!(new.move_down.half_transitions, 0);
+    }
+
+    #[test]
+    fn stick_emulates_dpad_buttons() {
+        let old = GameControllerInput::default();
+        let mut new = GameControllerInput::default();
+        let state = RawGamepadState {
+            buttons: GAMEPAD_A,
+            stick_left_x: i16::MAX,
+            stick_left_y: -i16::MAX,
+        };
+        process_gamepad(Some(&state), &old, &mut new);
+
+        assert!(new.is_connected);
+        assert!(new.is_analog);
+        assert!(new.move_right.button_is_down);
+        assert!(new.move_down.button_is_down);
+        assert!(!new.move_left.button_is_down);
+        assert!(new.action_down.button_is_down);
+        assert_eq!(new.action_down.half_transitions, 1);
+    }
+
+    #[test]
+    fn dpad_overrides_stick() {
+        let old = GameControllerInput::default();
+        let mut new = GameControllerInput::default();
+        let state = RawGamepadState {
+            buttons: GAMEPAD_DPAD_LEFT,
+            stick_left_x: i16::MAX,
+            stick_left_y: 0,
+        };
+        process_gamepad(Some(&state), &old, &mut new);
+
+        assert!(!new.is_analog);
+        assert_eq!(new.stick_left.x_average, -1.0);
+        assert!(new.move_left.button_is_down);
+    }
+
+    #[test]
+    fn held_gamepad_button_has_no_transition() {
+        let old = GameControllerInput {
+            action_down: ButtonInput {
+                button_is_down: true,
+                half_transitions: 1,
+            },
+            ..Default::default()
+        };
+        let mut new = GameControllerInput::default();
+        let state = RawGamepadState {
+            buttons: GAMEPAD_A,
+            ..Default::default()
+        };
+        process_gamepad(Some(&state), &old, &mut new);
+        assert!(new.action_down.button_is_down);
+        assert_eq!(new.action_down.half_transitions, 0);
+    }
+
+    #[test]
+    fn disconnected_gamepad() {
+        let old = GameControllerInput::default();
+        let mut new = GameControllerInput {
+            is_connected: true,
+            ..Default::default()
+        };
+        process_gamepad(None, &old, &mut new);
+        assert!(!new.is_connected);
+    }
+}
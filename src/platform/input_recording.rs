@@ -0,0 +1,355 @@
+// Records the raw device events the platform sees, before they become GameInput.
+// Replaying a trace runs them through the same processing code as the live loop, which gives us
+// golden traces for keyboard handling, deadzones and the stick to dpad emulation.
+
+use std::fmt::Write;
+use std::io;
+use std::mem;
+
+use crate::game::{GameControllerInput, GameInput};
+use crate::platform::input::{
+    begin_keyboard_frame, process_gamepad, process_key, release_keyboard, Key, RawGamepadState,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawInputEvent {
+    Key {
+        key: Key,
+        is_down: bool,
+        timestamp_us: u64,
+    },
+    /// `state` is `None` while the controller is not connected.
+    Gamepad {
+        index: usize,
+        state: Option<RawGamepadState>,
+        timestamp_us: u64,
+    },
+    Focus {
+        has_focus: bool,
+        timestamp_us: u64,
+    },
+    /// Everything recorded before belongs to one GameInput.
+    FrameEnd {
+        timestamp_us: u64,
+    },
+}
+
+#[derive(Default)]
+pub struct InputRecorder {
+    events: Vec<RawInputEvent>,
+}
+
+impl InputRecorder {
+    pub fn record(&mut self, event: RawInputEvent) {
+        self.events.push(event);
+    }
+
+    pub fn events(&self) -> &[RawInputEvent] {
+        &self.events
+    }
+
+    pub fn to_trace(&self) -> String {
+        write_trace(&self.events)
+    }
+}
+
+/// One event per line:
+/// - `key <name> down|up <timestamp_us>`
+/// - `pad <index> <buttons as hex> <stick x> <stick y> <timestamp_us>`
+/// - `pad <index> disconnected <timestamp_us>`
+/// - `focus gained|lost <timestamp_us>`
+/// - `frame <timestamp_us>`
+///
+/// Empty lines and lines starting with `#` are ignored.
+pub fn write_trace(events: &[RawInputEvent]) -> String {
+    let mut trace = String::new();
+    for event in events {
+        match *event {
+            RawInputEvent::Key {
+                key,
+                is_down,
+                timestamp_us,
+            } => {
+                let direction = if is_down { "down" } else { "up" };
+                writeln!(trace, "key {} {direction} {timestamp_us}", key.name())
+            }
+            RawInputEvent::Gamepad {
+                index,
+                state: Some(state),
+                timestamp_us,
+            } => writeln!(
+                trace,
+                "pad {index} {:#06x} {} {} {timestamp_us}",
+                state.buttons, state.stick_left_x, state.stick_left_y
+            ),
+            RawInputEvent::Gamepad {
+                index,
+                state: None,
+                timestamp_us,
+            } => writeln!(trace, "pad {index} disconnected {timestamp_us}"),
+            RawInputEvent::Focus {
+                has_focus,
+                timestamp_us,
+            } => {
+                let change = if has_focus { "gained" } else { "lost" };
+                writeln!(trace, "focus {change} {timestamp_us}")
+            }
+            RawInputEvent::FrameEnd { timestamp_us } => writeln!(trace, "frame {timestamp_us}"),
+        }
+        .expect("writing to a string can't fail");
+    }
+    trace
+}
+
+fn invalid_trace(line_number: usize, line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid trace line {}: {line}", line_number + 1),
+    )
+}
+
+pub fn parse_trace(trace: &str) -> io::Result<Vec<RawInputEvent>> {
+    let mut events = vec![];
+    // the first controller slot is the keyboard
+    let gamepad_count = GameInput::default().controllers.len() - 1;
+
+    for (line_number, line) in trace.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let invalid = || invalid_trace(line_number, line);
+        let number = |part: &str| part.parse::<u64>().map_err(|_| invalid());
+        let gamepad = |part: &str| {
+            let index = number(part)? as usize;
+            if index < gamepad_count {
+                Ok(index)
+            } else {
+                Err(invalid())
+            }
+        };
+
+        let event = match parts.as_slice() {
+            ["key", key, direction, timestamp] => RawInputEvent::Key {
+                key: Key::from_name(key).ok_or_else(invalid)?,
+                is_down: match *direction {
+                    "down" => true,
+                    "up" => false,
+                    _ => return Err(invalid()),
+                },
+                timestamp_us: number(timestamp)?,
+            },
+            ["pad", index, "disconnected", timestamp] => RawInputEvent::Gamepad {
+                index: gamepad(index)?,
+                state: None,
+                timestamp_us: number(timestamp)?,
+            },
+            ["pad", index, buttons, stick_x, stick_y, timestamp] => RawInputEvent::Gamepad {
+                index: gamepad(index)?,
+                state: Some(RawGamepadState {
+                    buttons: u16::from_str_radix(buttons.trim_start_matches("0x"), 16)
+                        .map_err(|_| invalid())?,
+                    stick_left_x: stick_x.parse().map_err(|_| invalid())?,
+                    stick_left_y: stick_y.parse().map_err(|_| invalid())?,
+                }),
+                timestamp_us: number(timestamp)?,
+            },
+            ["focus", change, timestamp] => RawInputEvent::Focus {
+                has_focus: match *change {
+                    "gained" => true,
+                    "lost" => false,
+                    _ => return Err(invalid()),
+                },
+                timestamp_us: number(timestamp)?,
+            },
+            ["frame", timestamp] => RawInputEvent::FrameEnd {
+                timestamp_us: number(timestamp)?,
+            },
+            _ => return Err(invalid()),
+        };
+        events.push(event);
+    }
+
+    Ok(events)
+}
+
+/// Runs the events through the platform input processing, one GameInput per recorded frame.
+/// Gamepad slots start out at 1, like in the live loop. The window starts out focused.
+pub fn replay(events: &[RawInputEvent]) -> Vec<GameInput> {
+    let mut frames = vec![];
+    let mut old_inputs = GameInput::default();
+    let mut new_inputs = GameInput::default();
+    let mut has_focus = true;
+
+    begin_keyboard_frame(&old_inputs[0], &mut new_inputs[0]);
+    for event in events {
+        match *event {
+            RawInputEvent::Key { key, is_down, .. } => {
+                process_key(&mut new_inputs[0], key, is_down);
+            }
+            RawInputEvent::Gamepad { index, state, .. } => {
+                let slot = index + 1;
+                process_gamepad(state.as_ref(), &old_inputs[slot], &mut new_inputs[slot]);
+            }
+            RawInputEvent::Focus {
+                has_focus: focus, ..
+            } => {
+                if has_focus && !focus {
+                    release_keyboard(&mut new_inputs[0]);
+                }
+                has_focus = focus;
+            }
+            RawInputEvent::FrameEnd { .. } => {
+                new_inputs.has_focus = has_focus;
+                frames.push(new_inputs.clone());
+                mem::swap(&mut old_inputs, &mut new_inputs);
+                begin_keyboard_frame(&old_inputs[0], &mut new_inputs[0]);
+            }
+        }
+    }
+
+    frames
+}
+
+fn describe_controller(description: &mut String, slot: usize, input: &GameControllerInput) {
+    const NAMES: [&str; 12] = [
+        "move_up",
+        "move_down",
+        "move_left",
+        "move_right",
+        "action_up",
+        "action_down",
+        "action_left",
+        "action_right",
+        "shoulder_left",
+        "shoulder_right",
+        "start",
+        "back",
+    ];
+
+    write!(
+        description,
+        "  {slot}: analog={} stick=({:.3}, {:.3})",
+        input.is_analog, input.stick_left.x_average, input.stick_left.y_average
+    )
+    .expect("writing to a string can't fail");
+
+    for (name, button) in NAMES.iter().zip(input.buttons()) {
+        if button.button_is_down || button.half_transitions > 0 {
+            let state = if button.button_is_down { "down" } else { "up" };
+            write!(description, " {name}={state}/{}", button.half_transitions)
+                .expect("writing to a string can't fail");
+        }
+    }
+    description.push('\n');
+}
+
+/// Stable text form of replayed frames, which is what the golden files contain.
+pub fn describe_frames(frames: &[GameInput]) -> String {
+    let mut description = String::new();
+    for (index, frame) in frames.iter().enumerate() {
+        let focus = if frame.has_focus { "" } else { " unfocused" };
+        writeln!(description, "frame {index}{focus}").expect("writing to a string can't fail");
+        for (slot, input) in frame.controllers.iter().enumerate() {
+            if input.is_connected {
+                describe_controller(&mut description, slot, input);
+            }
+        }
+    }
+    description
+}
+
+#[cfg(test)]
+mod input_recording_tests {
+    use crate::platform::input::{Key, RawGamepadState, GAMEPAD_START};
+    use crate::platform::input_recording::{
+        describe_frames, parse_trace, replay, write_trace, InputRecorder, RawInputEvent,
+    };
+
+    fn check_golden(trace: &str, golden: &str) {
+        let events = parse_trace(trace).expect("golden trace should parse");
+        let description = describe_frames(&replay(&events));
+        assert_eq!(description.trim(), golden.trim());
+    }
+
+    #[test]
+    fn golden_keyboard() {
+        check_golden(
+            include_str!("traces/keyboard.trace"),
+            include_str!("traces/keyboard.golden"),
+        );
+    }
+
+    #[test]
+    fn golden_gamepad() {
+        check_golden(
+            include_str!("traces/gamepad.trace"),
+            include_str!("traces/gamepad.golden"),
+        );
+    }
+
+    #[test]
+    fn golden_focus() {
+        check_golden(
+            include_str!("traces/focus.trace"),
+            include_str!("traces/focus.golden"),
+        );
+    }
+
+    #[test]
+    fn repeated_key_lines_replay() {
+        check_golden(
+            "key w down 10\nkey w down 20\nframe 30\nkey w up 40\nkey w up 50\nframe 60\n",
+            "frame 0\n  0: analog=false stick=(0.000, 0.000) move_up=down/1\n\
+             frame 1\n  0: analog=false stick=(0.000, 0.000) move_up=up/1",
+        );
+    }
+
+    #[test]
+    fn trace_round_trip() {
+        let mut recorder = InputRecorder::default();
+        recorder.record(RawInputEvent::Key {
+            key: Key::Escape,
+            is_down: true,
+            timestamp_us: 10,
+        });
+        recorder.record(RawInputEvent::Gamepad {
+            index: 2,
+            state: Some(RawGamepadState {
+                buttons: GAMEPAD_START,
+                stick_left_x: -32767,
+                stick_left_y: 12,
+            }),
+            timestamp_us: 20,
+        });
+        recorder.record(RawInputEvent::Gamepad {
+            index: 3,
+            state: None,
+            timestamp_us: 21,
+        });
+        recorder.record(RawInputEvent::Focus {
+            has_focus: false,
+            timestamp_us: 30,
+        });
+        recorder.record(RawInputEvent::FrameEnd { timestamp_us: 33 });
+
+        let parsed = parse_trace(&recorder.to_trace()).unwrap();
+        assert_eq!(parsed, recorder.events());
+        assert_eq!(write_trace(&parsed), recorder.to_trace());
+    }
+
+    #[test]
+    fn invalid_lines_are_errors() {
+        assert!(parse_trace("key w sideways 10").is_err());
+        assert!(parse_trace("key f1 down 10").is_err());
+        assert!(parse_trace("pad 0 0xzz 0 0 10").is_err());
+        // only as many pads as there are controller slots, replaying more would panic
+        assert!(parse_trace("pad 3 disconnected 10").is_ok());
+        assert!(parse_trace("pad 4 disconnected 10").is_err());
+        assert!(parse_trace("pad 18446744073709551615 0x0 0 0 10").is_err());
+        assert!(parse_trace("frame").is_err());
+        assert!(parse_trace("# only a comment\n\n").unwrap().is_empty());
+    }
+}
@@ -1,28 +1,208 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+#[allow(unused)]
+pub use utility::debug_io::{debug_platform_read_entire_file, debug_platform_write_entire_file};
+
+use self::frame_dump::FrameDump;
+use self::presentation::PresentationMode;
+use self::screenshot::ScreenshotFormat;
+use crate::game::PixelFormat;
+
+pub mod frame_dump;
+pub mod headless;
+pub mod input;
+pub mod input_recording;
+pub mod presentation;
+pub mod screenshot;
 #[cfg(windows)]
 pub mod windows;
 
-#[allow(unused)]
-pub fn debug_platform_read_entire_file<C>(filename: C) -> std::io::Result<Vec<u8>>
-where
-    C: AsRef<Path>,
-{
-    std::fs::read(filename)
+#[derive(Default, Debug)]
+pub struct PlatformOptions {
+    /// Raw device events get written here on exit, see [`input_recording`].
+    pub record_raw_input: Option<PathBuf>,
+    /// Mute audio and slow down the update rate while the window is in the background.
+    pub background_throttle: bool,
+    /// Run without a window, see [`headless`].
+    pub headless: bool,
+    /// Headless runs replay this trace as their input.
+    pub replay_raw_input: Option<PathBuf>,
+    /// How many frames a headless run lasts.
+    pub frames: Option<u64>,
+    /// Frames a headless run saves screenshots of, counting from 0.
+    pub capture_frames: Vec<u64>,
+    /// Where screenshots go, `screenshots` if not set.
+    pub screenshot_directory: Option<PathBuf>,
+    pub screenshot_format: ScreenshotFormat,
+    /// Every frame gets appended to this video, the sound goes next to it, see [`frame_dump`].
+    pub dump_video: Option<PathBuf>,
+    /// How the buffer gets scaled into the window.
+    pub presentation_mode: PresentationMode,
+    /// The back buffer follows the window's size, times this, instead of staying 1280x720.
+    pub resize_buffer: Option<f32>,
+    /// Layout of the back buffer in headless runs.
+    pub pixel_format: PixelFormat,
 }
 
-#[allow(unused)]
-pub fn debug_platform_write_entire_file<C, D>(filename: C, data: D) -> std::io::Result<()>
-where
-    C: AsRef<Path>,
-    D: AsRef<[u8]>,
-{
-    std::fs::write(filename, data)
+impl PlatformOptions {
+    pub fn from_args<I>(args: I) -> Self
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--record-raw-input" => {
+                    options.record_raw_input = args.next().map(PathBuf::from);
+                }
+                "--background-throttle" => {
+                    options.background_throttle = true;
+                }
+                "--headless" => {
+                    options.headless = true;
+                }
+                "--replay-raw-input" => {
+                    options.replay_raw_input = args.next().map(PathBuf::from);
+                }
+                "--frames" => match args.next().map(|frames| frames.parse()) {
+                    Some(Ok(frames)) => options.frames = Some(frames),
+                    _ => println!("Ignoring --frames without a frame count"),
+                },
+                "--capture-frames" => {
+                    for frame in args.next().unwrap_or_default().split(',') {
+                        match frame.trim().parse() {
+                            Ok(frame) => options.capture_frames.push(frame),
+                            Err(_) => println!("Ignoring invalid frame index {frame:?}"),
+                        }
+                    }
+                }
+                "--screenshot-directory" => {
+                    options.screenshot_directory = args.next().map(PathBuf::from);
+                }
+                "--screenshot-format" => {
+                    match args.next().as_deref().and_then(ScreenshotFormat::from_name) {
+                        Some(format) => options.screenshot_format = format,
+                        None => println!("Ignoring --screenshot-format, expected png or bmp"),
+                    }
+                }
+                "--dump-video" => {
+                    options.dump_video = args.next().map(PathBuf::from);
+                }
+                "--presentation" => {
+                    match args.next().as_deref().and_then(PresentationMode::from_name) {
+                        Some(mode) => options.presentation_mode = mode,
+                        None => {
+                            println!("Ignoring --presentation, expected fit, integer or stretch")
+                        }
+                    }
+                }
+                "--resize-buffer" => match args.next().map(|scale| scale.parse::<f32>()) {
+                    Some(Ok(scale)) if scale > 0.0 && scale.is_finite() => {
+                        options.resize_buffer = Some(scale)
+                    }
+                    _ => println!("Ignoring --resize-buffer without a scale above 0"),
+                },
+                "--pixel-format" => match args.next().as_deref().and_then(PixelFormat::from_name) {
+                    Some(format) => options.pixel_format = format,
+                    None => {
+                        println!("Ignoring --pixel-format, expected bgra8, rgba8 or rgb565")
+                    }
+                },
+                _ => println!("Ignoring unknown argument {arg}"),
+            }
+        }
+        options
+    }
+
+    pub fn frame_dump(&self, frames_per_second: u32) -> Option<FrameDump> {
+        self.dump_video
+            .clone()
+            .map(|path| FrameDump::new(path, frames_per_second))
+    }
+
+    pub fn screenshot_directory(&self) -> &Path {
+        self.screenshot_directory
+            .as_deref()
+            .unwrap_or(Path::new("screenshots"))
+    }
 }
 
-#[cfg(windows)]
 pub fn platform_main() {
-    use self::windows::win32main::win32main;
+    let options = PlatformOptions::from_args(std::env::args().skip(1));
+    if options.headless {
+        headless::headless_main(&options);
+        return;
+    }
+
+    #[cfg(windows)]
+    windows::win32main::win32main(options);
+    #[cfg(not(windows))]
+    println!("No windowed platform layer for this target yet, only --headless works!");
+}
+
+#[cfg(test)]
+mod platform_tests {
+    use std::path::PathBuf;
+
+    use crate::game::PixelFormat;
+    use crate::platform::presentation::PresentationMode;
+    use crate::platform::screenshot::ScreenshotFormat;
+    use crate::platform::PlatformOptions;
+
+    fn parse(args: &[&str]) -> PlatformOptions {
+        PlatformOptions::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn headless_arguments() {
+        let options = parse(&[
+            "--headless",
+            "--replay-raw-input",
+            "bug.trace",
+            "--frames",
+            "200",
+            "--capture-frames",
+            "0, 10,x,199",
+            "--screenshot-format",
+            "BMP",
+            "--dump-video",
+            "clip.y4m",
+            "--pixel-format",
+            "rgb565",
+        ]);
+        assert!(options.headless);
+        assert_eq!(options.replay_raw_input, Some(PathBuf::from("bug.trace")));
+        assert_eq!(options.frames, Some(200));
+        assert_eq!(options.capture_frames, [0, 10, 199]);
+        assert_eq!(options.screenshot_format, ScreenshotFormat::Bmp);
+        assert_eq!(options.dump_video, Some(PathBuf::from("clip.y4m")));
+        assert_eq!(options.pixel_format, PixelFormat::Rgb565);
+    }
+
+    #[test]
+    fn invalid_values_are_ignored() {
+        let options = parse(&["--frames", "lots", "--screenshot-format", "gif", "--frames"]);
+        assert_eq!(options.frames, None);
+        assert_eq!(options.screenshot_format, ScreenshotFormat::Png);
+        let options = parse(&["--pixel-format", "argb"]);
+        assert_eq!(options.pixel_format, PixelFormat::Bgra8);
+    }
+
+    #[test]
+    fn presentation_mode() {
+        assert_eq!(parse(&[]).presentation_mode, PresentationMode::Fit);
+        let options = parse(&["--presentation", "stretch"]);
+        assert_eq!(options.presentation_mode, PresentationMode::Stretch);
+        let options = parse(&["--presentation", "sideways"]);
+        assert_eq!(options.presentation_mode, PresentationMode::Fit);
+    }
 
-    win32main()
+    #[test]
+    fn resize_buffer() {
+        assert_eq!(parse(&[]).resize_buffer, None);
+        assert_eq!(parse(&["--resize-buffer", "0.5"]).resize_buffer, Some(0.5));
+        assert_eq!(parse(&["--resize-buffer", "-1"]).resize_buffer, None);
+        assert_eq!(parse(&["--resize-buffer", "inf"]).resize_buffer, None);
+    }
 }
@@ -0,0 +1,409 @@
+// Where the back buffer goes in the window. Shared by every platform layer that shows the buffer,
+// so they all scale it, draw the bars around it and map the mouse back the same way.
+
+use utility::math::Rect2i;
+
+use crate::game::DirtyRects;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PresentationMode {
+    /// As big as fits with the buffer's aspect ratio, bars fill the rest.
+    #[default]
+    Fit,
+    /// The biggest whole multiple of the buffer size that fits, for crisp pixels. Windows smaller
+    /// than the buffer fall back to fit.
+    IntegerScale,
+    /// Covers the whole window, whatever the aspect ratio.
+    Stretch,
+}
+
+impl PresentationMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "fit" => Some(Self::Fit),
+            "integer" => Some(Self::IntegerScale),
+            "stretch" => Some(Self::Stretch),
+            _ => None,
+        }
+    }
+}
+
+/// Neither side of a resized back buffer gets bigger than this.
+pub const MAX_BUFFER_DIMENSION: i32 = 8192;
+/// Once the dirty parts cover more of the buffer than this, presenting all of it is cheaper than
+/// copying them one at a time.
+pub const PARTIAL_PRESENT_LIMIT: f64 = 0.5;
+
+/// Back buffer size for a window when the buffer follows the window's size, `scale` times the
+/// client area. `None` for minimized windows, which report a size of 0.
+pub fn buffer_size_for_window(
+    (window_width, window_height): (i32, i32),
+    scale: f32,
+) -> Option<(i32, i32)> {
+    if window_width <= 0 || window_height <= 0 || scale.is_nan() || scale <= 0.0 {
+        return None;
+    }
+    let side =
+        |length: i32| ((length as f32 * scale).round() as i32).clamp(1, MAX_BUFFER_DIMENSION);
+    Some((side(window_width), side(window_height)))
+}
+
+/// The buffer's place in a window of a given size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Presentation {
+    /// Window pixels the buffer gets scaled into.
+    pub destination: Rect2i,
+    pub window_width: i32,
+    pub window_height: i32,
+    pub buffer_width: i32,
+    pub buffer_height: i32,
+}
+
+/// `a * b / c` rounded to the closest integer, without overflowing along the way.
+fn scale(a: i32, b: i32, c: i32) -> i32 {
+    ((a as i64 * b as i64 * 2 + c as i64) / (c as i64 * 2)) as i32
+}
+
+impl Presentation {
+    pub fn new(
+        mode: PresentationMode,
+        (buffer_width, buffer_height): (i32, i32),
+        (window_width, window_height): (i32, i32),
+    ) -> Self {
+        let window_width = window_width.max(0);
+        let window_height = window_height.max(0);
+        let (width, height) = if buffer_width <= 0 || buffer_height <= 0 {
+            (0, 0)
+        } else {
+            let integer_factor = (window_width / buffer_width).min(window_height / buffer_height);
+            match mode {
+                PresentationMode::Stretch => (window_width, window_height),
+                PresentationMode::IntegerScale if integer_factor >= 1 => (
+                    buffer_width * integer_factor,
+                    buffer_height * integer_factor,
+                ),
+                // Note(voided): compare the aspect ratios by cross multiplying, whichever side
+                // runs into the window first decides the scale
+                _ if window_width as i64 * buffer_height as i64
+                    <= window_height as i64 * buffer_width as i64 =>
+                {
+                    (
+                        window_width,
+                        scale(buffer_height, window_width, buffer_width),
+                    )
+                }
+                _ => (
+                    scale(buffer_width, window_height, buffer_height),
+                    window_height,
+                ),
+            }
+        };
+
+        Self {
+            destination: Rect2i::with_size(
+                (window_width - width) / 2,
+                (window_height - height) / 2,
+                width,
+                height,
+            ),
+            window_width,
+            window_height,
+            buffer_width,
+            buffer_height,
+        }
+    }
+
+    /// The parts of the window the buffer doesn't cover, top, bottom, left and right. Empty ones
+    /// are left out.
+    pub fn bars(&self) -> impl Iterator<Item = Rect2i> {
+        let destination = self.destination;
+        [
+            Rect2i::new(0, 0, self.window_width, destination.min_y),
+            Rect2i::new(0, destination.max_y, self.window_width, self.window_height),
+            Rect2i::new(0, destination.min_y, destination.min_x, destination.max_y),
+            Rect2i::new(
+                destination.max_x,
+                destination.min_y,
+                self.window_width,
+                destination.max_y,
+            ),
+        ]
+        .into_iter()
+        .filter(|bar| !bar.is_empty())
+    }
+
+    /// The window pixels a rectangle of buffer pixels gets scaled onto. Rectangles that share an
+    /// edge in the buffer share it in the window too, so presenting a frame in parts leaves no
+    /// gaps between them.
+    pub fn buffer_to_window(&self, rect: Rect2i) -> Rect2i {
+        if self.buffer_width <= 0 || self.buffer_height <= 0 {
+            return Rect2i::default();
+        }
+        let destination = self.destination;
+        let x = |x: i32| {
+            destination.min_x
+                + scale(
+                    x.clamp(0, self.buffer_width),
+                    destination.width(),
+                    self.buffer_width,
+                )
+        };
+        let y = |y: i32| {
+            destination.min_y
+                + scale(
+                    y.clamp(0, self.buffer_height),
+                    destination.height(),
+                    self.buffer_height,
+                )
+        };
+        Rect2i::new(x(rect.min_x), y(rect.min_y), x(rect.max_x), y(rect.max_y))
+    }
+
+    /// Where a rectangle of buffer rows sits in the source of a `StretchDIBits` call.
+    /// Note(voided): the buffer is a top-down DIB, but `StretchDIBits` still counts the source
+    /// rectangle's y up from the bottom of the image, so the top rows start `height - max_y` up.
+    pub fn dib_source(&self, rect: Rect2i) -> Rect2i {
+        let bounds = Rect2i::new(0, 0, self.buffer_width, self.buffer_height);
+        let rect = rect.intersect(&bounds);
+        if rect.is_empty() {
+            return Rect2i::default();
+        }
+        Rect2i::new(
+            rect.min_x,
+            self.buffer_height - rect.max_y,
+            rect.max_x,
+            self.buffer_height - rect.min_y,
+        )
+    }
+
+    /// The parts of the buffer that have to be copied to the window, `None` if all of it does:
+    /// when the window isn't showing the `last` presentation anymore, or when so much changed
+    /// that copying everything is cheaper.
+    pub fn dirty_parts<'a>(
+        &self,
+        last: Option<&Presentation>,
+        dirty: &'a DirtyRects,
+    ) -> Option<&'a [Rect2i]> {
+        if last != Some(self) {
+            return None;
+        }
+        let buffer_area = self.buffer_width as i64 * self.buffer_height as i64;
+        if dirty.area() as f64 > buffer_area as f64 * PARTIAL_PRESENT_LIMIT {
+            return None;
+        }
+        Some(dirty.rects())
+    }
+
+    /// The buffer pixel under a window pixel, `None` over the bars.
+    /// TODO(voided): map the cursor with this once the game takes mouse input
+    pub fn window_to_buffer(&self, x: i32, y: i32) -> Option<(i32, i32)> {
+        let destination = self.destination;
+        if !destination.contains(x, y) {
+            return None;
+        }
+        let buffer_x =
+            (x - destination.min_x) as i64 * self.buffer_width as i64 / destination.width() as i64;
+        let buffer_y = (y - destination.min_y) as i64 * self.buffer_height as i64
+            / destination.height() as i64;
+        Some((buffer_x as i32, buffer_y as i32))
+    }
+}
+
+#[cfg(test)]
+mod presentation_tests {
+    use utility::math::Rect2i;
+
+    use crate::game::DirtyRects;
+    use crate::platform::presentation::{
+        buffer_size_for_window, Presentation, PresentationMode, MAX_BUFFER_DIMENSION,
+    };
+
+    const BUFFER: (i32, i32) = (1280, 720);
+
+    fn destination(mode: PresentationMode, window: (i32, i32)) -> Rect2i {
+        Presentation::new(mode, BUFFER, window).destination
+    }
+
+    #[test]
+    fn fit_letterboxes_and_pillarboxes() {
+        // same aspect ratio, no bars
+        let exact = Presentation::new(PresentationMode::Fit, BUFFER, (1920, 1080));
+        assert_eq!(exact.destination, Rect2i::new(0, 0, 1920, 1080));
+        assert_eq!(exact.bars().count(), 0);
+
+        // taller window, bars on top and bottom
+        let tall = Presentation::new(PresentationMode::Fit, BUFFER, (1280, 1024));
+        assert_eq!(tall.destination, Rect2i::new(0, 152, 1280, 872));
+        assert_eq!(
+            tall.bars().collect::<Vec<_>>(),
+            [
+                Rect2i::new(0, 0, 1280, 152),
+                Rect2i::new(0, 872, 1280, 1024)
+            ]
+        );
+
+        // wider window, bars left and right
+        let wide = Presentation::new(PresentationMode::Fit, BUFFER, (2560, 1080));
+        assert_eq!(wide.destination, Rect2i::new(320, 0, 2240, 1080));
+        assert_eq!(
+            wide.bars().collect::<Vec<_>>(),
+            [
+                Rect2i::new(0, 0, 320, 1080),
+                Rect2i::new(2240, 0, 2560, 1080)
+            ]
+        );
+    }
+
+    #[test]
+    fn bars_and_destination_cover_the_window() {
+        for mode in [
+            PresentationMode::Fit,
+            PresentationMode::IntegerScale,
+            PresentationMode::Stretch,
+        ] {
+            for window in [(1, 1), (333, 1000), (1281, 721), (3000, 2000), (641, 359)] {
+                let presentation = Presentation::new(mode, BUFFER, window);
+                let area: i64 = presentation.destination.area()
+                    + presentation.bars().map(|bar| bar.area()).sum::<i64>();
+                assert_eq!(
+                    area,
+                    window.0 as i64 * window.1 as i64,
+                    "{mode:?} {window:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn integer_scale() {
+        assert_eq!(
+            destination(PresentationMode::IntegerScale, (3000, 2000)),
+            Rect2i::new(220, 280, 2780, 1720)
+        );
+        assert_eq!(
+            destination(PresentationMode::IntegerScale, (1300, 800)),
+            Rect2i::new(10, 40, 1290, 760)
+        );
+        // too small for 1x, so it scales down like fit
+        assert_eq!(
+            destination(PresentationMode::IntegerScale, (640, 480)),
+            destination(PresentationMode::Fit, (640, 480))
+        );
+    }
+
+    #[test]
+    fn stretch_covers_everything() {
+        let stretch = Presentation::new(PresentationMode::Stretch, BUFFER, (300, 900));
+        assert_eq!(stretch.destination, Rect2i::new(0, 0, 300, 900));
+        assert_eq!(stretch.bars().count(), 0);
+    }
+
+    #[test]
+    fn mouse_maps_into_the_buffer() {
+        let wide = Presentation::new(PresentationMode::Fit, BUFFER, (2560, 1080));
+        assert_eq!(wide.window_to_buffer(320, 0), Some((0, 0)));
+        assert_eq!(wide.window_to_buffer(2239, 1079), Some((1279, 719)));
+        assert_eq!(wide.window_to_buffer(1280, 540), Some((640, 360)));
+        assert_eq!(wide.window_to_buffer(319, 500), None);
+        assert_eq!(wide.window_to_buffer(2240, 500), None);
+        assert_eq!(wide.window_to_buffer(400, -1), None);
+
+        let doubled = Presentation::new(PresentationMode::IntegerScale, BUFFER, (2560, 1440));
+        assert_eq!(doubled.window_to_buffer(3, 5), Some((1, 2)));
+    }
+
+    #[test]
+    fn buffer_parts_map_into_the_window() {
+        let wide = Presentation::new(PresentationMode::Fit, BUFFER, (2560, 1080));
+        assert_eq!(
+            wide.buffer_to_window(Rect2i::new(0, 0, 1280, 720)),
+            wide.destination
+        );
+        assert_eq!(
+            wide.buffer_to_window(Rect2i::new(640, 360, 1280, 720)),
+            Rect2i::new(1280, 540, 2240, 1080)
+        );
+        // clipped to the buffer
+        assert_eq!(
+            wide.buffer_to_window(Rect2i::new(-10, 700, 10, 900)),
+            Rect2i::new(320, 1050, 335, 1080)
+        );
+
+        // odd scales round, but neighbours still meet
+        let odd = Presentation::new(PresentationMode::Stretch, BUFFER, (1000, 333));
+        let left = odd.buffer_to_window(Rect2i::new(0, 0, 417, 301));
+        let right = odd.buffer_to_window(Rect2i::new(417, 301, 1280, 720));
+        assert_eq!((left.max_x, left.max_y), (right.min_x, right.min_y));
+    }
+
+    #[test]
+    fn dib_sources_count_rows_from_the_bottom() {
+        let presentation = Presentation::new(PresentationMode::Fit, BUFFER, (1920, 1080));
+        let everything = Rect2i::new(0, 0, 1280, 720);
+        assert_eq!(presentation.dib_source(everything), everything);
+        assert_eq!(
+            presentation.dib_source(Rect2i::new(64, 0, 128, 32)),
+            Rect2i::new(64, 688, 128, 720)
+        );
+        assert_eq!(
+            presentation.dib_source(Rect2i::new(0, 700, 1280, 900)),
+            Rect2i::new(0, 0, 1280, 20)
+        );
+        assert_eq!(
+            presentation.dib_source(Rect2i::new(0, 800, 10, 900)),
+            Rect2i::default()
+        );
+    }
+
+    #[test]
+    fn mostly_static_frames_present_in_parts() {
+        let presentation = Presentation::new(PresentationMode::Fit, BUFFER, (1920, 1080));
+        let mut dirty = DirtyRects::default();
+        dirty.add(Rect2i::new(0, 0, 64, 64));
+        dirty.add(Rect2i::new(640, 320, 704, 384));
+
+        let parts = presentation.dirty_parts(Some(&presentation), &dirty);
+        assert_eq!(parts, Some(dirty.rects()));
+        assert!(presentation.dirty_parts(Some(&presentation), &DirtyRects::default()) == Some(&[]));
+
+        // the window shows something else, or there's too much to copy
+        let resized = Presentation::new(PresentationMode::Fit, BUFFER, (1280, 720));
+        assert_eq!(presentation.dirty_parts(Some(&resized), &dirty), None);
+        assert_eq!(presentation.dirty_parts(None, &dirty), None);
+        dirty.add(Rect2i::new(0, 0, 1280, 400));
+        assert_eq!(presentation.dirty_parts(Some(&presentation), &dirty), None);
+    }
+
+    #[test]
+    fn degenerate_sizes() {
+        let minimized = Presentation::new(PresentationMode::Fit, BUFFER, (0, 0));
+        assert!(minimized.destination.is_empty());
+        assert_eq!(minimized.window_to_buffer(0, 0), None);
+        assert_eq!(minimized.bars().count(), 0);
+
+        let no_buffer = Presentation::new(PresentationMode::Fit, (0, 0), (100, 100));
+        assert!(no_buffer.destination.is_empty());
+        assert_eq!(no_buffer.window_to_buffer(50, 50), None);
+        assert_eq!(no_buffer.bars().map(|bar| bar.area()).sum::<i64>(), 10000);
+
+        assert_eq!(
+            PresentationMode::from_name("Integer"),
+            Some(PresentationMode::IntegerScale)
+        );
+        assert_eq!(PresentationMode::from_name("zoom"), None);
+    }
+
+    #[test]
+    fn resized_buffer_sizes() {
+        assert_eq!(buffer_size_for_window((1600, 900), 1.0), Some((1600, 900)));
+        assert_eq!(buffer_size_for_window((1601, 901), 0.5), Some((801, 451)));
+        assert_eq!(buffer_size_for_window((3, 1), 0.1), Some((1, 1)));
+        assert_eq!(
+            buffer_size_for_window((5000, 100), 4.0),
+            Some((MAX_BUFFER_DIMENSION, 400))
+        );
+        assert_eq!(buffer_size_for_window((0, 0), 1.0), None);
+        assert_eq!(buffer_size_for_window((800, 600), 0.0), None);
+        assert_eq!(buffer_size_for_window((800, 600), f32::NAN), None);
+    }
+}
@@ -0,0 +1,171 @@
+// Writes the back buffer to an image file, for the screenshot hotkey and headless captures.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use utility::image::bmp::encode_bmp;
+use utility::image::png::encode_png;
+use utility::image::Bitmap;
+
+use crate::game::GameOffscreenBuffer;
+use crate::platform::debug_platform_write_entire_file;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenshotFormat {
+    #[default]
+    Png,
+    Bmp,
+}
+
+impl ScreenshotFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "bmp" => Some(Self::Bmp),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Bmp => "bmp",
+        }
+    }
+
+    pub fn encode(self, bitmap: &Bitmap) -> Vec<u8> {
+        match self {
+            Self::Png => encode_png(bitmap),
+            Self::Bmp => encode_bmp(bitmap),
+        }
+    }
+}
+
+/// Copies the back buffer into a bitmap, whatever its pixel format.
+/// Note(voided): in a BGRA8 buffer like the 32 bit BI_RGB dib section, the fourth byte is
+/// whatever the game left there. Every pixel ends up opaque.
+pub fn capture_buffer(buffer: &GameOffscreenBuffer) -> Bitmap {
+    buffer.to_bitmap()
+}
+
+/// Year, month and day of the days since 1970-01-01, from Howard Hinnant's date algorithms.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+/// `screenshot_<utc date>_<utc time>_<milliseconds>[_frame<index>].<extension>`, which sorts by
+/// the time it was taken.
+pub fn screenshot_filename(
+    since_epoch: Duration,
+    frame_index: Option<u64>,
+    format: ScreenshotFormat,
+) -> String {
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let time_of_day = seconds % 86400;
+    let frame = frame_index
+        .map(|index| format!("_frame{index}"))
+        .unwrap_or_default();
+    format!(
+        "screenshot_{year:04}{month:02}{day:02}_{:02}{:02}{:02}_{:03}{frame}.{}",
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60,
+        since_epoch.subsec_millis(),
+        format.extension()
+    )
+}
+
+/// Saves the buffer into `directory`, which gets created if needed, and returns the file's path.
+pub fn save_screenshot(
+    buffer: &mut GameOffscreenBuffer,
+    directory: &Path,
+    format: ScreenshotFormat,
+    frame_index: Option<u64>,
+) -> io::Result<PathBuf> {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let path = directory.join(screenshot_filename(since_epoch, frame_index, format));
+
+    std::fs::create_dir_all(directory)?;
+    debug_platform_write_entire_file(&path, format.encode(&capture_buffer(buffer)))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod screenshot_tests {
+    use std::time::Duration;
+
+    use utility::image::parse_image;
+
+    use crate::game::{GameOffscreenBuffer, PixelFormat};
+    use crate::platform::screenshot::{
+        capture_buffer, civil_from_days, screenshot_filename, ScreenshotFormat,
+    };
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(11017), (2000, 3, 1));
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+    }
+
+    #[test]
+    fn filenames() {
+        let time = Duration::from_millis(1_709_251_199_042);
+        assert_eq!(
+            screenshot_filename(time, None, ScreenshotFormat::Png),
+            "screenshot_20240229_235959_042.png"
+        );
+        assert_eq!(
+            screenshot_filename(time, Some(120), ScreenshotFormat::Bmp),
+            "screenshot_20240229_235959_042_frame120.bmp"
+        );
+    }
+
+    #[test]
+    fn capture_converts_bgrx() {
+        // B G R X bytes, with junk in X
+        #[rustfmt::skip]
+        let bytes: [u8; 16] = [
+            0x30, 0x20, 0x10, 0x00,  0x60, 0x50, 0x40, 0x7F,
+            0x00, 0x00, 0xFF, 0x00,  0xFF, 0x00, 0x00, 0x12,
+        ];
+        let mut memory: Vec<u32> = bytes
+            .chunks_exact(4)
+            .map(|pixel| u32::from_le_bytes(pixel.try_into().unwrap()))
+            .collect();
+        let mut buffer = unsafe {
+            GameOffscreenBuffer::new(memory.as_mut_ptr().cast(), 2, 2, PixelFormat::Bgra8)
+        }
+        .unwrap();
+        let bitmap = capture_buffer(&buffer);
+        assert_eq!(
+            bitmap.pixels(),
+            &[0xFF102030, 0xFF405060, 0xFFFF0000, 0xFF0000FF]
+        );
+
+        for format in [ScreenshotFormat::Png, ScreenshotFormat::Bmp] {
+            let decoded = parse_image(&format.encode(&bitmap)).unwrap();
+            assert_eq!(decoded.pixels(), bitmap.pixels(), "{format:?}");
+        }
+    }
+}
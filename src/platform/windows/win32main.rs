@@ -1,22 +1,28 @@
 use crate::game::game_update_and_render;
 use crate::game::{
-    ButtonInput, GameControllerInput, GameInput, GameMemory, GameOffscreenBuffer, GameSoundBuffer,
+    DirtyRects, GameControllerInput, GameInput, GameMemory, GameOffscreenBuffer, GameSoundBuffer,
+    PixelFormat,
 };
+use crate::platform::input::{
+    begin_keyboard_frame, process_gamepad, process_key, release_keyboard, Key, RawGamepadState,
+};
+use crate::platform::input_recording::{InputRecorder, RawInputEvent};
+use crate::platform::presentation::{buffer_size_for_window, Presentation, PresentationMode};
+use crate::platform::screenshot::save_screenshot;
 use crate::platform::windows::win32_x_input::{
-    load_xinput, XInputGamepad, XinputState, XINPUT_GAMEPAD_A, XINPUT_GAMEPAD_B,
-    XINPUT_GAMEPAD_BACK, XINPUT_GAMEPAD_DPAD_DOWN, XINPUT_GAMEPAD_DPAD_LEFT,
-    XINPUT_GAMEPAD_DPAD_RIGHT, XINPUT_GAMEPAD_DPAD_UP, XINPUT_GAMEPAD_LEFT_SHOULDER,
-    XINPUT_GAMEPAD_RIGHT_SHOULDER, XINPUT_GAMEPAD_START, XINPUT_GAMEPAD_X, XINPUT_GAMEPAD_Y,
-    XINPUT_GET_STATE, XUSER_MAX_COUNT,
+    load_xinput, XinputState, XINPUT_GET_STATE, XUSER_MAX_COUNT,
 };
+use crate::platform::{debug_platform_write_entire_file, PlatformOptions};
 use std::arch::x86_64::_rdtsc;
 use std::cmp::max;
 use std::ffi::c_void;
 use std::fmt::Debug;
 use std::mem;
+use std::path::PathBuf;
 use std::ptr::null_mut;
 use std::time::Duration;
 use utility::ring_buffer::RingBuffer;
+use utility::work_queue::WorkQueue;
 use utility::{gigabytes, megabytes};
 
 use windows::Win32::Media::{timeBeginPeriod, TIMERR_NOERROR};
@@ -49,6 +55,14 @@ macro_rules! has_bitflag {
 global_mut!(PERFORMANCE_FREQUENCY: i64 = 0);
 //TODO(voided): This is a global for now.
 global_mut!(RUN_STATE: RunState = RunState::Starting);
+global_mut!(APP_IS_ACTIVE: bool = true);
+global_mut!(SCREENSHOT_REQUESTED: bool = false);
+global_mut!(PRESENTATION_MODE: PresentationMode = PresentationMode::Fit);
+//Note(voided): set when the back buffer follows the window size, see --resize-buffer
+global_mut!(BUFFER_SCALE: Option<f32> = None);
+global_mut!(BUFFER_RESIZED: bool = false);
+//Note(voided): what the window shows right now, partial presents only work on top of the same one
+global_mut!(LAST_PRESENTATION: Option<Presentation> = None);
 global_mut!(GLOBAL_BACK_BUFFER: OffscreenBuffer = OffscreenBuffer {
     info: BITMAPINFO {
         bmiHeader: BITMAPINFOHEADER {
@@ -107,18 +121,29 @@ unsafe fn window_dimension(window: HWND) -> (i32, i32) {
     (width, height)
 }
 
-unsafe fn resize_dib_section(buffer: &mut OffscreenBuffer, width: i32, height: i32) {
-    //TODO(voided): bulletproof this.
-    //maybe don't free first, free after, then free first if that fails.
+/// False if the new buffer couldn't be allocated, the old one stays as it was then.
+unsafe fn resize_dib_section(buffer: &mut OffscreenBuffer, width: i32, height: i32) -> bool {
+    //Note(voided): allocate the new buffer before freeing the old one, so a failed allocation
+    //leaves us with something to draw into.
+    let bytes_per_pixel = 4;
+    let memory = VirtualAlloc(
+        None,
+        (width * height * bytes_per_pixel) as usize,
+        MEM_COMMIT,
+        PAGE_READWRITE,
+    );
+    if memory.is_null() {
+        return false;
+    }
 
     if !buffer.memory.is_null() {
         VirtualFree(buffer.memory, 0, MEM_RELEASE).ok();
-        buffer.memory = null_mut();
     }
 
+    buffer.memory = memory;
     buffer.width = width;
     buffer.height = height;
-    buffer.bytes_per_pixel = 4;
+    buffer.bytes_per_pixel = bytes_per_pixel;
 
     buffer.info.bmiHeader.biSize = mem::size_of::<BITMAPINFOHEADER>() as u32;
     buffer.info.bmiHeader.biWidth = buffer.width;
@@ -126,30 +151,68 @@ unsafe fn resize_dib_section(buffer: &mut OffscreenBuffer, width: i32, height: i
     buffer.info.bmiHeader.biPlanes = 1;
     buffer.info.bmiHeader.biBitCount = 32;
     buffer.info.bmiHeader.biCompression = BI_RGB.0;
-
-    buffer.memory = VirtualAlloc(
-        None,
-        buffer.memory_size() as usize,
-        MEM_COMMIT,
-        PAGE_READWRITE,
-    );
+    true
 }
 
 unsafe fn win32_display_buffer_in_window(
     buffer: &OffscreenBuffer,
     device_context: HDC,
-    x: i32,
-    y: i32,
     window_width: i32,
     window_height: i32,
+    dirty: Option<&DirtyRects>,
 ) {
-    //TODO(voided): Aspect ratio correction
+    let presentation = Presentation::new(
+        PRESENTATION_MODE,
+        (buffer.width, buffer.height),
+        (window_width, window_height),
+    );
+    let last_presentation = LAST_PRESENTATION;
+    LAST_PRESENTATION = Some(presentation);
+
+    let parts = dirty.and_then(|dirty| presentation.dirty_parts(last_presentation.as_ref(), dirty));
+    if let Some(parts) = parts {
+        for &part in parts {
+            let destination = presentation.buffer_to_window(part);
+            let source = presentation.dib_source(part);
+            StretchDIBits(
+                device_context,
+                destination.min_x,
+                destination.min_y,
+                destination.width(),
+                destination.height(),
+                source.min_x,
+                source.min_y,
+                source.width(),
+                source.height(),
+                Some(buffer.memory),
+                &buffer.info,
+                DIB_RGB_COLORS,
+                SRCCOPY,
+            );
+        }
+        return;
+    }
+
+    //Note(voided): the bars get cleared every time, otherwise whatever was there before a resize
+    //stays on screen
+    for bar in presentation.bars() {
+        PatBlt(
+            device_context,
+            bar.min_x,
+            bar.min_y,
+            bar.width(),
+            bar.height(),
+            BLACKNESS,
+        );
+    }
+
+    let destination = presentation.destination;
     StretchDIBits(
         device_context,
-        x,
-        y,
-        window_width,
-        window_height,
+        destination.min_x,
+        destination.min_y,
+        destination.width(),
+        destination.height(),
         0,
         0,
         buffer.width,
@@ -176,10 +239,25 @@ pub unsafe extern "system" fn window_procedure(
             RUN_STATE = RunState::Stopping;
         }
         WM_ACTIVATEAPP => {
-            println!("WM_ACTIVATEAPP");
+            APP_IS_ACTIVE = w_param.0 != 0;
+            println!("WM_ACTIVATEAPP {APP_IS_ACTIVE}");
         }
         WM_SIZE => {
-            println!("WM_SIZE");
+            let window_width = (l_param.0 & 0xFFFF) as i32;
+            let window_height = ((l_param.0 >> 16) & 0xFFFF) as i32;
+            println!("WM_SIZE {window_width}x{window_height}");
+
+            let new_size = BUFFER_SCALE
+                .and_then(|scale| buffer_size_for_window((window_width, window_height), scale));
+            if let Some((width, height)) = new_size {
+                if (width, height) != (GLOBAL_BACK_BUFFER.width, GLOBAL_BACK_BUFFER.height) {
+                    if resize_dib_section(&mut GLOBAL_BACK_BUFFER, width, height) {
+                        BUFFER_RESIZED = true;
+                    } else {
+                        println!("Failed to resize the back buffer to {width}x{height}");
+                    }
+                }
+            }
         }
         WM_CLOSE => {
             println!("WM_CLOSE");
@@ -200,16 +278,12 @@ pub unsafe extern "system" fn window_procedure(
             let mut paint: PAINTSTRUCT = PAINTSTRUCT::default();
             let hdc = BeginPaint(window, &mut paint);
 
-            let x = paint.rcPaint.left;
-            let y = paint.rcPaint.top;
-
             win32_display_buffer_in_window(
                 &GLOBAL_BACK_BUFFER,
                 hdc,
-                x,
-                y,
                 window_width,
                 window_height,
+                None,
             );
             EndPaint(window, &paint);
         }
@@ -221,36 +295,29 @@ pub unsafe extern "system" fn window_procedure(
     result
 }
 
-fn win32_process_x_input_digital_button(
-    input: &XInputGamepad,
-    button: u16,
-    old_state: &ButtonInput,
-    new_state: &mut ButtonInput,
-) {
-    new_state.button_is_down = has_bitflag!(input.wButtons, button);
-    new_state.half_transitions = if old_state.button_is_down != new_state.button_is_down {
-        1
-    } else {
-        0
-    };
-}
-
-fn normalize_i16(x: i16) -> f32 {
-    x as f32 / i16::MAX as f32
-}
-
-fn win32_process_stick(stick_position: i16, stick_deadzone: f32) -> f32 {
-    let position_normalized = normalize_i16(stick_position);
-
-    if position_normalized.abs() >= stick_deadzone {
-        position_normalized.signum() * (position_normalized.abs() - stick_deadzone)
-            / (1.0 - stick_deadzone)
-    } else {
-        0.0
+fn win32_map_key(vk_code: VIRTUAL_KEY) -> Option<Key> {
+    match vk_code {
+        VK_W => Some(Key::W),
+        VK_A => Some(Key::A),
+        VK_S => Some(Key::S),
+        VK_D => Some(Key::D),
+        VK_Q => Some(Key::Q),
+        VK_E => Some(Key::E),
+        VK_UP => Some(Key::Up),
+        VK_DOWN => Some(Key::Down),
+        VK_LEFT => Some(Key::Left),
+        VK_RIGHT => Some(Key::Right),
+        VK_ESCAPE => Some(Key::Escape),
+        VK_SPACE => Some(Key::Space),
+        _ => None,
     }
 }
 
-unsafe fn win32_process_pending_messages(keyboard_controller: &mut GameControllerInput) {
+unsafe fn win32_process_pending_messages(
+    keyboard_controller: &mut GameControllerInput,
+    mut recorder: Option<&mut InputRecorder>,
+    start_counter: i64,
+) {
     let mut message = Default::default();
     while PeekMessageW(&mut message, None, 0, 0, PM_REMOVE).as_bool() {
         match message.message {
@@ -267,86 +334,25 @@ unsafe fn win32_process_pending_messages(keyboard_controller: &mut GameControlle
                 let key_alt_is_down = has_bitflag!(l_param, KEY_ALT_IS_DOWN_FLAG);
 
                 if key_is_down != key_was_down {
-                    match vk_code {
-                        VK_W => {
-                            win32_process_keyboard_message(
-                                &mut keyboard_controller.move_up,
-                                key_is_down,
-                            );
-                        }
-                        VK_A => {
-                            win32_process_keyboard_message(
-                                &mut keyboard_controller.move_left,
-                                key_is_down,
-                            );
-                        }
-                        VK_S => {
-                            win32_process_keyboard_message(
-                                &mut keyboard_controller.move_down,
-                                key_is_down,
-                            );
+                    if let Some(key) = win32_map_key(vk_code) {
+                        if let Some(recorder) = recorder.as_deref_mut() {
+                            recorder.record(RawInputEvent::Key {
+                                key,
+                                is_down: key_is_down,
+                                timestamp_us: win32_get_timestamp_us(start_counter),
+                            });
                         }
-                        VK_D => {
-                            win32_process_keyboard_message(
-                                &mut keyboard_controller.move_right,
-                                key_is_down,
-                            );
-                        }
-                        VK_Q => {
-                            win32_process_keyboard_message(
-                                &mut keyboard_controller.shoulder_left,
-                                key_is_down,
-                            );
-                        }
-                        VK_E => {
-                            win32_process_keyboard_message(
-                                &mut keyboard_controller.shoulder_right,
-                                key_is_down,
-                            );
-                        }
-                        VK_UP => {
-                            win32_process_keyboard_message(
-                                &mut keyboard_controller.action_up,
-                                key_is_down,
-                            );
-                        }
-                        VK_DOWN => {
-                            win32_process_keyboard_message(
-                                &mut keyboard_controller.action_down,
-                                key_is_down,
-                            );
-                        }
-                        VK_LEFT => {
-                            win32_process_keyboard_message(
-                                &mut keyboard_controller.action_left,
-                                key_is_down,
-                            );
-                        }
-                        VK_RIGHT => {
-                            win32_process_keyboard_message(
-                                &mut keyboard_controller.action_right,
-                                key_is_down,
-                            );
-                        }
-                        VK_ESCAPE => {
-                            win32_process_keyboard_message(
-                                &mut keyboard_controller.start,
-                                key_is_down,
-                            );
-                        }
-                        VK_SPACE => {
-                            win32_process_keyboard_message(
-                                &mut keyboard_controller.back,
-                                key_is_down,
-                            );
-                        }
-                        _ => {}
+                        process_key(keyboard_controller, key, key_is_down);
                     }
                 }
 
                 if vk_code == VK_F4 && key_alt_is_down {
                     RUN_STATE = RunState::Stopping;
                 }
+
+                if vk_code == VK_F12 && key_is_down && !key_was_down {
+                    SCREENSHOT_REQUESTED = true;
+                }
             }
             _ => {
                 TranslateMessage(&message);
@@ -356,12 +362,6 @@ unsafe fn win32_process_pending_messages(keyboard_controller: &mut GameControlle
     }
 }
 
-fn win32_process_keyboard_message(new_button_state: &mut ButtonInput, key_is_down: bool) {
-    debug_assert!(new_button_state.button_is_down != key_is_down);
-    new_button_state.button_is_down = key_is_down;
-    new_button_state.half_transitions += 1;
-}
-
 #[inline]
 unsafe fn win32_get_wall_clock() -> i64 {
     let mut end_counter = Default::default();
@@ -374,15 +374,21 @@ unsafe fn win32_get_elapsed_seconds(start: i64, end: i64) -> f32 {
     (end - start) as f32 / PERFORMANCE_FREQUENCY as f32
 }
 
-pub fn win32main() {
+unsafe fn win32_get_timestamp_us(start: i64) -> u64 {
+    ((win32_get_wall_clock() - start) as i128 * 1_000_000 / PERFORMANCE_FREQUENCY as i128) as u64
+}
+
+pub fn win32main(options: PlatformOptions) {
     unsafe {
         // sets windows scheduler time | sleep granularity
         let sleep_is_granular = timeBeginPeriod(1) == TIMERR_NOERROR;
+        PRESENTATION_MODE = options.presentation_mode;
 
         // TODO(voided): get actual get refresh rate reliably?
         let monitor_refresh_hz = 60;
         let game_update_hz = monitor_refresh_hz / 2;
         let target_seconds_per_frame = 1.0 / game_update_hz as f32;
+        const BACKGROUND_UPDATE_HZ: u32 = 10;
 
         {
             let x_input = load_xinput();
@@ -415,9 +421,16 @@ pub fn win32main() {
                 MEM_COMMIT,
                 PAGE_READWRITE,
             ),
+            //Note(voided): the data directory sits next to where the game gets started from
+            asset_root: PathBuf::from("."),
+            work_queue: WorkQueue::for_all_cores(),
         };
 
-        resize_dib_section(&mut GLOBAL_BACK_BUFFER, 1280, 720);
+        BUFFER_SCALE = options.resize_buffer;
+        if !resize_dib_section(&mut GLOBAL_BACK_BUFFER, 1280, 720) {
+            println!("Failed to allocate the back buffer!");
+            return;
+        }
 
         let instance = GetModuleHandleW(None).expect("failed to lodd instance");
         debug_assert!(instance.0 != 0);
@@ -458,32 +471,58 @@ pub fn win32main() {
         QueryPerformanceFrequency(&mut PERFORMANCE_FREQUENCY).ok();
 
         let mut last_counter = win32_get_wall_clock();
+        let start_counter = last_counter;
+
+        let mut raw_input_recorder = options
+            .record_raw_input
+            .is_some()
+            .then(InputRecorder::default);
+
+        let mut frame_dump = options.frame_dump(game_update_hz);
 
         let mut old_inputs = GameInput::default();
         let mut new_inputs = GameInput::default();
+        let mut had_focus = true;
 
         if !game_memory.permanent_storage.is_null() {
             while RUN_STATE != RunState::Stopping {
-                new_inputs[0] = Default::default();
-                let new_keyboard_controller = &mut new_inputs[0];
-                let old_keyboard_controller = &mut old_inputs[0];
-
-                for (new_button, old_button) in new_keyboard_controller
-                    .buttons_mut()
-                    .into_iter()
-                    .zip(old_keyboard_controller.buttons().into_iter())
-                {
-                    new_button.button_is_down = old_button.button_is_down;
-                }
+                begin_keyboard_frame(&old_inputs[0], &mut new_inputs[0]);
 
-                new_keyboard_controller.is_connected = true;
+                win32_process_pending_messages(
+                    &mut new_inputs[0],
+                    raw_input_recorder.as_mut(),
+                    start_counter,
+                );
 
-                win32_process_pending_messages(new_keyboard_controller);
+                let has_focus = APP_IS_ACTIVE;
+                if has_focus != had_focus {
+                    if let Some(recorder) = raw_input_recorder.as_mut() {
+                        recorder.record(RawInputEvent::Focus {
+                            has_focus,
+                            timestamp_us: win32_get_timestamp_us(start_counter),
+                        });
+                    }
+                    if !has_focus {
+                        //Note(voided): we don't get the key ups from other windows
+                        release_keyboard(&mut new_inputs[0]);
+                    }
+                    had_focus = has_focus;
+                }
+                new_inputs.has_focus = has_focus;
+                new_inputs.buffer_resized = BUFFER_RESIZED;
+                BUFFER_RESIZED = false;
+
+                let throttled = options.background_throttle && !has_focus;
+                let target_seconds_per_frame = if throttled {
+                    1.0 / BACKGROUND_UPDATE_HZ as f32
+                } else {
+                    target_seconds_per_frame
+                };
 
                 //TODO(voided): Update to a more modern api.
                 //TODO(voided): Test how to dynamically load XInput in case it's not available. (day 6 - 22:00)
                 //TODO(voided): Should we poll this more frequently.
-                let max_controllers = max(new_inputs.len() as u32 - 1, XUSER_MAX_COUNT);
+                let max_controllers = max(new_inputs.controllers.len() as u32 - 1, XUSER_MAX_COUNT);
                 let mut controller_state = XinputState::default();
                 for controller_index in 0..max_controllers {
                     let old_input = &mut old_inputs[controller_index as usize + 1];
@@ -491,156 +530,39 @@ pub fn win32main() {
 
                     let result = XINPUT_GET_STATE(controller_index, &mut controller_state);
 
-                    if result == ERROR_SUCCESS.0 {
-                        //Note(voided): Controller is plugged in.
-                        new_input.is_connected = true;
-                        //TODO(voided): See if controller_state.dwPacketNumber increments too rapidly.
-
-                        let gamepad = &controller_state.Gamepad;
-                        #[allow(unused)]
-                        let keypad_up = has_bitflag!(gamepad.wButtons, XINPUT_GAMEPAD_DPAD_UP);
-                        #[allow(unused)]
-                        let keypad_down = has_bitflag!(gamepad.wButtons, XINPUT_GAMEPAD_DPAD_DOWN);
-                        #[allow(unused)]
-                        let keypad_left = has_bitflag!(gamepad.wButtons, XINPUT_GAMEPAD_DPAD_LEFT);
-                        #[allow(unused)]
-                        let keypad_right =
-                            has_bitflag!(gamepad.wButtons, XINPUT_GAMEPAD_DPAD_RIGHT);
-
-                        const STICK_DEADZONE: f32 = 0.1;
-                        const THRESHOLD: f32 = 0.5;
-
-                        new_input.stick_left.x_average =
-                            win32_process_stick(gamepad.sThumbLX, STICK_DEADZONE);
-                        new_input.stick_left.y_average =
-                            win32_process_stick(gamepad.sThumbLY, STICK_DEADZONE);
-
-                        if new_input.stick_left.x_average != 0.0
-                            || new_input.stick_left.y_average != 0.0
-                        {
-                            new_input.is_analog = true;
-                        }
-
-                        if has_bitflag!(gamepad.wButtons, XINPUT_GAMEPAD_DPAD_UP) {
-                            new_input.stick_left.y_average = 1.0;
-                            new_input.is_analog = false;
-                        } else if has_bitflag!(gamepad.wButtons, XINPUT_GAMEPAD_DPAD_DOWN) {
-                            new_input.stick_left.y_average = -1.0;
-                            new_input.is_analog = false;
-                        }
-
-                        if has_bitflag!(gamepad.wButtons, XINPUT_GAMEPAD_DPAD_LEFT) {
-                            new_input.stick_left.x_average = -1.0;
-                            new_input.is_analog = false;
-                        } else if has_bitflag!(gamepad.wButtons, XINPUT_GAMEPAD_DPAD_RIGHT) {
-                            new_input.stick_left.x_average = 1.0;
-                            new_input.is_analog = false;
-                        }
-
-                        let mut fake_button_inputs: XInputGamepad = Default::default();
-                        const FAKE_BUTTON_UP: u16 = 1;
-                        const FAKE_BUTTON_DOWN: u16 = 2;
-                        const FAKE_BUTTON_LEFT: u16 = 4;
-                        const FAKE_BUTTON_RIGHT: u16 = 8;
-
-                        if new_input.stick_left.y_average >= THRESHOLD {
-                            fake_button_inputs.wButtons += FAKE_BUTTON_UP;
-                        } else if new_input.stick_left.y_average.abs() >= THRESHOLD {
-                            fake_button_inputs.wButtons += FAKE_BUTTON_DOWN;
-                        }
+                    //TODO(voided): See if controller_state.dwPacketNumber increments too rapidly.
+                    let gamepad = (result == ERROR_SUCCESS.0).then(|| RawGamepadState {
+                        buttons: controller_state.Gamepad.wButtons,
+                        stick_left_x: controller_state.Gamepad.sThumbLX,
+                        stick_left_y: controller_state.Gamepad.sThumbLY,
+                    });
+
+                    if let Some(recorder) = raw_input_recorder.as_mut() {
+                        recorder.record(RawInputEvent::Gamepad {
+                            index: controller_index as usize,
+                            state: gamepad,
+                            timestamp_us: win32_get_timestamp_us(start_counter),
+                        });
+                    }
 
-                        if new_input.stick_left.x_average >= THRESHOLD {
-                            fake_button_inputs.wButtons += FAKE_BUTTON_RIGHT;
-                        } else if new_input.stick_left.x_average.abs() >= THRESHOLD {
-                            fake_button_inputs.wButtons += FAKE_BUTTON_LEFT;
-                        }
+                    process_gamepad(gamepad.as_ref(), old_input, new_input);
+                }
 
-                        win32_process_x_input_digital_button(
-                            &fake_button_inputs,
-                            FAKE_BUTTON_UP,
-                            &old_input.move_up,
-                            &mut new_input.move_up,
-                        );
-                        win32_process_x_input_digital_button(
-                            &fake_button_inputs,
-                            FAKE_BUTTON_DOWN,
-                            &old_input.move_down,
-                            &mut new_input.move_down,
-                        );
-                        win32_process_x_input_digital_button(
-                            &fake_button_inputs,
-                            FAKE_BUTTON_LEFT,
-                            &old_input.move_left,
-                            &mut new_input.move_left,
-                        );
-                        win32_process_x_input_digital_button(
-                            &fake_button_inputs,
-                            FAKE_BUTTON_RIGHT,
-                            &old_input.move_right,
-                            &mut new_input.move_right,
-                        );
-
-                        win32_process_x_input_digital_button(
-                            gamepad,
-                            XINPUT_GAMEPAD_START,
-                            &old_input.start,
-                            &mut new_input.start,
-                        );
-                        win32_process_x_input_digital_button(
-                            gamepad,
-                            XINPUT_GAMEPAD_BACK,
-                            &old_input.back,
-                            &mut new_input.back,
-                        );
-                        win32_process_x_input_digital_button(
-                            gamepad,
-                            XINPUT_GAMEPAD_Y,
-                            &old_input.action_up,
-                            &mut new_input.action_up,
-                        );
-                        win32_process_x_input_digital_button(
-                            gamepad,
-                            XINPUT_GAMEPAD_A,
-                            &old_input.action_down,
-                            &mut new_input.action_down,
-                        );
-                        win32_process_x_input_digital_button(
-                            gamepad,
-                            XINPUT_GAMEPAD_X,
-                            &old_input.action_left,
-                            &mut new_input.action_left,
-                        );
-                        win32_process_x_input_digital_button(
-                            gamepad,
-                            XINPUT_GAMEPAD_B,
-                            &old_input.action_right,
-                            &mut new_input.action_right,
-                        );
-                        win32_process_x_input_digital_button(
-                            gamepad,
-                            XINPUT_GAMEPAD_LEFT_SHOULDER,
-                            &old_input.shoulder_left,
-                            &mut new_input.shoulder_left,
-                        );
-                        win32_process_x_input_digital_button(
-                            gamepad,
-                            XINPUT_GAMEPAD_RIGHT_SHOULDER,
-                            &old_input.shoulder_right,
-                            &mut new_input.shoulder_right,
-                        );
-                    } else {
-                        //Note(Voided): Controller is not available.
-                        new_input.is_connected = false;
-                    }
+                if let Some(recorder) = raw_input_recorder.as_mut() {
+                    recorder.record(RawInputEvent::FrameEnd {
+                        timestamp_us: win32_get_timestamp_us(start_counter),
+                    });
                 }
 
                 // game_update_and_render();
-                let mut buffer = GameOffscreenBuffer {
-                    memory: GLOBAL_BACK_BUFFER.memory,
-                    width: GLOBAL_BACK_BUFFER.width,
-                    height: GLOBAL_BACK_BUFFER.height,
-                    bytes_per_pixel: GLOBAL_BACK_BUFFER.bytes_per_pixel,
-                };
+                //Note(voided): resize_dib_section sets up a 32 bit BI_RGB section, B G R X
+                let mut buffer = GameOffscreenBuffer::new(
+                    GLOBAL_BACK_BUFFER.memory,
+                    GLOBAL_BACK_BUFFER.width,
+                    GLOBAL_BACK_BUFFER.height,
+                    PixelFormat::Bgra8,
+                )
+                .expect("back buffer is not usable by the game");
 
                 let s = audio_source.source();
                 let mut s = s.lock().expect("failed to lock source");
@@ -658,6 +580,32 @@ pub fn win32main() {
                     &mut buffer,
                     &mut sound_buffer,
                 );
+                let dirty = *buffer.dirty();
+
+                if throttled {
+                    sound_buffer_mem.fill(0.0);
+                }
+
+                //Note(voided): the dump starts once the audio device told us its sample rate
+                if let Some(dump) = frame_dump.as_mut().filter(|_| rate > 0) {
+                    if let Err(err) = dump.write_frame(&buffer, sound_buffer_mem, rate) {
+                        println!("Stopped dumping frames: {err}");
+                        frame_dump = None;
+                    }
+                }
+
+                if SCREENSHOT_REQUESTED {
+                    SCREENSHOT_REQUESTED = false;
+                    match save_screenshot(
+                        &mut buffer,
+                        options.screenshot_directory(),
+                        options.screenshot_format,
+                        None,
+                    ) {
+                        Ok(path) => println!("Saved screenshot to {}", path.display()),
+                        Err(err) => println!("Failed to save screenshot: {err}"),
+                    }
+                }
 
                 let (l, r) = s.buffer.write_buffers(to_fill);
                 for (t, s) in l
@@ -678,7 +626,11 @@ pub fn win32main() {
                 if seconds_elapsed_this_frame < target_seconds_per_frame {
                     let time_to_wait = target_seconds_per_frame - seconds_elapsed_this_frame;
                     if sleep_is_granular {
-                        std::thread::sleep(Duration::from_millis((time_to_wait / 1000.0) as u64));
+                        //Note(voided): the scheduler can wake us up late, so the last millisecond
+                        //gets spun through instead of slept. A throttled background frame sleeps
+                        //nearly all of its 100ms.
+                        let sleep_ms = (time_to_wait * 1000.0) as u64;
+                        std::thread::sleep(Duration::from_millis(sleep_ms.saturating_sub(1)));
                         seconds_elapsed_this_frame =
                             win32_get_elapsed_seconds(last_counter, win32_get_wall_clock());
                     }
@@ -718,14 +670,28 @@ pub fn win32main() {
                 win32_display_buffer_in_window(
                     &GLOBAL_BACK_BUFFER,
                     device_context,
-                    0,
-                    0,
                     window_width,
                     window_height,
+                    Some(&dirty),
                 );
 
                 ReleaseDC(window, device_context);
             }
         }
+
+        if let Some(dump) = frame_dump {
+            if let Err(err) = dump.finish() {
+                println!("Failed to finish the frame dump: {err}");
+            }
+        }
+
+        if let (Some(path), Some(recorder)) = (&options.record_raw_input, &raw_input_recorder) {
+            if let Err(err) = debug_platform_write_entire_file(path, recorder.to_trace()) {
+                println!(
+                    "Failed to write raw input trace to {}: {err}",
+                    path.display()
+                );
+            }
+        }
     }
 }
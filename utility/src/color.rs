@@ -0,0 +1,208 @@
+// Color spaces. Images and the back buffer hold sRGB encoded 8 bit channels, which spend more of
+// their values on dark colors. Blending, interpolating or lighting those values directly makes
+// everything come out too dark, so the math happens on linear colors: `V4`s with 0..=1 channels,
+// x y z w being r g b a. Alpha is never encoded, it's linear in both.
+
+use std::sync::OnceLock;
+
+use crate::math::{v4, V4};
+
+/// The exact sRGB curve, 0..=1 in and out.
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// A gamma of 2 instead of the sRGB curve, cheap enough for anything per pixel. Off by up to
+/// about 0.1 in the darks, see the tests.
+pub fn srgb_to_linear_fast(value: f32) -> f32 {
+    value * value
+}
+
+pub fn linear_to_srgb_fast(value: f32) -> f32 {
+    value.max(0.0).sqrt()
+}
+
+pub fn srgb_color_to_linear(color: V4) -> V4 {
+    v4(
+        srgb_to_linear(color.r()),
+        srgb_to_linear(color.g()),
+        srgb_to_linear(color.b()),
+        color.a(),
+    )
+}
+
+pub fn linear_color_to_srgb(color: V4) -> V4 {
+    v4(
+        linear_to_srgb(color.r()),
+        linear_to_srgb(color.g()),
+        linear_to_srgb(color.b()),
+        color.a(),
+    )
+}
+
+/// Premultiplies a linear color by its alpha.
+pub fn premultiply_color(color: V4) -> V4 {
+    v4(
+        color.r() * color.a(),
+        color.g() * color.a(),
+        color.b() * color.a(),
+        color.a(),
+    )
+}
+
+/// Undoes [`premultiply_color`], colors with an alpha of 0 become transparent black.
+pub fn unpremultiply_color(color: V4) -> V4 {
+    if color.a() <= 0.0 {
+        return V4::default();
+    }
+    v4(
+        color.r() / color.a(),
+        color.g() / color.a(),
+        color.b() / color.a(),
+        color.a(),
+    )
+}
+
+/// 0..=1 channel to 0..=255, rounded.
+fn to_byte(value: f32) -> u32 {
+    (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u32
+}
+
+/// Linear channel to an 8 bit sRGB one, with the exact curve.
+pub fn encode_srgb8(linear: f32) -> u32 {
+    to_byte(linear_to_srgb(linear))
+}
+
+pub fn decode_srgb8(channel: u32) -> f32 {
+    srgb_to_linear((channel & 0xFF) as f32 / 255.0)
+}
+
+/// Linear color to a 0xAARRGGBB pixel with sRGB encoded channels.
+pub fn pack_srgb8(color: V4) -> u32 {
+    let color = linear_color_to_srgb(color);
+    to_byte(color.a()) << 24
+        | to_byte(color.r()) << 16
+        | to_byte(color.g()) << 8
+        | to_byte(color.b())
+}
+
+pub fn unpack_srgb8(pixel: u32) -> V4 {
+    let channel = |shift: u32| ((pixel >> shift) & 0xFF) as f32 / 255.0;
+    srgb_color_to_linear(v4(channel(16), channel(8), channel(0), channel(24)))
+}
+
+/// Steps of the linear to sRGB table, enough that every 8 bit value survives a round trip.
+pub const LINEAR_STEPS: usize = 4096;
+
+/// Lookup tables for converting 8 bit channels in the inner loops.
+pub struct SrgbTables {
+    pub to_linear: [f32; 256],
+    /// Note(voided): u32s so SIMD code can gather from it.
+    pub to_srgb: [u32; LINEAR_STEPS],
+}
+
+impl SrgbTables {
+    /// Built the first time something asks for them.
+    pub fn get() -> &'static Self {
+        static TABLES: OnceLock<Box<SrgbTables>> = OnceLock::new();
+        TABLES.get_or_init(|| {
+            let mut tables = Box::new(SrgbTables {
+                to_linear: [0.0; 256],
+                to_srgb: [0; LINEAR_STEPS],
+            });
+            for (value, linear) in tables.to_linear.iter_mut().enumerate() {
+                *linear = srgb_to_linear(value as f32 / 255.0);
+            }
+            for (step, srgb) in tables.to_srgb.iter_mut().enumerate() {
+                *srgb = to_byte(linear_to_srgb(step as f32 / (LINEAR_STEPS - 1) as f32));
+            }
+            tables
+        })
+    }
+
+    pub fn decode(&self, channel: u32) -> f32 {
+        self.to_linear[(channel & 0xFF) as usize]
+    }
+
+    /// Where `linear` lands in [`Self::to_srgb`].
+    pub fn encode_index(linear: f32) -> usize {
+        (linear.clamp(0.0, 1.0) * (LINEAR_STEPS - 1) as f32 + 0.5) as usize
+    }
+
+    pub fn encode(&self, linear: f32) -> u32 {
+        self.to_srgb[Self::encode_index(linear)]
+    }
+}
+
+#[cfg(test)]
+mod color_tests {
+    use crate::color::{
+        linear_to_srgb, linear_to_srgb_fast, pack_srgb8, premultiply_color, srgb_to_linear,
+        srgb_to_linear_fast, unpack_srgb8, unpremultiply_color, SrgbTables,
+    };
+    use crate::math::v4;
+
+    #[test]
+    fn known_values() {
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb_to_linear(0.5) - 0.21404).abs() < 1e-5);
+        assert!((linear_to_srgb(0.5) - 0.73536).abs() < 1e-5);
+        // the linear part at the bottom
+        assert!((srgb_to_linear(0.04) - 0.04 / 12.92).abs() < 1e-9);
+    }
+
+    #[test]
+    fn round_trips() {
+        for step in 0..=1000 {
+            let value = step as f32 / 1000.0;
+            assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-5);
+            assert!((srgb_to_linear(linear_to_srgb(value)) - value).abs() < 1e-5);
+            let fast = linear_to_srgb_fast(srgb_to_linear_fast(value));
+            assert!((fast - value).abs() < 1e-6);
+            assert!((srgb_to_linear_fast(value) - srgb_to_linear(value)).abs() < 0.11);
+            assert!((linear_to_srgb_fast(value) - linear_to_srgb(value)).abs() < 0.11);
+        }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let tables = SrgbTables::get();
+        for value in 0..=255 {
+            assert_eq!(tables.encode(tables.decode(value)), value);
+            let pixel = value << 24 | value << 16 | ((255 - value) << 8) | (value / 2);
+            assert_eq!(pack_srgb8(unpack_srgb8(pixel)), pixel);
+        }
+        assert_eq!(tables.encode(-1.0), 0);
+        assert_eq!(tables.encode(7.0), 255);
+    }
+
+    #[test]
+    fn packing() {
+        assert_eq!(pack_srgb8(v4(1.0, 0.0, 0.5, 1.0)), 0xFFFF00BC);
+        assert_eq!(pack_srgb8(v4(2.0, -1.0, 0.0, 0.5)), 0x80FF0000);
+    }
+
+    #[test]
+    fn premultiplied_colors() {
+        let color = v4(0.5, 1.0, 0.25, 0.5);
+        assert_eq!(premultiply_color(color), v4(0.25, 0.5, 0.125, 0.5));
+        assert_eq!(unpremultiply_color(premultiply_color(color)), color);
+        assert_eq!(
+            unpremultiply_color(v4(0.5, 0.5, 0.5, 0.0)),
+            v4(0.0, 0.0, 0.0, 0.0)
+        );
+    }
+}
@@ -0,0 +1,19 @@
+// Note(voided): lives here rather than in the platform layer, so the game can load files too
+// without depending on the executable.
+
+use std::path::Path;
+
+pub fn debug_platform_read_entire_file<C>(filename: C) -> std::io::Result<Vec<u8>>
+where
+    C: AsRef<Path>,
+{
+    std::fs::read(filename)
+}
+
+pub fn debug_platform_write_entire_file<C, D>(filename: C, data: D) -> std::io::Result<()>
+where
+    C: AsRef<Path>,
+    D: AsRef<[u8]>,
+{
+    std::fs::write(filename, data)
+}
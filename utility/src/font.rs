@@ -0,0 +1,367 @@
+// Bitmap fonts. A font is a glyph atlas, an ordinary image with every glyph in it, plus metrics
+// that say where each glyph is in the atlas and how to place it. The metrics are a text file next
+// to the atlas, written by hand or by a tool that bakes them from a TrueType font.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+
+use crate::image::Bitmap;
+use crate::math::{v2, Rect2i, V2};
+
+/// One glyph, in atlas pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glyph {
+    /// Where the glyph is in the atlas, empty for glyphs without pixels like space.
+    pub atlas: Rect2i,
+    /// From the pen position on the baseline to the top left corner of the glyph, y down.
+    pub offset: V2,
+    /// How far the pen moves after the glyph.
+    pub advance: f32,
+}
+
+/// Everything about a font except its pixels.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FontMetrics {
+    /// Distance between the baselines of two lines.
+    pub line_height: f32,
+    /// From the top of a line to its baseline.
+    pub ascent: f32,
+    pub glyphs: Vec<(char, Glyph)>,
+    /// Added to the advance between two glyphs, usually negative.
+    pub kerning: Vec<(char, char, f32)>,
+}
+
+fn invalid_metrics(line_number: usize, line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid font metrics line {}: {line}", line_number + 1),
+    )
+}
+
+impl FontMetrics {
+    /// One entry per line, characters are written as their code point in decimal:
+    /// - `line_height <pixels>`
+    /// - `ascent <pixels>`
+    /// - `glyph <char> <atlas x> <atlas y> <width> <height> <offset x> <offset y> <advance>`
+    /// - `kern <first char> <second char> <pixels>`
+    ///
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut metrics = Self::default();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let invalid = || invalid_metrics(line_number, line);
+            let float = |part: &str| part.parse::<f32>().map_err(|_| invalid());
+            let int = |part: &str| part.parse::<i32>().map_err(|_| invalid());
+            let character = |part: &str| {
+                part.parse::<u32>()
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(invalid)
+            };
+
+            match parts.as_slice() {
+                ["line_height", pixels] => metrics.line_height = float(pixels)?,
+                ["ascent", pixels] => metrics.ascent = float(pixels)?,
+                ["glyph", c, x, y, width, height, offset_x, offset_y, advance] => {
+                    let (width, height) = (int(width)?, int(height)?);
+                    if width < 0 || height < 0 {
+                        return Err(invalid());
+                    }
+                    let (x, y) = (int(x)?, int(y)?);
+                    // the atlas rectangle has to fit in an i32 to be anywhere in the atlas
+                    let max_x = x.checked_add(width).ok_or_else(invalid)?;
+                    let max_y = y.checked_add(height).ok_or_else(invalid)?;
+                    let glyph = Glyph {
+                        atlas: Rect2i::new(x, y, max_x, max_y),
+                        offset: v2(float(offset_x)?, float(offset_y)?),
+                        advance: float(advance)?,
+                    };
+                    metrics.glyphs.push((character(c)?, glyph));
+                }
+                ["kern", first, second, pixels] => {
+                    metrics
+                        .kerning
+                        .push((character(first)?, character(second)?, float(pixels)?));
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(metrics)
+    }
+}
+
+/// Writes the format [`FontMetrics::parse`] reads.
+impl fmt::Display for FontMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "line_height {}", self.line_height)?;
+        writeln!(f, "ascent {}", self.ascent)?;
+        for (c, glyph) in &self.glyphs {
+            let atlas = glyph.atlas;
+            writeln!(
+                f,
+                "glyph {} {} {} {} {} {} {} {}",
+                *c as u32,
+                atlas.min_x,
+                atlas.min_y,
+                atlas.width(),
+                atlas.height(),
+                glyph.offset.x,
+                glyph.offset.y,
+                glyph.advance
+            )?;
+        }
+        for (first, second, pixels) in &self.kerning {
+            writeln!(f, "kern {} {} {pixels}", *first as u32, *second as u32)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    /// Lines start at the position.
+    #[default]
+    Left,
+    /// Lines are centered on the position.
+    Center,
+    /// Lines end at the position.
+    Right,
+}
+
+impl Alignment {
+    /// How much of a line's width is left of the position.
+    fn factor(self) -> f32 {
+        match self {
+            Self::Left => 0.0,
+            Self::Center => 0.5,
+            Self::Right => 1.0,
+        }
+    }
+}
+
+/// A glyph where layout put it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlacedGlyph {
+    pub glyph: Glyph,
+    /// Top left corner of the glyph.
+    pub position: V2,
+}
+
+/// Characters the font doesn't have get drawn as this one, or skipped if it's missing too.
+const REPLACEMENT: char = '?';
+
+pub struct Font {
+    atlas: Bitmap,
+    line_height: f32,
+    ascent: f32,
+    glyphs: HashMap<char, Glyph>,
+    kerning: HashMap<(char, char), f32>,
+}
+
+impl Font {
+    /// Fails if a glyph is outside of the atlas.
+    pub fn new(atlas: Bitmap, metrics: FontMetrics) -> io::Result<Self> {
+        let bounds = Rect2i::new(0, 0, atlas.width() as i32, atlas.height() as i32);
+        for (c, glyph) in &metrics.glyphs {
+            if !glyph.atlas.is_empty() && glyph.atlas.intersect(&bounds) != glyph.atlas {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("glyph {c:?} is outside of the font atlas"),
+                ));
+            }
+        }
+        Ok(Self {
+            atlas,
+            line_height: metrics.line_height,
+            ascent: metrics.ascent,
+            glyphs: metrics.glyphs.into_iter().collect(),
+            kerning: metrics
+                .kerning
+                .into_iter()
+                .map(|(first, second, pixels)| ((first, second), pixels))
+                .collect(),
+        })
+    }
+
+    pub fn atlas(&self) -> &Bitmap {
+        &self.atlas
+    }
+
+    pub fn line_height(&self) -> f32 {
+        self.line_height
+    }
+
+    pub fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs
+            .get(&c)
+            .or_else(|| self.glyphs.get(&REPLACEMENT))
+    }
+
+    pub fn kerning(&self, first: char, second: char) -> f32 {
+        self.kerning.get(&(first, second)).copied().unwrap_or(0.0)
+    }
+
+    /// Pen positions of the glyphs in a line, relative to its start.
+    fn line_glyphs<'a>(&'a self, line: &'a str) -> impl Iterator<Item = (f32, &'a Glyph)> + 'a {
+        let mut pen = 0.0;
+        let mut previous = None;
+        line.chars().filter_map(move |c| {
+            let glyph = self.glyph(c)?;
+            if let Some(previous) = previous {
+                pen += self.kerning(previous, c);
+            }
+            previous = Some(c);
+            let start = pen;
+            pen += glyph.advance;
+            Some((start, glyph))
+        })
+    }
+
+    /// Width of a single line, from the first pen position to the last.
+    pub fn line_width(&self, line: &str) -> f32 {
+        self.line_glyphs(line)
+            .last()
+            .map_or(0.0, |(pen, glyph)| pen + glyph.advance)
+    }
+
+    /// Width of the widest line and the height of all of them.
+    pub fn text_size(&self, text: &str) -> V2 {
+        let mut size = v2(0.0, 0.0);
+        for line in lines(text) {
+            size.x = size.x.max(self.line_width(line));
+            size.y += self.line_height;
+        }
+        size
+    }
+
+    /// Places the glyphs of `text`, `\n` starts a new line. `position` is the top of the first
+    /// line, at its left end, center or right end depending on `alignment`.
+    pub fn layout<'a>(
+        &'a self,
+        text: &'a str,
+        position: V2,
+        alignment: Alignment,
+    ) -> impl Iterator<Item = PlacedGlyph> + 'a {
+        lines(text).enumerate().flat_map(move |(index, line)| {
+            let start_x = position.x - alignment.factor() * self.line_width(line);
+            let baseline = position.y + self.ascent + index as f32 * self.line_height;
+            self.line_glyphs(line).map(move |(pen, glyph)| PlacedGlyph {
+                glyph: *glyph,
+                position: v2(start_x + pen, baseline) + glyph.offset,
+            })
+        })
+    }
+}
+
+fn lines(text: &str) -> impl Iterator<Item = &str> {
+    text.split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+}
+
+// Note(voided): the atlas would drown out everything else
+impl fmt::Debug for Font {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Font")
+            .field("atlas", &self.atlas)
+            .field("line_height", &self.line_height)
+            .field("glyphs", &self.glyphs.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod font_tests {
+    use crate::font::{Alignment, Font, FontMetrics, Glyph};
+    use crate::image::Bitmap;
+    use crate::math::{v2, Rect2i};
+
+    /// 4 pixel wide glyphs next to each other in the atlas, 'A' kerns closer to 'V'.
+    fn test_font() -> Font {
+        let glyph = |index: i32| Glyph {
+            atlas: Rect2i::with_size(index * 4, 0, 4, 6),
+            offset: v2(0.0, -6.0),
+            advance: 5.0,
+        };
+        let metrics = FontMetrics {
+            line_height: 10.0,
+            ascent: 7.0,
+            glyphs: vec![('A', glyph(0)), ('V', glyph(1)), ('?', glyph(2))],
+            kerning: vec![('A', 'V', -1.5)],
+        };
+        Font::new(Bitmap::new(12, 6, vec![0xFFFFFFFF; 72]).unwrap(), metrics).unwrap()
+    }
+
+    #[test]
+    fn metrics_round_trip() {
+        let text = "# test font\n\
+            line_height 10\n\
+            ascent 7.5\n\
+            glyph 65 0 0 4 6 0 -6 5\n\
+            glyph 32 0 0 0 0 0 0 3\n\
+            kern 65 86 -1.5\n";
+        let metrics = FontMetrics::parse(text).unwrap();
+        assert_eq!(metrics.line_height, 10.0);
+        assert_eq!(metrics.ascent, 7.5);
+        assert_eq!(metrics.glyphs.len(), 2);
+        assert_eq!(metrics.glyphs[0].1.atlas, Rect2i::new(0, 0, 4, 6));
+        assert_eq!(metrics.glyphs[1].0, ' ');
+        assert_eq!(metrics.kerning, [('A', 'V', -1.5)]);
+        assert_eq!(FontMetrics::parse(&metrics.to_string()).unwrap(), metrics);
+    }
+
+    #[test]
+    fn invalid_metrics_are_errors() {
+        assert!(FontMetrics::parse("glyph 65 0 0 4 6 0 -6").is_err());
+        assert!(FontMetrics::parse("glyph 65 0 0 -4 6 0 -6 5").is_err());
+        assert!(FontMetrics::parse("kern 65 55296 1").is_err());
+        assert!(FontMetrics::parse("ascent high").is_err());
+        assert!(FontMetrics::parse("glyph 65 2147483647 0 4 6 0 -6 5").is_err());
+        assert!(FontMetrics::parse("glyph 65 0 2147483645 4 6 0 -6 5").is_err());
+
+        let outside = FontMetrics::parse("glyph 65 10 0 4 6 0 -6 5").unwrap();
+        assert!(Font::new(Bitmap::new(12, 6, vec![0; 72]).unwrap(), outside).is_err());
+    }
+
+    #[test]
+    fn kerning_and_missing_glyphs() {
+        let font = test_font();
+        assert_eq!(font.line_width("AV"), 8.5);
+        assert_eq!(font.line_width("VA"), 10.0);
+        // drawn as '?'
+        assert_eq!(font.glyph('x').unwrap().atlas.min_x, 8);
+        assert_eq!(font.line_width(""), 0.0);
+
+        let positions: Vec<_> = font
+            .layout("AV", v2(10.0, 20.0), Alignment::Left)
+            .map(|placed| placed.position)
+            .collect();
+        assert_eq!(positions, [v2(10.0, 21.0), v2(13.5, 21.0)]);
+    }
+
+    #[test]
+    fn lines_and_alignment() {
+        let font = test_font();
+        assert_eq!(font.text_size("AAA\nA\r\nAA"), v2(15.0, 30.0));
+
+        let placed: Vec<_> = font
+            .layout("AA\nA", v2(20.0, 0.0), Alignment::Right)
+            .map(|placed| placed.position)
+            .collect();
+        assert_eq!(placed, [v2(10.0, 1.0), v2(15.0, 1.0), v2(15.0, 11.0)]);
+
+        let centered: Vec<_> = font
+            .layout("AA\n\nA", v2(20.0, 0.0), Alignment::Center)
+            .map(|placed| placed.position)
+            .collect();
+        assert_eq!(centered, [v2(15.0, 1.0), v2(20.0, 1.0), v2(17.5, 21.0)]);
+    }
+}
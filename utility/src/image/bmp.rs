@@ -0,0 +1,284 @@
+// 32 bit BMP files, uncompressed or with bit field masks, which is what paint programs write
+// when the image has an alpha channel.
+
+use std::io;
+
+use crate::image::{premultiply, unpremultiply, Bitmap};
+
+const FILE_HEADER_SIZE: usize = 14;
+const INFO_HEADER_SIZE: usize = 40;
+const V4_HEADER_SIZE: usize = 108;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+
+fn invalid_bmp(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid bmp: {reason}"))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> io::Result<u16> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| invalid_bmp("truncated header"))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> io::Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| invalid_bmp("truncated header"))
+}
+
+/// Where a channel sits in a pixel, from one of the bit field masks.
+#[derive(Debug, Clone, Copy)]
+struct ChannelMask {
+    mask: u32,
+    shift: u32,
+    max: u32,
+}
+
+impl ChannelMask {
+    fn new(mask: u32) -> Self {
+        let shift = if mask == 0 { 0 } else { mask.trailing_zeros() };
+        Self {
+            mask,
+            shift,
+            max: mask >> shift,
+        }
+    }
+
+    /// Channel value scaled to 0..=255, `missing` if the pixel has no such channel.
+    fn extract(&self, pixel: u32, missing: u32) -> u32 {
+        if self.mask == 0 {
+            return missing;
+        }
+        // Note(voided): masks can be up to 32 bits wide, which overflows a u32 once scaled
+        let value = ((pixel & self.mask) >> self.shift) as u64;
+        let max = self.max as u64;
+        ((value * 255 + max / 2) / max) as u32
+    }
+}
+
+/// Parses a 32 bit BMP into a premultiplied, top down [`Bitmap`].
+/// Bottom up (positive height) and top down (negative height) files are both supported.
+pub fn parse_bmp(data: &[u8]) -> io::Result<Bitmap> {
+    if data.get(0..2) != Some(b"BM") {
+        return Err(invalid_bmp("missing BM signature"));
+    }
+    let pixel_offset = read_u32(data, 10)? as usize;
+    let header_size = read_u32(data, FILE_HEADER_SIZE)? as usize;
+    if header_size < INFO_HEADER_SIZE {
+        return Err(invalid_bmp("unsupported header"));
+    }
+
+    let info = FILE_HEADER_SIZE;
+    let width = read_u32(data, info + 4)? as i32;
+    let height = read_u32(data, info + 8)? as i32;
+    let bits_per_pixel = read_u16(data, info + 14)?;
+    let compression = read_u32(data, info + 16)?;
+
+    if bits_per_pixel != 32 {
+        return Err(invalid_bmp("only 32 bits per pixel are supported"));
+    }
+    if width < 0 || height == i32::MIN {
+        return Err(invalid_bmp("bad dimensions"));
+    }
+    let top_down = height < 0;
+    let width = width as usize;
+    let height = height.unsigned_abs() as usize;
+
+    // Note(voided): without masks the bytes are B G R A, but most writers leave A at 0 and mean
+    // opaque, so a file without any alpha counts as opaque.
+    let (red, green, blue, alpha) = match compression {
+        BI_RGB => (0x00FF0000, 0x0000FF00, 0x000000FF, 0xFF000000),
+        BI_BITFIELDS => {
+            // the masks follow a plain info header, but are part of the bigger headers
+            let masks = info + INFO_HEADER_SIZE;
+            let alpha = if header_size >= V4_HEADER_SIZE {
+                read_u32(data, masks + 12)?
+            } else {
+                0
+            };
+            (
+                read_u32(data, masks)?,
+                read_u32(data, masks + 4)?,
+                read_u32(data, masks + 8)?,
+                alpha,
+            )
+        }
+        _ => return Err(invalid_bmp("compressed files are not supported")),
+    };
+    let [red, green, blue, mut alpha] = [red, green, blue, alpha].map(ChannelMask::new);
+
+    let size = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .ok_or_else(|| invalid_bmp("bad dimensions"))?;
+    let pixel_data = pixel_offset
+        .checked_add(size)
+        .and_then(|end| data.get(pixel_offset..end))
+        .ok_or_else(|| invalid_bmp("truncated pixel data"))?;
+    let raw: Vec<u32> = pixel_data
+        .chunks_exact(4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+
+    if compression == BI_RGB && raw.iter().all(|pixel| pixel & alpha.mask == 0) {
+        alpha = ChannelMask::new(0);
+    }
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        // Note(voided): 32 bit rows never need padding
+        let row = if top_down { y } else { height - 1 - y };
+        for &pixel in &raw[row * width..(row + 1) * width] {
+            let color = alpha.extract(pixel, 0xFF) << 24
+                | red.extract(pixel, 0) << 16
+                | green.extract(pixel, 0) << 8
+                | blue.extract(pixel, 0);
+            pixels.push(premultiply(color));
+        }
+    }
+
+    Ok(Bitmap::new(width, height, pixels).expect("size was checked above"))
+}
+
+/// Writes an uncompressed 32 bit BMP, bottom up like most programs expect. Alpha goes into the
+/// fourth byte, which [`parse_bmp`] reads back as long as it isn't 0 everywhere.
+pub fn encode_bmp(bitmap: &Bitmap) -> Vec<u8> {
+    let pixel_offset = (FILE_HEADER_SIZE + INFO_HEADER_SIZE) as u32;
+    let image_size = (bitmap.width() * bitmap.height() * 4) as u32;
+
+    let mut data = Vec::with_capacity((pixel_offset + image_size) as usize);
+    data.extend_from_slice(b"BM");
+    data.extend_from_slice(&(pixel_offset + image_size).to_le_bytes());
+    data.extend_from_slice(&0u32.to_le_bytes());
+    data.extend_from_slice(&pixel_offset.to_le_bytes());
+
+    data.extend_from_slice(&(INFO_HEADER_SIZE as u32).to_le_bytes());
+    data.extend_from_slice(&(bitmap.width() as i32).to_le_bytes());
+    data.extend_from_slice(&(bitmap.height() as i32).to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&32u16.to_le_bytes());
+    data.extend_from_slice(&BI_RGB.to_le_bytes());
+    data.extend_from_slice(&image_size.to_le_bytes());
+    // resolution and palette, none of which matter
+    data.resize(pixel_offset as usize, 0);
+
+    for y in (0..bitmap.height()).rev() {
+        for &color in bitmap.row(y).expect("y is in range") {
+            data.extend_from_slice(&unpremultiply(color).to_le_bytes());
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod bmp_tests {
+    use crate::image::bmp::{encode_bmp, parse_bmp};
+    use crate::image::Bitmap;
+
+    /// Builds a file with the given masks (`None` for an uncompressed one), rows as stored.
+    fn bmp(width: i32, height: i32, masks: Option<[u32; 4]>, pixels: &[u32]) -> Vec<u8> {
+        let header_size: u32 = if masks.is_some() { 108 } else { 40 };
+        let pixel_offset = 14 + header_size;
+
+        let mut data = vec![];
+        data.extend_from_slice(b"BM");
+        data.extend_from_slice(&(pixel_offset + pixels.len() as u32 * 4).to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&pixel_offset.to_le_bytes());
+
+        data.extend_from_slice(&header_size.to_le_bytes());
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&32u16.to_le_bytes());
+        let compression: u32 = if masks.is_some() { 3 } else { 0 };
+        data.extend_from_slice(&compression.to_le_bytes());
+        data.resize(14 + 40, 0);
+        if let Some(masks) = masks {
+            for mask in masks {
+                data.extend_from_slice(&mask.to_le_bytes());
+            }
+            data.resize(pixel_offset as usize, 0);
+        }
+
+        for pixel in pixels {
+            data.extend_from_slice(&pixel.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn bottom_up_and_top_down() {
+        let bottom_up = parse_bmp(&bmp(2, 2, None, &[1, 2, 3, 4])).unwrap();
+        let top_down = parse_bmp(&bmp(2, -2, None, &[3, 4, 1, 2])).unwrap();
+        assert_eq!((bottom_up.width(), bottom_up.height()), (2, 2));
+        assert_eq!(
+            bottom_up.pixels(),
+            &[0xFF000003, 0xFF000004, 0xFF000001, 0xFF000002]
+        );
+        assert_eq!(top_down.pixels(), bottom_up.pixels());
+    }
+
+    #[test]
+    fn uncompressed_alpha_is_used_if_present() {
+        let bitmap = parse_bmp(&bmp(2, 1, None, &[0x80FF0000, 0x00FFFFFF])).unwrap();
+        assert_eq!(bitmap.pixels(), &[0x80BC0000, 0x00000000]);
+    }
+
+    #[test]
+    fn bit_field_masks() {
+        // R G B A byte order, which is what the masks in many exported files look like
+        let masks = [0x000000FF, 0x0000FF00, 0x00FF0000, 0xFF000000];
+        let bitmap = parse_bmp(&bmp(2, 1, Some(masks), &[0xFF0080FF, 0x804020FF])).unwrap();
+        assert_eq!(bitmap.pixels(), &[0xFFFF8000, 0x80BC152D]);
+    }
+
+    #[test]
+    fn small_masks_get_scaled() {
+        // 5 6 5 bits in a 32 bit pixel, no alpha
+        let masks = [0xF800, 0x07E0, 0x001F, 0];
+        let bitmap = parse_bmp(&bmp(2, 1, Some(masks), &[0xFFFF, 0xF800])).unwrap();
+        assert_eq!(bitmap.pixels(), &[0xFFFFFFFF, 0xFFFF0000]);
+    }
+
+    #[test]
+    fn full_width_masks_get_scaled() {
+        let masks = [0, 0, 0, 0xFFFFFFFF];
+        let pixels = [0xFFFFFFFF, 0x80000000, 0];
+        let bitmap = parse_bmp(&bmp(3, 1, Some(masks), &pixels)).unwrap();
+        assert_eq!(bitmap.pixels(), &[0xFF000000, 0x80000000, 0]);
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let bitmap = Bitmap::new(3, 2, vec![0xFF102030, 0xFF405060, 0, 0x80400000, 1, 2]);
+        let bitmap = bitmap.unwrap();
+        let decoded = parse_bmp(&encode_bmp(&bitmap)).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (3, 2));
+        assert_eq!(decoded.row(0), bitmap.row(0));
+        assert_eq!(decoded.pixel(0, 1), Some(0x80400000));
+        // premultiplied colors can't have more color than alpha
+        assert_eq!(decoded.pixel(1, 1), Some(0));
+    }
+
+    #[test]
+    fn invalid_files_are_errors() {
+        let valid = bmp(2, 2, None, &[1, 2, 3, 4]);
+        assert!(parse_bmp(&valid[..valid.len() - 1]).is_err());
+        assert!(parse_bmp(&valid[..20]).is_err());
+        assert!(parse_bmp(b"PNG").is_err());
+
+        let mut wrong_depth = valid.clone();
+        wrong_depth[28] = 24;
+        assert!(parse_bmp(&wrong_depth).is_err());
+
+        let mut compressed = valid.clone();
+        compressed[30] = 1;
+        assert!(parse_bmp(&compressed).is_err());
+
+        let huge = bmp(i32::MAX, i32::MAX, None, &[]);
+        assert!(parse_bmp(&huge).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+// Images the game loads from disk.
+
+use std::fmt;
+use std::io;
+
+use crate::color::{decode_srgb8, encode_srgb8};
+
+pub mod bmp;
+pub mod png;
+pub mod zlib;
+
+/// Pixels are 0xAARRGGBB like the back buffer, with premultiplied alpha, rows top to bottom.
+pub struct Bitmap {
+    width: usize,
+    height: usize,
+    pixels: Vec<u32>,
+}
+
+impl Bitmap {
+    /// `None` if `pixels` doesn't have exactly `width * height` entries.
+    pub fn new(width: usize, height: usize, pixels: Vec<u32>) -> Option<Self> {
+        if width.checked_mul(height)? != pixels.len() {
+            return None;
+        }
+        Some(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    pub fn view(&self) -> BitmapView<'_> {
+        BitmapView {
+            width: self.width,
+            height: self.height,
+            pixels: &self.pixels,
+        }
+    }
+
+    pub fn row(&self, y: usize) -> Option<&[u32]> {
+        self.view().row(y)
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[u32]> + '_ {
+        self.view().rows()
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Option<u32> {
+        self.view().pixel(x, y)
+    }
+}
+
+/// Borrowed pixels laid out like a [`Bitmap`], for images that live in memory the bitmap doesn't
+/// own, like render targets in an arena.
+#[derive(Clone, Copy)]
+pub struct BitmapView<'a> {
+    width: usize,
+    height: usize,
+    pixels: &'a [u32],
+}
+
+impl<'a> BitmapView<'a> {
+    /// `None` if `pixels` doesn't have exactly `width * height` entries.
+    pub fn new(width: usize, height: usize, pixels: &'a [u32]) -> Option<Self> {
+        if width.checked_mul(height)? != pixels.len() {
+            return None;
+        }
+        Some(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &'a [u32] {
+        self.pixels
+    }
+
+    pub fn row(&self, y: usize) -> Option<&'a [u32]> {
+        if y >= self.height {
+            return None;
+        }
+        let start = y * self.width;
+        Some(&self.pixels[start..start + self.width])
+    }
+
+    /// Top to bottom.
+    pub fn rows(&self) -> impl Iterator<Item = &'a [u32]> {
+        let view = *self;
+        (0..self.height).map(move |y| view.row(y).expect("y is inside the view"))
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Option<u32> {
+        if x >= self.width {
+            return None;
+        }
+        self.row(y).map(|row| row[x])
+    }
+
+    /// Copies the pixels into a bitmap of its own.
+    pub fn to_bitmap(&self) -> Bitmap {
+        Bitmap {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.to_vec(),
+        }
+    }
+}
+
+// Note(voided): the pixels would drown out everything else
+impl fmt::Debug for Bitmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bitmap")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for BitmapView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BitmapView")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Scales the color channels of a straight alpha 0xAARRGGBB color by its alpha.
+/// Note(voided): the scaling happens on the linear colors, the channels stay sRGB encoded. That
+/// way decoding a premultiplied channel gives the premultiplied linear color blending wants.
+pub fn premultiply(color: u32) -> u32 {
+    scale_channels(color, |linear, alpha| linear * alpha)
+}
+
+/// Undoes [`premultiply`]. Colors with an alpha of 0 become fully transparent black.
+pub fn unpremultiply(color: u32) -> u32 {
+    if color >> 24 == 0 {
+        return 0;
+    }
+    scale_channels(color, |linear, alpha| linear / alpha)
+}
+
+fn scale_channels(color: u32, scale: impl Fn(f32, f32) -> f32) -> u32 {
+    let alpha = color >> 24;
+    if alpha == 0xFF {
+        return color;
+    }
+    let channel = |shift: u32| {
+        let linear = scale(decode_srgb8(color >> shift), alpha as f32 / 255.0);
+        encode_srgb8(linear) << shift
+    };
+    alpha << 24 | channel(16) | channel(8) | channel(0)
+}
+
+/// Parses a BMP or PNG file, depending on what the data starts with.
+pub fn parse_image(data: &[u8]) -> io::Result<Bitmap> {
+    if data.starts_with(&png::SIGNATURE) {
+        png::parse_png(data)
+    } else {
+        bmp::parse_bmp(data)
+    }
+}
+
+#[cfg(test)]
+mod image_tests {
+    use crate::image::{premultiply, unpremultiply, Bitmap, BitmapView};
+
+    #[test]
+    fn bitmap_size_has_to_match() {
+        assert!(Bitmap::new(2, 2, vec![0; 3]).is_none());
+        assert!(Bitmap::new(usize::MAX, 2, vec![]).is_none());
+
+        let bitmap = Bitmap::new(2, 2, vec![1, 2, 3, 4]).unwrap();
+        assert_eq!(bitmap.row(1), Some(&[3, 4][..]));
+        assert_eq!(bitmap.pixel(1, 0), Some(2));
+        assert_eq!(bitmap.pixel(2, 0), None);
+        assert_eq!(bitmap.row(2), None);
+    }
+
+    #[test]
+    fn views_borrow_the_pixels() {
+        let pixels = [1, 2, 3, 4, 5, 6];
+        assert!(BitmapView::new(4, 2, &pixels).is_none());
+
+        let view = BitmapView::new(3, 2, &pixels).unwrap();
+        assert_eq!(view.row(1), Some(&[4, 5, 6][..]));
+        assert_eq!(view.rows().collect::<Vec<_>>(), [[1, 2, 3], [4, 5, 6]]);
+        assert_eq!(view.pixel(2, 0), Some(3));
+        assert_eq!(view.pixel(3, 0), None);
+
+        let bitmap = view.to_bitmap();
+        assert_eq!(bitmap.pixels(), pixels);
+        assert_eq!(bitmap.view().pixels(), pixels);
+    }
+
+    #[test]
+    fn premultiplied_alpha() {
+        assert_eq!(premultiply(0xFFFF8040), 0xFFFF8040);
+        // half of linear full red is a lot more than half of 0xFF in sRGB
+        assert_eq!(premultiply(0x80FF8040), 0x80BC5D2D);
+        assert_eq!(premultiply(0x00FFFFFF), 0x00000000);
+
+        // off by one, premultiplying loses precision
+        assert_eq!(unpremultiply(0x80BC5D2D), 0x80FF8141);
+        assert_eq!(unpremultiply(0x00102030), 0x00000000);
+        for alpha in [0x01, 0x80, 0xFF] {
+            let color = alpha << 24 | 0x00FF7F00;
+            assert_eq!(
+                premultiply(unpremultiply(premultiply(color))),
+                premultiply(color)
+            );
+        }
+    }
+}
@@ -0,0 +1,686 @@
+// PNG files: every color type and bit depth, transparency chunks and Adam7 interlacing.
+// Note(voided): the encoder only writes 8 bit RGBA without interlacing, which is all screenshots
+// and golden images need. It shares the chunk, filter and zlib code with the decoder.
+
+use std::io;
+
+use crate::image::zlib::{deflate_zlib, inflate_zlib};
+use crate::image::{premultiply, unpremultiply, Bitmap};
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Largest width or height we accept, so a broken header can't make us allocate gigabytes.
+const MAX_DIMENSION: usize = 16384;
+
+const GRAY: u8 = 0;
+const RGB: u8 = 2;
+const PALETTE: u8 = 3;
+const GRAY_ALPHA: u8 = 4;
+const RGBA: u8 = 6;
+
+/// (x, y, step x, step y) of each pass, the first pixel and the distance to the next.
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+const NOT_INTERLACED: [(usize, usize, usize, usize); 1] = [(0, 0, 1, 1)];
+
+fn invalid_png(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid png: {reason}"))
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let crc = data.iter().fold(0xFFFFFFFF, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    });
+    crc ^ 0xFFFFFFFF
+}
+
+fn read_u32(data: &[u8], offset: usize) -> io::Result<u32> {
+    offset
+        .checked_add(4)
+        .and_then(|end| data.get(offset..end))
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| invalid_png("unexpected end of file"))
+}
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() != 13 {
+            return Err(invalid_png("bad header size"));
+        }
+        let width = read_u32(data, 0)? as usize;
+        let height = read_u32(data, 4)? as usize;
+        let [bit_depth, color_type, compression, filter, interlace] =
+            [data[8], data[9], data[10], data[11], data[12]];
+
+        if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(invalid_png("bad dimensions"));
+        }
+        let valid_depth = match color_type {
+            GRAY => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+            PALETTE => matches!(bit_depth, 1 | 2 | 4 | 8),
+            RGB | GRAY_ALPHA | RGBA => matches!(bit_depth, 8 | 16),
+            _ => false,
+        };
+        if !valid_depth {
+            return Err(invalid_png("bad color type and bit depth"));
+        }
+        if compression != 0 || filter != 0 || interlace > 1 {
+            return Err(invalid_png(
+                "unknown compression, filter or interlace method",
+            ));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced: interlace == 1,
+        })
+    }
+
+    fn channels(&self) -> usize {
+        match self.color_type {
+            GRAY | PALETTE => 1,
+            GRAY_ALPHA => 2,
+            RGB => 3,
+            _ => 4,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    /// Distance to the byte of the pixel to the left that the filters use, at least 1.
+    fn filter_stride(&self) -> usize {
+        (self.bits_per_pixel() / 8).max(1)
+    }
+
+    fn row_bytes(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    fn passes(&self) -> &'static [(usize, usize, usize, usize)] {
+        if self.interlaced {
+            &ADAM7
+        } else {
+            &NOT_INTERLACED
+        }
+    }
+
+    /// Pixels in a pass, which can be 0 for small interlaced images.
+    fn pass_size(&self, (x, y, step_x, step_y): (usize, usize, usize, usize)) -> (usize, usize) {
+        (
+            self.width.saturating_sub(x).div_ceil(step_x),
+            self.height.saturating_sub(y).div_ceil(step_y),
+        )
+    }
+
+    /// Size of the decompressed data, each row has its filter byte in front.
+    fn raw_size(&self) -> usize {
+        self.passes()
+            .iter()
+            .map(|&pass| match self.pass_size(pass) {
+                (0, _) | (_, 0) => 0,
+                (width, height) => height * (1 + self.row_bytes(width)),
+            })
+            .sum()
+    }
+}
+
+enum Transparency {
+    None,
+    /// Alpha of the first palette entries, the rest are opaque.
+    Palette(Vec<u8>),
+    /// Pixels of exactly this color are transparent, in the image's bit depth.
+    Key([u16; 3]),
+}
+
+fn predict(filter: u8, left: u8, up: u8, up_left: u8) -> u8 {
+    match filter {
+        1 => left,
+        2 => up,
+        3 => ((left as u16 + up as u16) / 2) as u8,
+        4 => {
+            let (a, b, c) = (left as i16, up as i16, up_left as i16);
+            let p = a + b - c;
+            let (pa, pb, pc) = ((p - a).abs(), (p - b).abs(), (p - c).abs());
+            if pa <= pb && pa <= pc {
+                left
+            } else if pb <= pc {
+                up
+            } else {
+                up_left
+            }
+        }
+        _ => 0,
+    }
+}
+
+/// Undoes the filter of `row` in place. `prior` is the unfiltered row above, empty for the
+/// first row of a pass.
+fn unfilter(filter: u8, row: &mut [u8], prior: &[u8], stride: usize) -> io::Result<()> {
+    if filter > 4 {
+        return Err(invalid_png("unknown filter type"));
+    }
+    for i in 0..row.len() {
+        let left = if i >= stride { row[i - stride] } else { 0 };
+        let up = prior.get(i).copied().unwrap_or(0);
+        let up_left = if i >= stride {
+            prior.get(i - stride).copied().unwrap_or(0)
+        } else {
+            0
+        };
+        row[i] = row[i].wrapping_add(predict(filter, left, up, up_left));
+    }
+    Ok(())
+}
+
+/// Channel `channel` of pixel `x` in an unfiltered row, in the image's bit depth.
+fn sample(header: &Header, row: &[u8], x: usize, channel: usize) -> u16 {
+    match header.bit_depth {
+        16 => read_u16(row, (x * header.channels() + channel) * 2),
+        8 => row[x * header.channels() + channel] as u16,
+        depth => {
+            let depth = depth as usize;
+            let bit = x * depth;
+            let shift = 8 - depth - bit % 8;
+            (row[bit / 8] as u16 >> shift) & ((1 << depth) - 1)
+        }
+    }
+}
+
+/// A sample in the image's bit depth to 0..=255.
+fn scale_to_8_bits(value: u16, bit_depth: u8) -> u32 {
+    let value = value as u32;
+    match bit_depth {
+        16 => (value * 255 + 32767) / 65535,
+        depth => value * 255 / ((1 << depth) - 1),
+    }
+}
+
+/// One straight alpha 0xAARRGGBB pixel.
+fn convert_pixel(
+    header: &Header,
+    row: &[u8],
+    x: usize,
+    palette: &[[u8; 3]],
+    transparency: &Transparency,
+) -> io::Result<u32> {
+    let channel = |channel| sample(header, row, x, channel);
+    let scale = |value| scale_to_8_bits(value, header.bit_depth);
+    let argb = |a: u32, r: u32, g: u32, b: u32| a << 24 | r << 16 | g << 8 | b;
+
+    let color = match header.color_type {
+        PALETTE => {
+            let index = channel(0) as usize;
+            let [r, g, b] = *palette
+                .get(index)
+                .ok_or_else(|| invalid_png("palette index out of range"))?;
+            let alpha = match transparency {
+                Transparency::Palette(alphas) => alphas.get(index).copied().unwrap_or(0xFF),
+                _ => 0xFF,
+            };
+            argb(alpha as u32, r as u32, g as u32, b as u32)
+        }
+        GRAY | GRAY_ALPHA => {
+            let gray = channel(0);
+            let alpha = match (header.color_type, transparency) {
+                (GRAY_ALPHA, _) => scale(channel(1)),
+                (_, Transparency::Key([key, _, _])) if *key == gray => 0,
+                _ => 0xFF,
+            };
+            argb(alpha, scale(gray), scale(gray), scale(gray))
+        }
+        _ => {
+            let rgb = [channel(0), channel(1), channel(2)];
+            let alpha = match (header.color_type, transparency) {
+                (RGBA, _) => scale(channel(3)),
+                (_, Transparency::Key(key)) if *key == rgb => 0,
+                _ => 0xFF,
+            };
+            argb(alpha, scale(rgb[0]), scale(rgb[1]), scale(rgb[2]))
+        }
+    };
+    Ok(color)
+}
+
+fn parse_transparency(
+    header: &Header,
+    data: &[u8],
+    palette_size: usize,
+) -> io::Result<Transparency> {
+    match header.color_type {
+        PALETTE if data.len() <= palette_size => Ok(Transparency::Palette(data.to_vec())),
+        GRAY if data.len() == 2 => Ok(Transparency::Key([read_u16(data, 0), 0, 0])),
+        RGB if data.len() == 6 => Ok(Transparency::Key([
+            read_u16(data, 0),
+            read_u16(data, 2),
+            read_u16(data, 4),
+        ])),
+        // Note(voided): not allowed with an alpha channel, nothing to do with one anyway
+        GRAY_ALPHA | RGBA => Ok(Transparency::None),
+        _ => Err(invalid_png("bad transparency chunk")),
+    }
+}
+
+/// Decodes a PNG into a premultiplied, top down [`Bitmap`]. 16 bit channels get rounded to 8.
+pub fn parse_png(data: &[u8]) -> io::Result<Bitmap> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(invalid_png("missing signature"));
+    }
+
+    let mut header = None;
+    let mut palette: Vec<[u8; 3]> = vec![];
+    let mut transparency = Transparency::None;
+    let mut compressed = vec![];
+
+    let mut position = SIGNATURE.len();
+    loop {
+        let length = read_u32(data, position)? as usize;
+        let end = position
+            .checked_add(8)
+            .and_then(|start| start.checked_add(length))
+            .filter(|&end| end <= data.len())
+            .ok_or_else(|| invalid_png("truncated chunk"))?;
+        let kind = &data[position + 4..position + 8];
+        let chunk = &data[position + 8..end];
+        if crc32(&data[position + 4..end]) != read_u32(data, end)? {
+            return Err(invalid_png("chunk checksum mismatch"));
+        }
+        position = end + 4;
+
+        if kind == b"IHDR" {
+            if header.is_some() {
+                return Err(invalid_png("more than one header"));
+            }
+            header = Some(Header::parse(chunk)?);
+            continue;
+        }
+        let header = header
+            .as_ref()
+            .ok_or_else(|| invalid_png("header has to come first"))?;
+
+        match kind {
+            b"PLTE" => {
+                let max_entries = match header.color_type {
+                    PALETTE => 1 << header.bit_depth,
+                    _ => 256,
+                };
+                if chunk.is_empty()
+                    || !chunk.len().is_multiple_of(3)
+                    || chunk.len() / 3 > max_entries
+                {
+                    return Err(invalid_png("bad palette"));
+                }
+                palette = chunk
+                    .chunks_exact(3)
+                    .map(|entry| [entry[0], entry[1], entry[2]])
+                    .collect();
+            }
+            b"tRNS" => transparency = parse_transparency(header, chunk, palette.len())?,
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            b"IEND" => break,
+            // Note(voided): lowercase first letter means the chunk is safe to ignore
+            _ if kind[0] & 0x20 == 0 => return Err(invalid_png("unknown critical chunk")),
+            _ => {}
+        }
+    }
+
+    let header = header.ok_or_else(|| invalid_png("missing header"))?;
+    if header.color_type == PALETTE && palette.is_empty() {
+        return Err(invalid_png("missing palette"));
+    }
+
+    let raw_size = header.raw_size();
+    let mut raw = inflate_zlib(&compressed, raw_size)?;
+    if raw.len() != raw_size {
+        return Err(invalid_png("not enough image data"));
+    }
+
+    let mut pixels = vec![0u32; header.width * header.height];
+    let stride = header.filter_stride();
+    let mut offset = 0;
+    for &pass in header.passes() {
+        let (pass_width, pass_height) = header.pass_size(pass);
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        let (start_x, start_y, step_x, step_y) = pass;
+        let row_bytes = header.row_bytes(pass_width);
+
+        for pass_y in 0..pass_height {
+            let (before, rest) = raw.split_at_mut(offset + 1);
+            let filter = before[offset];
+            let prior = if pass_y == 0 {
+                &before[0..0]
+            } else {
+                &before[offset - row_bytes..offset]
+            };
+            let row = &mut rest[..row_bytes];
+            unfilter(filter, row, prior, stride)?;
+
+            let y = start_y + pass_y * step_y;
+            for pass_x in 0..pass_width {
+                let x = start_x + pass_x * step_x;
+                let color = convert_pixel(&header, row, pass_x, &palette, &transparency)?;
+                pixels[y * header.width + x] = premultiply(color);
+            }
+            offset += 1 + row_bytes;
+        }
+    }
+
+    Ok(Bitmap::new(header.width, header.height, pixels).expect("size matches the header"))
+}
+
+fn write_chunk(output: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    output.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = output.len();
+    output.extend_from_slice(kind);
+    output.extend_from_slice(data);
+    let crc = crc32(&output[start..]);
+    output.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Picks the filter with the smallest sum of absolute differences for each row, the usual
+/// heuristic.
+fn filter_rows(rows: &[Vec<u8>], stride: usize) -> Vec<u8> {
+    let mut filtered = vec![];
+    let empty = vec![];
+    let mut candidate = vec![];
+    let mut best = vec![];
+
+    for (y, row) in rows.iter().enumerate() {
+        let prior = if y == 0 { &empty } else { &rows[y - 1] };
+        let mut best_cost = u64::MAX;
+        for filter in 0..=4 {
+            candidate.clear();
+            candidate.push(filter);
+            for i in 0..row.len() {
+                let left = if i >= stride { row[i - stride] } else { 0 };
+                let up = prior.get(i).copied().unwrap_or(0);
+                let up_left = if i >= stride {
+                    prior.get(i - stride).copied().unwrap_or(0)
+                } else {
+                    0
+                };
+                candidate.push(row[i].wrapping_sub(predict(filter, left, up, up_left)));
+            }
+            let cost = candidate[1..]
+                .iter()
+                .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                .sum();
+            if cost < best_cost {
+                best_cost = cost;
+                std::mem::swap(&mut best, &mut candidate);
+            }
+        }
+        filtered.extend_from_slice(&best);
+    }
+    filtered
+}
+
+/// Encodes the bitmap as an 8 bit RGBA PNG, undoing the premultiplied alpha.
+/// Note(voided): PNG doesn't allow empty images, other decoders will refuse those.
+pub fn encode_png(bitmap: &Bitmap) -> Vec<u8> {
+    let mut header = vec![];
+    header.extend_from_slice(&(bitmap.width() as u32).to_be_bytes());
+    header.extend_from_slice(&(bitmap.height() as u32).to_be_bytes());
+    header.extend_from_slice(&[8, RGBA, 0, 0, 0]);
+
+    let rows: Vec<Vec<u8>> = (0..bitmap.height())
+        .map(|y| {
+            let row = bitmap.row(y).expect("y is in range");
+            row.iter()
+                .flat_map(|&color| {
+                    let color = unpremultiply(color);
+                    [
+                        (color >> 16) as u8,
+                        (color >> 8) as u8,
+                        color as u8,
+                        (color >> 24) as u8,
+                    ]
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut output = SIGNATURE.to_vec();
+    write_chunk(&mut output, b"IHDR", &header);
+    write_chunk(&mut output, b"IDAT", &deflate_zlib(&filter_rows(&rows, 4)));
+    write_chunk(&mut output, b"IEND", &[]);
+    output
+}
+
+#[cfg(test)]
+mod png_tests {
+    use crate::image::png::{crc32, encode_png, parse_png, SIGNATURE};
+    use crate::image::{premultiply, Bitmap};
+
+    /// A png from `test_images` and the pixels it should decode to, see `generate.py` there.
+    macro_rules! test_image {
+        ($name:literal) => {
+            (
+                $name,
+                include_bytes!(concat!("test_images/", $name, ".png")),
+                include_bytes!(concat!("test_images/", $name, ".rgba")),
+            )
+        };
+    }
+
+    const TEST_IMAGES: [(&str, &[u8], &[u8]); 14] = [
+        test_image!("gray1"),
+        test_image!("gray4_interlaced"),
+        test_image!("gray8_trns"),
+        test_image!("gray16"),
+        test_image!("gray_alpha8"),
+        test_image!("gray_alpha16_interlaced"),
+        test_image!("rgb8_trns"),
+        test_image!("rgb16"),
+        test_image!("rgba8"),
+        test_image!("rgba8_interlaced"),
+        test_image!("rgba16"),
+        test_image!("palette4_trns"),
+        test_image!("palette8_interlaced"),
+        test_image!("rgba8_flat"),
+    ];
+
+    fn expected_pixels(rgba: &[u8]) -> Vec<u32> {
+        rgba.chunks_exact(4)
+            .map(|p| {
+                premultiply(
+                    (p[3] as u32) << 24 | (p[0] as u32) << 16 | (p[1] as u32) << 8 | p[2] as u32,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn crc32_known_value() {
+        assert_eq!(crc32(b"IEND"), 0xAE426082);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn decodes_test_images() {
+        for (name, png, rgba) in TEST_IMAGES {
+            let bitmap = parse_png(png).unwrap_or_else(|err| panic!("{name}: {err}"));
+            assert_eq!((bitmap.width(), bitmap.height()), (13, 11), "{name}");
+            assert!(
+                bitmap.pixels() == expected_pixels(rgba),
+                "{name} decoded wrong"
+            );
+        }
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let pixels = (0..7 * 5).map(|i| premultiply(i * 0x0713_3B1F)).collect();
+        let bitmap = Bitmap::new(7, 5, pixels).unwrap();
+        let decoded = parse_png(&encode_png(&bitmap)).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (7, 5));
+
+        // Note(voided): premultiplying loses precision at low alpha, so only opaque and fully
+        // transparent pixels survive exactly
+        for (&original, &decoded) in bitmap.pixels().iter().zip(decoded.pixels()) {
+            match original >> 24 {
+                0 | 0xFF => assert_eq!(original, decoded),
+                _ => assert_eq!(original >> 24, decoded >> 24),
+            }
+        }
+
+        for (name, png, _) in TEST_IMAGES {
+            let bitmap = parse_png(png).unwrap();
+            let opaque: Vec<u32> = bitmap.pixels().iter().map(|p| p | 0xFF000000).collect();
+            let opaque = Bitmap::new(13, 11, opaque).unwrap();
+            let decoded = parse_png(&encode_png(&opaque)).unwrap();
+            assert!(decoded.pixels() == opaque.pixels(), "{name} round trip");
+        }
+    }
+
+    #[test]
+    fn invalid_files_are_errors() {
+        let (_, png, _) = TEST_IMAGES[8];
+        assert!(parse_png(&png[..png.len() - 1]).is_err());
+        assert!(parse_png(&png[..SIGNATURE.len()]).is_err());
+        assert!(parse_png(&png[1..]).is_err());
+
+        let mut bad_crc = png.to_vec();
+        bad_crc[SIGNATURE.len() + 8] ^= 1;
+        assert!(parse_png(&bad_crc).is_err());
+    }
+
+    /// xorshift64, tests don't need anything better
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, max: usize) -> usize {
+            (self.next() % max.max(1) as u64) as usize
+        }
+    }
+
+    /// Recomputes the chunk checksums, so mutations get past the checksum check.
+    fn fix_checksums(data: &mut [u8]) {
+        let mut position = SIGNATURE.len();
+        while position + 8 <= data.len() {
+            let length = u32::from_be_bytes(data[position..position + 4].try_into().unwrap());
+            let end = position + 8 + length as usize;
+            if end + 4 > data.len() {
+                return;
+            }
+            let crc = crc32(&data[position + 4..end]);
+            data[end..end + 4].copy_from_slice(&crc.to_be_bytes());
+            position = end + 4;
+        }
+    }
+
+    fn mutate(rng: &mut Rng, data: &mut Vec<u8>) {
+        for _ in 0..1 + rng.below(4) {
+            let index = rng.below(data.len());
+            match rng.below(5) {
+                0 => data[index] = rng.next() as u8,
+                1 => data[index] ^= 1 << rng.below(8),
+                2 => data.truncate(index),
+                3 => {
+                    let end = (index + rng.below(16)).min(data.len());
+                    data.drain(index..end);
+                }
+                _ => {
+                    let end = (index + rng.below(16)).min(data.len());
+                    let copy = data[index..end].to_vec();
+                    let at = rng.below(data.len());
+                    data.splice(at..at, copy);
+                }
+            }
+            if data.is_empty() {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn fuzz_malformed_files() {
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        for (_, png, _) in TEST_IMAGES {
+            for iteration in 0..200 {
+                let mut data = png.to_vec();
+                mutate(&mut rng, &mut data);
+                if iteration % 2 == 0 {
+                    fix_checksums(&mut data);
+                }
+                // anything goes, as long as it doesn't panic
+                let _ = parse_png(&data);
+            }
+        }
+    }
+
+    #[test]
+    fn fuzz_random_image_data() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        let (_, png, _) = TEST_IMAGES[8];
+        let header = &png[..SIGNATURE.len() + 25];
+        for _ in 0..500 {
+            let mut compressed = vec![0x78, 0x9C];
+            compressed.extend((0..rng.below(200)).map(|_| rng.next() as u8));
+
+            let mut data = header.to_vec();
+            data.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+            data.extend_from_slice(b"IDAT");
+            data.extend_from_slice(&compressed);
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0, 0, 0, 0]);
+            fix_checksums(&mut data);
+            let _ = parse_png(&data);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+#!/usr/bin/env python3
+# Writes the png test images next to this script, each with a .rgba file holding the pixels the
+# decoder should produce: straight alpha, 8 bits per channel, rows top to bottom.
+# Note(voided): this is a separate implementation on purpose, using zlib's real compressor with
+# every filter type, so the decoder doesn't only get tested against our own encoder.
+
+import os
+import random
+import struct
+import zlib
+
+WIDTH, HEIGHT = 13, 11
+ADAM7 = [(0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2)]
+CHANNELS = {0: 1, 2: 3, 3: 1, 4: 2, 6: 4}
+
+
+def chunk(kind, data):
+    body = kind + data
+    return struct.pack(">I", len(data)) + body + struct.pack(">I", zlib.crc32(body))
+
+
+def pack_row(samples, depth):
+    if depth == 16:
+        return b"".join(struct.pack(">H", s) for s in samples)
+    if depth == 8:
+        return bytes(samples)
+    out, bits, count = bytearray(), 0, 0
+    for s in samples:
+        bits = (bits << depth) | s
+        count += depth
+        if count == 8:
+            out.append(bits)
+            bits, count = 0, 0
+    if count:
+        out.append(bits << (8 - count))
+    return bytes(out)
+
+
+def paeth(a, b, c):
+    p = a + b - c
+    pa, pb, pc = abs(p - a), abs(p - b), abs(p - c)
+    if pa <= pb and pa <= pc:
+        return a
+    return b if pb <= pc else c
+
+
+def filter_row(kind, row, prior, bpp):
+    out = bytearray()
+    for i, x in enumerate(row):
+        a = row[i - bpp] if i >= bpp else 0
+        b = prior[i] if prior else 0
+        c = prior[i - bpp] if prior and i >= bpp else 0
+        predictor = [0, a, b, (a + b) // 2, paeth(a, b, c)][kind]
+        out.append((x - predictor) & 0xFF)
+    return bytes(out)
+
+
+def scale(value, depth):
+    if depth == 16:
+        return (value * 255 + 32767) // 65535
+    return value * 255 // ((1 << depth) - 1)
+
+
+def write(name, color_type, depth, pixels, interlace=False, palette=None, trns=None, rng=None):
+    """`pixels[y][x]` is a tuple of raw samples, a palette index for color type 3."""
+    channels = CHANNELS[color_type]
+    bpp = max(1, channels * depth // 8)
+    passes = ADAM7 if interlace else [(0, 0, 1, 1)]
+    raw = bytearray()
+    for x0, y0, dx, dy in passes:
+        prior = None
+        for y in range(y0, HEIGHT, dy):
+            samples = [s for x in range(x0, WIDTH, dx) for s in pixels[y][x]]
+            if not samples:
+                continue
+            row = pack_row(samples, depth)
+            kind = rng.randrange(5)
+            raw.append(kind)
+            raw += filter_row(kind, row, prior, bpp)
+            prior = row
+
+    ihdr = struct.pack(">IIBBBBB", WIDTH, HEIGHT, depth, color_type, 0, 0, int(interlace))
+    png = b"\x89PNG\r\n\x1a\n" + chunk(b"IHDR", ihdr)
+    if palette:
+        png += chunk(b"PLTE", b"".join(bytes(entry) for entry in palette))
+    if trns is not None:
+        png += chunk(b"tRNS", trns)
+    png += chunk(b"tEXt", b"Comment\x00ignored")
+    data = zlib.compress(bytes(raw), 9)
+    # split the data up, the decoder has to glue the chunks together
+    for start in range(0, len(data), 40):
+        png += chunk(b"IDAT", data[start : start + 40])
+    png += chunk(b"IEND", b"")
+
+    expected = bytearray()
+    for y in range(HEIGHT):
+        for x in range(WIDTH):
+            p = pixels[y][x]
+            if color_type == 3:
+                r, g, b = palette[p[0]]
+                a = trns[p[0]] if trns and p[0] < len(trns) else 255
+            elif color_type in (0, 4):
+                r = g = b = scale(p[0], depth)
+                a = scale(p[1], depth) if color_type == 4 else 255
+                if trns is not None and color_type == 0 and p[0] == struct.unpack(">H", trns)[0]:
+                    a = 0
+            else:
+                r, g, b = (scale(s, depth) for s in p[:3])
+                a = scale(p[3], depth) if color_type == 6 else 255
+                if trns is not None and color_type == 2 and tuple(p) == struct.unpack(">HHH", trns):
+                    a = 0
+            expected += bytes([r, g, b, a])
+
+    here = os.path.dirname(os.path.abspath(__file__))
+    with open(os.path.join(here, name + ".png"), "wb") as f:
+        f.write(png)
+    with open(os.path.join(here, name + ".rgba"), "wb") as f:
+        f.write(expected)
+
+
+def image(channels, maximum, rng, levels=5):
+    """Random pixels picked from a few levels, so zlib uses dynamic huffman codes for them."""
+    values = [rng.randrange(maximum + 1) for _ in range(levels)] + [0, maximum]
+    return [[tuple(rng.choice(values) for _ in range(channels)) for _ in range(WIDTH)] for _ in range(HEIGHT)]
+
+
+def main():
+    rng = random.Random(1234)
+    write("gray1", 0, 1, image(1, 1, rng), rng=rng)
+    write("gray4_interlaced", 0, 4, image(1, 15, rng), interlace=True, rng=rng)
+    gray8 = image(1, 255, rng)
+    write("gray8_trns", 0, 8, gray8, trns=struct.pack(">H", gray8[0][0][0]), rng=rng)
+    write("gray16", 0, 16, image(1, 65535, rng), rng=rng)
+    write("gray_alpha8", 4, 8, image(2, 255, rng), rng=rng)
+    write("gray_alpha16_interlaced", 4, 16, image(2, 65535, rng), interlace=True, rng=rng)
+    rgb8 = image(3, 255, rng)
+    write("rgb8_trns", 2, 8, rgb8, trns=struct.pack(">HHH", *rgb8[3][4]), rng=rng)
+    write("rgb16", 2, 16, image(3, 65535, rng), rng=rng)
+    # noise doesn't compress, which gives stored blocks
+    write("rgba8", 6, 8, image(4, 255, rng, levels=256), rng=rng)
+    write("rgba8_interlaced", 6, 8, image(4, 255, rng), interlace=True, rng=rng)
+    write("rgba16", 6, 16, image(4, 65535, rng), rng=rng)
+    palette = [tuple(rng.randrange(256) for _ in range(3)) for _ in range(16)]
+    write("palette4_trns", 3, 4, image(1, 15, rng), palette=palette, trns=bytes([0, 128, 255, 7]), rng=rng)
+    palette = [tuple(rng.randrange(256) for _ in range(3)) for _ in range(200)]
+    write("palette8_interlaced", 3, 8, image(1, 199, rng), palette=palette, interlace=True, rng=rng)
+    # flat colors, so the compressor uses long matches
+    flat = [[(40, 80, 120, 255) if x < 7 else (200, 100, 0, 128) for x in range(WIDTH)] for _ in range(HEIGHT)]
+    write("rgba8_flat", 6, 8, flat, rng=rng)
+
+
+if __name__ == "__main__":
+    main()
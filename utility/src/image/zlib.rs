@@ -0,0 +1,513 @@
+// zlib streams (RFC 1950) around deflate (RFC 1951), as used by PNG.
+// Note(voided): the decoder reads one bit at a time when decoding huffman codes, like zlib's
+// puff.c. That's plenty for assets and screenshots, and keeps the bounds checks in one place.
+
+use std::io;
+
+const MAX_BITS: usize = 15;
+const END_OF_BLOCK: u16 = 256;
+const WINDOW_SIZE: usize = 32768;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order the code length code lengths are stored in, in a dynamic block.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn invalid_zlib(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid zlib stream: {reason}"),
+    )
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MODULO: u32 = 65521;
+    let mut a = 1u32;
+    let mut b = 0u32;
+    // Note(voided): 5552 bytes is the most that can be summed up before b could overflow
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MODULO;
+        b %= MODULO;
+    }
+    b << 16 | a
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bit: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            bit: 0,
+        }
+    }
+
+    fn bit(&mut self) -> io::Result<u32> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or_else(|| invalid_zlib("unexpected end of data"))?;
+        let value = (byte as u32 >> self.bit) & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    /// `count` bits, least significant first.
+    fn bits(&mut self, count: u32) -> io::Result<u32> {
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.bit()? << i;
+        }
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.position += 1;
+        }
+    }
+
+    fn bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        debug_assert_eq!(self.bit, 0);
+        let bytes = self
+            .position
+            .checked_add(count)
+            .and_then(|end| self.data.get(self.position..end))
+            .ok_or_else(|| invalid_zlib("unexpected end of data"))?;
+        self.position += count;
+        Ok(bytes)
+    }
+}
+
+/// Canonical huffman code, stored as the number of codes per length and the symbols ordered by
+/// their code.
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Self> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+
+        // incomplete codes are fine, the missing codes just fail to decode
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(invalid_zlib("over subscribed huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for length in 1..MAX_BITS {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+
+        Ok(Self { counts, symbols })
+    }
+
+    fn fixed() -> (Self, Self) {
+        let mut lengths = [0u8; 288];
+        lengths[0..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..288].fill(8);
+        let literals = Self::new(&lengths).expect("fixed code is valid");
+        let distances = Self::new(&[5; 30]).expect("fixed code is valid");
+        (literals, distances)
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> io::Result<u16> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for &count in &self.counts[1..] {
+            code |= reader.bit()? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid_zlib("bad huffman code"))
+    }
+}
+
+fn read_dynamic_codes(reader: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(invalid_zlib("too many codes"));
+    }
+
+    let mut code_length_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_length_lengths)?;
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = code_lengths.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *index
+                    .checked_sub(1)
+                    .and_then(|previous| lengths.get(previous))
+                    .ok_or_else(|| invalid_zlib("repeat without a length"))?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        let end = index + repeat;
+        if end > lengths.len() {
+            return Err(invalid_zlib("too many code lengths"));
+        }
+        lengths[index..end].fill(value);
+        index = end;
+    }
+
+    if lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(invalid_zlib("missing end of block code"));
+    }
+    let (literal_lengths, distance_lengths) = lengths.split_at(literal_count);
+    Ok((
+        Huffman::new(literal_lengths)?,
+        Huffman::new(distance_lengths)?,
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_size: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = literals.decode(reader)?;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            END_OF_BLOCK => return Ok(()),
+            _ => {
+                let index = (symbol - 257) as usize;
+                if index >= LENGTH_BASE.len() {
+                    return Err(invalid_zlib("bad length code"));
+                }
+                let length =
+                    LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+                let index = distances.decode(reader)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    return Err(invalid_zlib("bad distance code"));
+                }
+                let distance = DISTANCE_BASE[index] as usize
+                    + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+                if distance > output.len() {
+                    return Err(invalid_zlib("distance too far back"));
+                }
+
+                // the copy can overlap with what it writes, so it has to go byte by byte
+                let start = output.len() - distance;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            }
+        }
+        if output.len() > max_size {
+            return Err(invalid_zlib("more data than expected"));
+        }
+    }
+}
+
+/// Decompresses a zlib stream, failing instead of growing the output past `max_size`.
+pub fn inflate_zlib(data: &[u8], max_size: usize) -> io::Result<Vec<u8>> {
+    let (&cmf, &flg) = match data {
+        [cmf, flg, ..] => (cmf, flg),
+        _ => return Err(invalid_zlib("missing header")),
+    };
+    if cmf & 0x0F != 8 || cmf >> 4 > 7 {
+        return Err(invalid_zlib("not deflate"));
+    }
+    if !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+        return Err(invalid_zlib("bad header check"));
+    }
+    if flg & 0x20 != 0 {
+        return Err(invalid_zlib("preset dictionaries are not supported"));
+    }
+
+    let mut reader = BitReader::new(&data[2..]);
+    let mut output = vec![];
+    loop {
+        let is_final = reader.bit()? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let header = reader.bytes(4)?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                let length_complement = u16::from_le_bytes([header[2], header[3]]);
+                if length != !length_complement {
+                    return Err(invalid_zlib("stored block length mismatch"));
+                }
+                output.extend_from_slice(reader.bytes(length as usize)?);
+                if output.len() > max_size {
+                    return Err(invalid_zlib("more data than expected"));
+                }
+            }
+            1 => {
+                let (literals, distances) = Huffman::fixed();
+                inflate_block(&mut reader, &mut output, max_size, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut output, max_size, &literals, &distances)?;
+            }
+            _ => return Err(invalid_zlib("bad block type")),
+        }
+        if is_final {
+            break;
+        }
+    }
+
+    reader.align_to_byte();
+    let checksum = reader.bytes(4)?;
+    let checksum = u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+    if checksum != adler32(&output) {
+        return Err(invalid_zlib("checksum mismatch"));
+    }
+    Ok(output)
+}
+
+#[derive(Default)]
+struct BitWriter {
+    output: Vec<u8>,
+    buffer: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    /// `count` bits of `value`, least significant first.
+    fn bits(&mut self, value: u32, count: u32) {
+        self.buffer |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes go out most significant bit first.
+    fn code(&mut self, code: u32, length: u32) {
+        self.bits(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.output.push(self.buffer as u8);
+        }
+        self.output
+    }
+}
+
+fn write_fixed_literal(writer: &mut BitWriter, symbol: u16) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => writer.code(0x30 + symbol, 8),
+        144..=255 => writer.code(0x190 + symbol - 144, 9),
+        256..=279 => writer.code(symbol - 256, 7),
+        _ => writer.code(0xC0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let index = LENGTH_BASE
+        .iter()
+        .rposition(|&base| base as usize <= length)
+        .unwrap();
+    write_fixed_literal(writer, 257 + index as u16);
+    writer.bits(
+        (length - LENGTH_BASE[index] as usize) as u32,
+        LENGTH_EXTRA[index] as u32,
+    );
+
+    let index = DISTANCE_BASE
+        .iter()
+        .rposition(|&base| base as usize <= distance)
+        .unwrap();
+    writer.code(index as u32, 5);
+    writer.bits(
+        (distance - DISTANCE_BASE[index] as usize) as u32,
+        DISTANCE_EXTRA[index] as u32,
+    );
+}
+
+/// Compresses into a single block with the fixed huffman code, finding matches greedily.
+/// Nowhere near as small as zlib's output, but good enough for screenshots of mostly flat
+/// images.
+pub fn deflate_zlib(data: &[u8]) -> Vec<u8> {
+    const MIN_MATCH: usize = 3;
+    const MAX_MATCH: usize = 258;
+    const HASH_BITS: u32 = 15;
+
+    let mut writer = BitWriter::default();
+    // 32K window, default compression level
+    writer.bits(0x78, 8);
+    writer.bits(0x9C, 8);
+    writer.bits(1, 1);
+    writer.bits(1, 2);
+
+    let hash = |position: usize| {
+        let bytes = u32::from_le_bytes([data[position], data[position + 1], data[position + 2], 0]);
+        (bytes.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    };
+    // Note(voided): one candidate per hash, the most recent position
+    let mut last_positions = vec![usize::MAX; 1 << HASH_BITS];
+
+    let mut position = 0;
+    while position < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+        if position + MIN_MATCH <= data.len() {
+            let slot = hash(position);
+            let candidate = last_positions[slot];
+            last_positions[slot] = position;
+            if candidate != usize::MAX && position - candidate <= WINDOW_SIZE {
+                let max_length = MAX_MATCH.min(data.len() - position);
+                best_length = data[candidate..]
+                    .iter()
+                    .zip(&data[position..position + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                best_distance = position - candidate;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            write_match(&mut writer, best_length, best_distance);
+            for skipped in position + 1..(position + best_length).min(data.len() - 2) {
+                last_positions[hash(skipped)] = skipped;
+            }
+            position += best_length;
+        } else {
+            write_fixed_literal(&mut writer, data[position] as u16);
+            position += 1;
+        }
+    }
+    write_fixed_literal(&mut writer, END_OF_BLOCK);
+
+    let mut output = writer.finish();
+    output.extend_from_slice(&adler32(data).to_be_bytes());
+    output
+}
+
+#[cfg(test)]
+mod zlib_tests {
+    use crate::image::zlib::{adler32, deflate_zlib, inflate_zlib};
+
+    #[test]
+    fn adler32_known_value() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+        assert_eq!(adler32(&[]), 1);
+        // long enough to need the modulo in between
+        assert_eq!(adler32(&vec![0xFF; 100_000]), 0x149A_302C);
+    }
+
+    #[test]
+    fn stored_block() {
+        let data = [
+            0x78, 0x01, 0x01, 0x05, 0x00, 0xFA, 0xFF, b'h', b'e', b'l', b'l', b'o', 0x06, 0x2C,
+            0x02, 0x15,
+        ];
+        assert_eq!(inflate_zlib(&data, 100).unwrap(), b"hello");
+        assert!(inflate_zlib(&data, 4).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut noise = vec![];
+        let mut state = 0x1234_5678u32;
+        for _ in 0..70_000 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            noise.push((state >> 24) as u8);
+        }
+        let inputs: [&[u8]; 5] = [
+            b"",
+            b"a",
+            b"abcabcabcabcabcabcabcabcabc",
+            &[7; 100_000],
+            &noise,
+        ];
+        for input in inputs {
+            let compressed = deflate_zlib(input);
+            assert_eq!(inflate_zlib(&compressed, input.len()).unwrap(), input);
+        }
+        assert!(deflate_zlib(&[7; 100_000]).len() < 1000);
+    }
+
+    #[test]
+    fn corrupt_streams_are_errors() {
+        let compressed = deflate_zlib(b"hello hello hello");
+        assert!(inflate_zlib(&compressed[..compressed.len() - 1], 100).is_err());
+        assert!(inflate_zlib(&compressed[..1], 100).is_err());
+
+        let mut bad_checksum = compressed.clone();
+        *bad_checksum.last_mut().unwrap() ^= 1;
+        assert!(inflate_zlib(&bad_checksum, 100).is_err());
+
+        let mut bad_header = compressed.clone();
+        bad_header[1] ^= 1;
+        assert!(inflate_zlib(&bad_header, 100).is_err());
+
+        // reserved block type
+        assert!(inflate_zlib(&[0x78, 0x9C, 0x07, 0, 0, 0, 0], 100).is_err());
+    }
+}
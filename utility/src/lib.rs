@@ -1,5 +1,11 @@
 pub mod audio;
+pub mod color;
+pub mod debug_io;
+pub mod font;
+pub mod image;
+pub mod math;
 pub mod ring_buffer;
+pub mod work_queue;
 
 #[allow(unused)]
 pub const fn kilobytes(x: usize) -> usize {
@@ -0,0 +1,397 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct V2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+pub const fn v2(x: f32, y: f32) -> V2 {
+    V2 { x, y }
+}
+
+impl V2 {
+    pub fn dot(self, other: V2) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    /// Rotated by 90 degrees counter clockwise.
+    pub fn perp(self) -> V2 {
+        v2(-self.y, self.x)
+    }
+
+    pub fn length_squared(self) -> f32 {
+        self.dot(self)
+    }
+
+    pub fn length(self) -> f32 {
+        self.length_squared().sqrt()
+    }
+}
+
+impl Add for V2 {
+    type Output = V2;
+
+    fn add(self, rhs: V2) -> V2 {
+        v2(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl AddAssign for V2 {
+    fn add_assign(&mut self, rhs: V2) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for V2 {
+    type Output = V2;
+
+    fn sub(self, rhs: V2) -> V2 {
+        v2(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl SubAssign for V2 {
+    fn sub_assign(&mut self, rhs: V2) {
+        *self = *self - rhs;
+    }
+}
+
+impl Mul<f32> for V2 {
+    type Output = V2;
+
+    fn mul(self, rhs: f32) -> V2 {
+        v2(self.x * rhs, self.y * rhs)
+    }
+}
+
+impl Mul<V2> for f32 {
+    type Output = V2;
+
+    fn mul(self, rhs: V2) -> V2 {
+        rhs * self
+    }
+}
+
+impl Neg for V2 {
+    type Output = V2;
+
+    fn neg(self) -> V2 {
+        v2(-self.x, -self.y)
+    }
+}
+
+/// Note(voided): 3d only for lighting, z points out of the screen at the viewer.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct V3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+pub const fn v3(x: f32, y: f32, z: f32) -> V3 {
+    V3 { x, y, z }
+}
+
+impl V3 {
+    pub fn dot(self, other: V3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn length_squared(self) -> f32 {
+        self.dot(self)
+    }
+
+    pub fn length(self) -> f32 {
+        self.length_squared().sqrt()
+    }
+
+    /// Same direction with a length of 1, `fallback` for vectors too short to have a direction.
+    pub fn normalize_or(self, fallback: V3) -> V3 {
+        let length = self.length();
+        if length > 1e-6 {
+            self * (1.0 / length)
+        } else {
+            fallback
+        }
+    }
+
+    /// Component wise product.
+    pub fn hadamard(self, other: V3) -> V3 {
+        v3(self.x * other.x, self.y * other.y, self.z * other.z)
+    }
+}
+
+impl Add for V3 {
+    type Output = V3;
+
+    fn add(self, rhs: V3) -> V3 {
+        v3(self.x + rhs.x, self.y + rhs.y, self.z + rhs.z)
+    }
+}
+
+impl AddAssign for V3 {
+    fn add_assign(&mut self, rhs: V3) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for V3 {
+    type Output = V3;
+
+    fn sub(self, rhs: V3) -> V3 {
+        v3(self.x - rhs.x, self.y - rhs.y, self.z - rhs.z)
+    }
+}
+
+impl Mul<f32> for V3 {
+    type Output = V3;
+
+    fn mul(self, rhs: f32) -> V3 {
+        v3(self.x * rhs, self.y * rhs, self.z * rhs)
+    }
+}
+
+impl Mul<V3> for f32 {
+    type Output = V3;
+
+    fn mul(self, rhs: V3) -> V3 {
+        rhs * self
+    }
+}
+
+/// Note(voided): Doubles as color, x y z w being r g b a.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct V4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+pub const fn v4(x: f32, y: f32, z: f32, w: f32) -> V4 {
+    V4 { x, y, z, w }
+}
+
+impl V4 {
+    pub fn r(self) -> f32 {
+        self.x
+    }
+
+    pub fn g(self) -> f32 {
+        self.y
+    }
+
+    pub fn b(self) -> f32 {
+        self.z
+    }
+
+    pub fn a(self) -> f32 {
+        self.w
+    }
+
+    /// Component wise product.
+    pub fn hadamard(self, other: V4) -> V4 {
+        v4(
+            self.x * other.x,
+            self.y * other.y,
+            self.z * other.z,
+            self.w * other.w,
+        )
+    }
+}
+
+impl Add for V4 {
+    type Output = V4;
+
+    fn add(self, rhs: V4) -> V4 {
+        v4(
+            self.x + rhs.x,
+            self.y + rhs.y,
+            self.z + rhs.z,
+            self.w + rhs.w,
+        )
+    }
+}
+
+impl Sub for V4 {
+    type Output = V4;
+
+    fn sub(self, rhs: V4) -> V4 {
+        v4(
+            self.x - rhs.x,
+            self.y - rhs.y,
+            self.z - rhs.z,
+            self.w - rhs.w,
+        )
+    }
+}
+
+impl Mul<f32> for V4 {
+    type Output = V4;
+
+    fn mul(self, rhs: f32) -> V4 {
+        v4(self.x * rhs, self.y * rhs, self.z * rhs, self.w * rhs)
+    }
+}
+
+impl Mul<V4> for f32 {
+    type Output = V4;
+
+    fn mul(self, rhs: V4) -> V4 {
+        rhs * self
+    }
+}
+
+pub fn lerp<T>(a: T, t: f32, b: T) -> T
+where
+    T: Mul<f32, Output = T> + Add<Output = T>,
+{
+    a * (1.0 - t) + b * t
+}
+
+/// Integer rectangle, `max` is exclusive.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rect2i {
+    pub min_x: i32,
+    pub min_y: i32,
+    pub max_x: i32,
+    pub max_y: i32,
+}
+
+impl Rect2i {
+    pub const fn new(min_x: i32, min_y: i32, max_x: i32, max_y: i32) -> Self {
+        Self {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        }
+    }
+
+    /// Sizes that would reach past `i32::MAX` get cut short there.
+    pub const fn with_size(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Self::new(x, y, x.saturating_add(width), y.saturating_add(height))
+    }
+
+    pub fn width(&self) -> i32 {
+        self.max_x.saturating_sub(self.min_x).max(0)
+    }
+
+    pub fn height(&self) -> i32 {
+        self.max_y.saturating_sub(self.min_y).max(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width() == 0 || self.height() == 0
+    }
+
+    pub fn area(&self) -> i64 {
+        self.width() as i64 * self.height() as i64
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.min_x && x < self.max_x && y >= self.min_y && y < self.max_y
+    }
+
+    pub fn intersect(&self, other: &Rect2i) -> Rect2i {
+        Rect2i::new(
+            self.min_x.max(other.min_x),
+            self.min_y.max(other.min_y),
+            self.max_x.min(other.max_x),
+            self.max_y.min(other.max_y),
+        )
+    }
+
+    /// Smallest rectangle containing both. Empty rectangles don't contribute.
+    pub fn union(&self, other: &Rect2i) -> Rect2i {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        Rect2i::new(
+            self.min_x.min(other.min_x),
+            self.min_y.min(other.min_y),
+            self.max_x.max(other.max_x),
+            self.max_y.max(other.max_y),
+        )
+    }
+
+    pub fn offset(&self, x: i32, y: i32) -> Rect2i {
+        Rect2i::new(
+            self.min_x + x,
+            self.min_y + y,
+            self.max_x + x,
+            self.max_y + y,
+        )
+    }
+}
+
+#[cfg(test)]
+mod math_tests {
+    use crate::math::{lerp, v2, v3, v4, Rect2i, V3};
+
+    #[test]
+    fn v2_ops() {
+        let a = v2(1.0, 2.0);
+        let b = v2(3.0, -1.0);
+        assert_eq!(a + b, v2(4.0, 1.0));
+        assert_eq!(a - b, v2(-2.0, 3.0));
+        assert_eq!(2.0 * a, v2(2.0, 4.0));
+        assert_eq!(a.dot(b), 1.0);
+        assert_eq!(a.perp(), v2(-2.0, 1.0));
+        assert_eq!(v2(3.0, 4.0).length(), 5.0);
+    }
+
+    #[test]
+    fn v3_ops() {
+        let a = v3(1.0, 2.0, 2.0);
+        assert_eq!(a.length(), 3.0);
+        assert_eq!(a - v3(1.0, 0.0, 0.0), v3(0.0, 2.0, 2.0));
+        assert_eq!(a.dot(v3(2.0, 0.5, -1.0)), 1.0);
+        assert_eq!(
+            v3(0.0, 0.0, 4.0).normalize_or(V3::default()),
+            v3(0.0, 0.0, 1.0)
+        );
+        let up = v3(0.0, 0.0, 1.0);
+        assert_eq!(V3::default().normalize_or(up), up);
+        assert_eq!(lerp(a, 0.5, V3::default()), v3(0.5, 1.0, 1.0));
+    }
+
+    #[test]
+    fn v4_lerp() {
+        let a = v4(0.0, 1.0, 0.0, 1.0);
+        let b = v4(1.0, 0.0, 0.5, 1.0);
+        assert_eq!(lerp(a, 0.5, b), v4(0.5, 0.5, 0.25, 1.0));
+        assert_eq!(a.hadamard(b), v4(0.0, 0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn rect_intersect_and_union() {
+        let a = Rect2i::new(0, 0, 10, 10);
+        let b = Rect2i::new(5, -5, 15, 5);
+        assert_eq!(a.intersect(&b), Rect2i::new(5, 0, 10, 5));
+        assert_eq!(a.union(&b), Rect2i::new(0, -5, 15, 10));
+
+        let empty = Rect2i::new(20, 20, 20, 30);
+        assert!(empty.is_empty());
+        assert_eq!(a.union(&empty), a);
+        assert!(a.intersect(&Rect2i::new(10, 0, 20, 10)).is_empty());
+        assert_eq!(Rect2i::new(4, 4, 2, 2).area(), 0);
+    }
+
+    #[test]
+    fn rect_sizes_saturate() {
+        let far = Rect2i::with_size(i32::MAX - 1, i32::MAX, 10, 10);
+        assert_eq!(far, Rect2i::new(i32::MAX - 1, i32::MAX, i32::MAX, i32::MAX));
+        assert_eq!((far.width(), far.height()), (1, 0));
+
+        let everything = Rect2i::with_size(i32::MIN, i32::MIN, i32::MAX, i32::MAX);
+        assert_eq!(everything, Rect2i::new(i32::MIN, i32::MIN, -1, -1));
+        let wider = Rect2i::new(i32::MIN, 0, i32::MAX, 1);
+        assert_eq!(wider.width(), i32::MAX);
+        assert_eq!(wider.area(), i32::MAX as i64);
+    }
+}
@@ -0,0 +1,243 @@
+// Worker threads that live as long as the queue and help out with whatever gets split into jobs,
+// so splitting up a frame doesn't start and join threads every time.
+// Note(voided): the platform owns the queue and hands it to the game. Threads started by the game
+// would still be running its code when it gets reloaded. Jobs are only ever run while
+// `for_each` is waiting for them, so the game's code is never running when it gets unloaded.
+
+use std::any::Any;
+use std::mem;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+type Job = dyn Fn(usize) + Sync;
+
+/// A job that doesn't borrow anything as far as the compiler knows, see `WorkQueue::for_each`.
+#[derive(Clone, Copy)]
+struct JobRef(*const Job);
+
+// Safety: the job is Sync, and only gets called while the borrow it came from is alive
+unsafe impl Send for JobRef {}
+
+#[derive(Default)]
+struct State {
+    job: Option<JobRef>,
+    job_count: usize,
+    next_index: usize,
+    /// Threads in the middle of calling the job.
+    running: usize,
+    panic: Option<Box<dyn Any + Send>>,
+    shutdown: bool,
+}
+
+impl State {
+    fn take_index(&mut self) -> Option<(JobRef, usize)> {
+        let job = self.job.filter(|_| self.next_index < self.job_count)?;
+        let index = self.next_index;
+        self.next_index += 1;
+        self.running += 1;
+        Some((job, index))
+    }
+
+    fn is_done(&self) -> bool {
+        self.next_index == self.job_count && self.running == 0
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    work_available: Condvar,
+    work_done: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // Note(voided): jobs run without the lock and their panics get caught, so it can't be
+        // poisoned
+        self.state.lock().expect("no panics while locked")
+    }
+
+    /// Calls the job without the lock, and hands it back afterwards.
+    fn run<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, State>,
+        job: JobRef,
+        index: usize,
+    ) -> MutexGuard<'a, State> {
+        drop(state);
+        // Safety: for_each keeps the job alive until `running` is back to 0
+        let result = catch_unwind(AssertUnwindSafe(|| unsafe { (*job.0)(index) }));
+        state = self.lock();
+        state.running -= 1;
+        if let Err(panic) = result {
+            state.panic.get_or_insert(panic);
+        }
+        if state.is_done() {
+            self.work_done.notify_all();
+        }
+        state
+    }
+}
+
+pub struct WorkQueue {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkQueue {
+    /// `worker_count` threads besides the one calling [`Self::for_each`], which helps out.
+    pub fn new(worker_count: usize) -> Self {
+        let shared = Arc::new(Shared::default());
+        let workers = (0..worker_count)
+            .map(|index| {
+                let shared = Arc::clone(&shared);
+                thread::Builder::new()
+                    .name(format!("worker {index}"))
+                    .spawn(move || work(&shared))
+                    .expect("failed to start a worker thread")
+            })
+            .collect();
+        Self { shared, workers }
+    }
+
+    /// A thread for every core, counting the one calling [`Self::for_each`].
+    pub fn for_all_cores() -> Self {
+        Self::new(thread::available_parallelism().map_or(1, |n| n.get()) - 1)
+    }
+
+    /// Threads that work on jobs, the caller of [`Self::for_each`] included.
+    pub fn thread_count(&self) -> usize {
+        self.workers.len() + 1
+    }
+
+    /// Calls `job` once for every index in `0..count`, spread over the workers and this thread,
+    /// and returns once all of them are done. A panicking job panics here after the others are
+    /// done.
+    /// Note(voided): jobs must not call this on the same queue, that waits forever.
+    pub fn for_each(&self, count: usize, job: &(dyn Fn(usize) + Sync)) {
+        if self.workers.is_empty() {
+            (0..count).for_each(job);
+            return;
+        }
+
+        // Safety: only changes the lifetime, and this doesn't return before every call of the
+        // job has returned, so it outlives them all
+        let job = JobRef(unsafe { mem::transmute::<&(dyn Fn(usize) + Sync), *const Job>(job) });
+        let shared = &*self.shared;
+        let mut state = shared.lock();
+        // someone else's jobs are still running
+        while state.job.is_some() {
+            state = shared
+                .work_done
+                .wait(state)
+                .expect("no panics while locked");
+        }
+        state.job = Some(job);
+        state.job_count = count;
+        state.next_index = 0;
+        shared.work_available.notify_all();
+
+        loop {
+            if let Some((job, index)) = state.take_index() {
+                state = shared.run(state, job, index);
+            } else if state.is_done() {
+                break;
+            } else {
+                state = shared
+                    .work_done
+                    .wait(state)
+                    .expect("no panics while locked");
+            }
+        }
+
+        state.job = None;
+        let panic = state.panic.take();
+        drop(state);
+        // for the others waiting to add their jobs
+        shared.work_done.notify_all();
+        if let Some(panic) = panic {
+            resume_unwind(panic);
+        }
+    }
+}
+
+fn work(shared: &Shared) {
+    let mut state = shared.lock();
+    while !state.shutdown {
+        if let Some((job, index)) = state.take_index() {
+            state = shared.run(state, job, index);
+        } else {
+            state = shared
+                .work_available
+                .wait(state)
+                .expect("no panics while locked");
+        }
+    }
+}
+
+impl Drop for WorkQueue {
+    fn drop(&mut self) {
+        self.shared.lock().shutdown = true;
+        self.shared.work_available.notify_all();
+        for worker in self.workers.drain(..) {
+            // panics inside jobs get caught, a worker can't fail on its own
+            worker.join().ok();
+        }
+    }
+}
+
+impl std::fmt::Debug for WorkQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorkQueue")
+            .field("thread_count", &self.thread_count())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod work_queue_tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::work_queue::WorkQueue;
+
+    #[test]
+    fn every_index_runs_once() {
+        for worker_count in [0, 1, 3] {
+            let queue = WorkQueue::new(worker_count);
+            assert_eq!(queue.thread_count(), worker_count + 1);
+            // the same threads get reused for every batch
+            for count in [0, 1, 7, 100] {
+                let calls: Vec<AtomicUsize> = (0..count).map(|_| AtomicUsize::new(0)).collect();
+                queue.for_each(count, &|index| {
+                    calls[index].fetch_add(1, Ordering::Relaxed);
+                });
+                assert!(calls.iter().all(|calls| calls.load(Ordering::Relaxed) == 1));
+            }
+        }
+    }
+
+    #[test]
+    fn panics_reach_the_caller() {
+        let queue = WorkQueue::new(2);
+        let finished = AtomicUsize::new(0);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            queue.for_each(10, &|index| {
+                if index == 3 {
+                    panic!("job 3");
+                }
+                finished.fetch_add(1, Ordering::Relaxed);
+            });
+        }));
+        assert!(result.is_err());
+        assert_eq!(finished.load(Ordering::Relaxed), 9);
+
+        // and the queue still works afterwards
+        let sum = AtomicUsize::new(0);
+        queue.for_each(4, &|index| {
+            sum.fetch_add(index, Ordering::Relaxed);
+        });
+        assert_eq!(sum.load(Ordering::Relaxed), 6);
+    }
+}